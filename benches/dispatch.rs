@@ -0,0 +1,54 @@
+//! Times the interpreter's instruction loop on tight counting loops, run
+//! with `cargo bench --bench dispatch`
+use std::time::{Duration, Instant};
+
+use lox::Interpreter;
+
+const ITERATIONS: usize = 1_000_000;
+const RUNS: usize = 5;
+
+/// Best time of `RUNS` runs of `src`, each against fresh globals
+fn time(name: &str, src: &str) {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let mut lox = Interpreter::new();
+        let start = Instant::now();
+        lox.eval(src).unwrap();
+        best = best.min(start.elapsed());
+    }
+    println!(
+        "{}: {} iterations in {:?} (best of {}), {:.1} ns per iteration",
+        name,
+        ITERATIONS,
+        best,
+        RUNS,
+        best.as_nanos() as f64 / ITERATIONS as f64
+    );
+}
+
+fn main() {
+    time(
+        "counting loop",
+        &format!(
+            "fun count() {{\n    var i = 0;\n    while (i < {}) {{ i = i + 1; }}\n    return i;\n}}\ncount();\n",
+            ITERATIONS
+        ),
+    );
+    // every access goes through the globals table
+    time(
+        "global counting loop",
+        &format!(
+            "var count = 0;\nvar step = 1;\nvar limit = {};\nwhile (count < limit) {{ count = count + step; }}\ncount;\n",
+            ITERATIONS
+        ),
+    );
+    // reads a global string each time around
+    time(
+        "global string reads",
+        &format!(
+            "var text = \"{}\";\nvar i = 0;\nvar seen;\nwhile (i < {}) {{ seen = text; i = i + 1; }}\nseen;\n",
+            "lox ".repeat(256),
+            ITERATIONS
+        ),
+    );
+}
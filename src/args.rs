@@ -1,26 +1,310 @@
-use std::path::PathBuf;
-use structopt::StructOpt;
+use std::{path::PathBuf, thread};
+use structopt::{
+    clap::{AppSettings, Error, ErrorKind},
+    StructOpt,
+};
 
-use crate::runners::{InteractiveRunner, SrcRunner};
+use crate::{
+    errors::err::ErrorFormat,
+    instructions::chunk::OptLevel,
+    runners::{InteractiveRunner, SrcRunner},
+    vm::config::{set_config, Config, STACK_PER_CALL},
+};
+
+const MIN_STACK_SIZE: usize = 8 * 1024 * 1024;
+// the most stack the interpreter thread asks for, deeper call limits are
+// rejected rather than left to fail when the thread is spawned
+const MAX_STACK_SIZE: usize = 1024 * 1024 * 1024;
 
 #[derive(StructOpt, Debug)]
-#[structopt(name = "Lox", about = "The lox interpreter")]
+#[structopt(
+    name = "Lox",
+    about = "The lox interpreter",
+    global_settings = &[AppSettings::DisableVersion]
+)]
 pub struct LoxArgs {
-    /// The .lox file that contains lox code
+    /// The .lox file that contains lox code, `-` reads it from stdin
     pub src: Option<PathBuf>,
+
+    /// Arguments for the script, read with `args()`
+    pub script_args: Vec<String>,
+
+    /// Raise a runtime error when string concatenation produces a string
+    /// longer than this many bytes
+    #[structopt(long)]
+    pub max_string_length: Option<usize>,
+
+    /// Fail compilation when a single function compiles to more than this
+    /// many instructions
+    #[structopt(long, default_value = "1000000")]
+    pub max_chunk_size: usize,
+
+    /// Lox code to run in place of a source file, e.g. `--eval "print 1 + 1;"`
+    #[structopt(long, conflicts_with = "src")]
+    pub eval: Option<String>,
+
+    /// How deeply calls can nest before the script errors
+    #[structopt(long, default_value = "255")]
+    pub max_call_depth: usize,
+
+    /// A global function to call once the file has loaded, e.g. `main`
+    #[structopt(long)]
+    pub entry: Option<String>,
+
+    /// Let scripts read environment variables with `env(name)`
+    #[structopt(long)]
+    pub allow_env: bool,
+
+    /// Warn when a local variable shadows a variable from an outer scope
+    #[structopt(long)]
+    pub warn_shadowing: bool,
+
+    /// Warn when a number literal or arithmetic result is past 2^53 and
+    /// can't be stored exactly
+    #[structopt(long)]
+    pub warn_unsafe_int: bool,
+
+    /// Warn when a local variable is declared but never read
+    #[structopt(long)]
+    pub warn_unused: bool,
+
+    /// The REPL's prompt
+    #[structopt(long, env = "LOX_PROMPT", default_value = ">>>  ")]
+    pub prompt: String,
+
+    /// The REPL's prompt while a submission spans several lines
+    #[structopt(long, env = "LOX_CONTINUATION_PROMPT", default_value = "...  ")]
+    pub continuation_prompt: String,
+
+    /// How errors are written out: plain (one line each), pretty or json
+    #[structopt(long, default_value = "pretty", possible_values = &["plain", "pretty", "json"])]
+    pub error_format: ErrorFormat,
+
+    /// Which optimizations run over the compiled bytecode: off, basic
+    /// (cleanup after codegen) or full (also folds constant arithmetic)
+    #[structopt(long, default_value = "basic", possible_values = &["off", "basic", "full"])]
+    pub ir_optimize: OptLevel,
+
+    /// Share one allocation between equal strings, which saves memory in
+    /// string heavy scripts and makes comparing them a pointer check
+    #[structopt(long)]
+    pub intern_strings: bool,
+
+    /// Count how many times each kind of instruction runs and print the
+    /// counts to stderr once the script finishes
+    #[structopt(long)]
+    pub profile: bool,
+
+    /// Print the compiled bytecode of the script and every function in it
+    /// instead of running it
+    #[structopt(long)]
+    pub dump_bytecode: bool,
+
+    /// Print the version and the features this build supports
+    #[structopt(short = "V", long)]
+    pub version: bool,
+}
+
+/// The crate version followed by what the language supports and the
+/// features that can be switched on when running a script
+pub fn version() -> String {
+    format!(
+        "lox {}
+
+language:
+    classes, inheritance, closures, lazy globals, constants,
+    break, pass, ternary `?:`, power `**`, lists `[a, b]`,
+    maps `{{\"k\": v}}` and indexing `xs[i]`
+
+runtime flags:
+    --allow-env             environment variables through `env(name)`
+    --warn-shadowing        warnings for shadowed locals
+    --warn-unsafe-int       warnings for numbers past 2^53
+    --warn-unused           warnings for locals that are never read
+    --max-string-length     a limit on concatenated strings
+    --max-chunk-size        a limit on instructions per function
+    --max-call-depth        a limit on nested calls
+    --error-format          plain, pretty or json errors
+    --ir-optimize           off, basic or full bytecode optimization
+    --intern-strings        one shared allocation for equal strings
+    --profile               counts of the instructions a run executed
+    --dump-bytecode         the compiled bytecode instead of a run
+    --eval                  run a string of lox instead of a file",
+        env!("CARGO_PKG_VERSION")
+    )
 }
 
 impl LoxArgs {
+    fn config(&self) -> Config {
+        Config {
+            max_string_length: self.max_string_length,
+            allow_env: self.allow_env,
+            script_args: self.script_args.clone(),
+            warn_shadowing: self.warn_shadowing,
+            error_format: self.error_format,
+            source: match (&self.src, &self.eval) {
+                (Some(path), _) if path.as_os_str() != "-" => {
+                    Some(path.to_string_lossy().to_string())
+                }
+                (None, Some(_)) => Some("<eval>".to_string()),
+                _ => None,
+            },
+            max_chunk_size: Some(self.max_chunk_size),
+            max_call_depth: Some(self.max_call_depth),
+            warn_unsafe_int: self.warn_unsafe_int,
+            warn_unused: self.warn_unused,
+            ir_optimize: self.ir_optimize,
+            intern_strings: self.intern_strings,
+            profile: self.profile,
+        }
+    }
+
     pub fn process_req(&self) {
-        match self.src.clone() {
-            // execute from source
-            Some(path) => {
-                SrcRunner::new(path).execute();
+        if self.version {
+            println!("{}", version());
+            return;
+        }
+        let stack_size = self.stack_size().unwrap_or_else(|err| err.exit());
+        thread::scope(|scope| {
+            if let Err(err) = thread::Builder::new()
+                .stack_size(stack_size)
+                .spawn_scoped(scope, || self.run())
+            {
+                Error::with_description(
+                    &format!("failed to start the interpreter thread: {}", err),
+                    ErrorKind::Io,
+                )
+                .exit()
             }
+        });
+    }
+
+    /// Every nested lox call recurses through `Func::call` on the host
+    /// stack, so the interpreter gets a thread with room for the deepest
+    /// call it allows
+    fn stack_size(&self) -> Result<usize, Error> {
+        match self.max_call_depth.checked_mul(STACK_PER_CALL) {
+            Some(size) if size <= MAX_STACK_SIZE => Ok(MIN_STACK_SIZE.max(size)),
+            _ => Err(Error::with_description(
+                &format!(
+                    "--max-call-depth {} is too deep, calls can nest at most {} deep",
+                    self.max_call_depth,
+                    MAX_STACK_SIZE / STACK_PER_CALL
+                ),
+                ErrorKind::InvalidValue,
+            )),
+        }
+    }
+
+    fn run(&self) {
+        set_config(self.config());
+        let runner = match (self.src.clone(), self.eval.clone()) {
+            // execute from source
+            (Some(path), _) => SrcRunner::new(path, self.entry.clone()),
+            (None, Some(code)) => SrcRunner::from_code(code, self.entry.clone()),
+            (None, None) if self.dump_bytecode => Error::with_description(
+                "--dump-bytecode needs a source file or --eval",
+                ErrorKind::MissingRequiredArgument,
+            )
+            .exit(),
             // enter interactive mode
-            None => {
-                InteractiveRunner::new().execute();
+            (None, None) => {
+                InteractiveRunner::new(self.prompt.clone(), self.continuation_prompt.clone())
+                    .execute();
+                return;
             }
+        };
+        match self.dump_bytecode {
+            true => runner.dump(),
+            false => runner.execute(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{values::values::Value, vm::vm::VM};
+
+    use super::*;
+
+    #[test]
+    fn test_script_args() {
+        let args = LoxArgs::from_iter(vec!["lox", "prog.lox", "first", "--", "--second"]);
+        assert_eq!(args.script_args, vec!["first", "--second"]);
+        set_config(args.config());
+
+        let globals = VM::globals();
+        VM::interprate_with(b"var argv = args();\n".to_vec(), globals.clone()).unwrap();
+        assert_eq!(
+            (*globals).borrow().resolve(&"argv".into()),
+            Some(Value::Array(Rc::new(RefCell::new(vec![
+                Value::String("first".into()),
+                Value::String("--second".into())
+            ]))))
+        );
+        set_config(Config::default());
+    }
+
+    #[test]
+    fn test_prompt_flags() {
+        // the defaults give way to LOX_PROMPT and LOX_CONTINUATION_PROMPT,
+        // so only explicit flags are checked
+        let args = LoxArgs::from_iter(vec![
+            "lox",
+            "--prompt",
+            "lox> ",
+            "--continuation-prompt",
+            "| ",
+        ]);
+        assert_eq!(args.prompt, "lox> ");
+        assert_eq!(args.continuation_prompt, "| ");
+    }
+
+    #[test]
+    fn test_eval_conflicts_with_src() {
+        let args = LoxArgs::from_iter(vec!["lox", "--eval", "print 1;"]);
+        assert_eq!(args.eval, Some("print 1;".to_string()));
+        assert_eq!(args.config().source_name(), "<eval>");
+        assert!(LoxArgs::from_iter_safe(vec!["lox", "prog.lox", "--eval", "print 1;"]).is_err());
+    }
+
+    #[test]
+    fn test_profile_flag() {
+        assert!(!LoxArgs::from_iter(vec!["lox", "prog.lox"]).config().profile);
+        assert!(
+            LoxArgs::from_iter(vec!["lox", "prog.lox", "--profile"])
+                .config()
+                .profile
+        );
+    }
+
+    #[test]
+    fn test_stack_size() {
+        let args = LoxArgs::from_iter(vec!["lox", "prog.lox"]);
+        assert_eq!(args.stack_size().unwrap(), MIN_STACK_SIZE);
+
+        let args = LoxArgs::from_iter(vec!["lox", "prog.lox", "--max-call-depth", "10000"]);
+        assert_eq!(args.stack_size().unwrap(), 10000 * STACK_PER_CALL);
+
+        let too_deep = usize::MAX.to_string();
+        let args = LoxArgs::from_iter(vec!["lox", "prog.lox", "--max-call-depth", &too_deep]);
+        let err = args.stack_size().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidValue);
+        assert!(err
+            .message
+            .contains("is too deep, calls can nest at most 32768 deep"));
+    }
+
+    #[test]
+    fn test_version() {
+        assert!(LoxArgs::from_iter(vec!["lox", "--version"]).version);
+        assert!(LoxArgs::from_iter(vec!["lox", "-V"]).version);
+        assert!(!LoxArgs::from_iter(vec!["lox"]).version);
+
+        let text = version();
+        assert!(text.starts_with(&format!("lox {}", env!("CARGO_PKG_VERSION"))));
+        assert!(text.contains("--allow-env"));
+    }
+}
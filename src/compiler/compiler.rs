@@ -4,17 +4,24 @@ use crate::{
     compiler::{parser::Parser, scanner::Scanner},
     errors::err::ErrTrait,
     instructions::{chunk::Chunk, define::DefinitionScope, instructions::PopN},
-    values::{func::Func, values::Value},
-    vm::table::Table,
+    values::{func::Func, intern::Name, values::Value},
+    vm::{config::with_config, stack::Stack, table::Table},
 };
 
-use super::token::{Token, TokenType};
+use super::{
+    token::{Token, TokenType},
+    warnings::warn,
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum FunctionType {
     Script,
+    /// A script typed into the REPL, its last expression statement may
+    /// leave off the `;` to have its value printed
+    Repl,
     Function(String, u32),
     Method(String, u32),
+    Lazy(String, u32),
 }
 
 #[derive(Debug)]
@@ -23,25 +30,79 @@ pub struct Local {
     pub depth: usize,
     uninit: bool,
     const_: bool,
+    // whether it's read or captured anywhere, its scope warns when it isn't
+    used: bool,
+    line: usize,
 }
 
-#[derive(Debug)]
+/// Where a captured variable currently lives. It stays on the stack (at an
+/// absolute index) while its scope is alive and is moved into the upvalue
+/// once the scope ends
+#[derive(Debug, Clone)]
+pub enum Capture {
+    Open(usize),
+    Closed(Value),
+}
+
+#[derive(Debug, Clone)]
 pub struct UpValue {
     // index of the local
     pub index: usize,
-    pub value: Value,
+    // nesting depth of the function that declared the local
+    pub depth: usize,
+    pub value: Rc<RefCell<Capture>>,
+}
+
+impl UpValue {
+    pub fn get(&self, stack: &Rc<RefCell<Stack>>) -> Value {
+        match &*self.value.borrow() {
+            Capture::Open(idx) => (*stack).borrow()[*idx].clone(),
+            Capture::Closed(val) => val.clone(),
+        }
+    }
+
+    pub fn set(&self, stack: &Rc<RefCell<Stack>>, val: Value) {
+        let open = match &*self.value.borrow() {
+            Capture::Open(idx) => Some(*idx),
+            Capture::Closed(_) => None,
+        };
+        match open {
+            Some(idx) => (*stack).borrow_mut()[idx] = val,
+            None => *self.value.borrow_mut() = Capture::Closed(val),
+        }
+    }
 }
 
 pub struct Compiler<'a> {
     locals: Rc<RefCell<Vec<Local>>>,
     locals_count: usize,
     scope_depth: usize,
+    // how many functions deep this compiler is, the script is at 0
+    depth: usize,
     pub type_: FunctionType,
     globals: Rc<RefCell<Table>>,
     enclosing_compiler: Option<&'a Compiler<'a>>,
     pub upvalues: Rc<RefCell<Vec<UpValue>>>,
     pub context: String,
     pub inheriting: Option<String>,
+    // the loops being compiled, innermost last
+    loops: Vec<LoopScope>,
+    // names a function body used before they were declared, shared by the
+    // whole script and checked once it's compiled, with the error to raise
+    // if they never are
+    forward_refs: Rc<RefCell<Vec<ForwardRef>>>,
+}
+
+/// A name used before its declaration and the error raised if it's never declared
+type ForwardRef = (String, Box<dyn ErrTrait>);
+
+/// A loop being compiled, `breaks` and `continues` are the jumps its
+/// `break`s and `continue`s wrote, patched once the loop's exit and the
+/// start of its next iteration are known
+struct LoopScope {
+    scope_depth: usize,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
 }
 
 impl<'a> Compiler<'a> {
@@ -54,23 +115,31 @@ impl<'a> Compiler<'a> {
         inheriting: Option<String>,
     ) -> Result<Func, Box<dyn ErrTrait>> {
         let pre_compile_upvalue_len = (*upvalues).borrow().len();
-        let context = match &type_ {
-            FunctionType::Script => String::from("__main__"),
-            FunctionType::Method(name, _) | FunctionType::Function(name, _) => name.clone(),
+        let (context, line) = match &type_ {
+            FunctionType::Script | FunctionType::Repl => (String::from("__main__"), 1),
+            FunctionType::Method(name, line)
+            | FunctionType::Function(name, line)
+            | FunctionType::Lazy(name, line) => (name.clone(), *line as usize),
         };
 
         let mut compiler = Compiler {
             locals: Rc::new(RefCell::new(Vec::new())),
             locals_count: 0,
             scope_depth: 0,
+            depth: enclosing_compiler.map_or(0, |compiler| compiler.depth + 1),
             type_: type_.clone(),
             globals,
             enclosing_compiler,
             upvalues,
             context: context.clone(),
             inheriting,
+            loops: Vec::new(),
+            forward_refs: enclosing_compiler.map_or_else(
+                || Rc::new(RefCell::new(Vec::new())),
+                |compiler| compiler.forward_refs.clone(),
+            ),
         };
-        let scanner = Scanner::new(src);
+        let scanner = Scanner::new(src).starting_at(line);
         let mut chunk = Chunk::new();
         let parser = Parser::new(&scanner, &mut chunk, &mut compiler)?;
         parser.parse()?;
@@ -79,6 +148,8 @@ impl<'a> Compiler<'a> {
             .len()
             .saturating_sub(pre_compile_upvalue_len);
         let upvalues = parser.compiler.borrow().upvalues.clone();
+        chunk.optimize(with_config(|config| config.ir_optimize));
+        chunk.validate_jumps()?;
         Ok(Func::new(
             context,
             chunk,
@@ -107,20 +178,87 @@ impl<'a> Compiler<'a> {
             if self.scope_depth + 1 != (*self.locals).borrow()[self.locals_count - 1].depth {
                 break;
             }
-            (*self.locals).borrow_mut().pop();
+            if let Some(local) = (*self.locals).borrow_mut().pop() {
+                if !local.used && with_config(|config| config.warn_unused) {
+                    warn(format!(
+                        "Line {}: local `{}` is never used",
+                        local.line, local.name
+                    ));
+                }
+            }
             self.locals_count -= 1;
             pop_count += 1;
         }
-        chunk.write_to_chunk(Box::new(PopN::new(pop_count)), line)?;
+        chunk.write_to_chunk(PopN::new(pop_count), line)?;
         Ok(self.scope_depth)
     }
 
-    pub fn add_local<'b>(&mut self, local: String, const_: bool) -> DefinitionScope {
+    /// Drops back to the top level after a declaration failed part way
+    /// through, forgetting the scopes and loops it left open. A global whose
+    /// declaration failed counts as declared so later uses of it don't
+    /// report errors of their own
+    pub fn recover(&mut self) {
+        self.scope_depth = 0;
+        (*self.locals).borrow_mut().retain(|local| local.depth == 0);
+        self.locals_count = (*self.locals).borrow().len();
+        self.loops.clear();
+        for local in (*self.locals).borrow_mut().iter_mut() {
+            if local.uninit {
+                local.uninit = false;
+                (*self.globals).borrow_mut().declare(Name::new(&local.name));
+            }
+        }
+    }
+
+    pub fn start_loop(&mut self) {
+        self.loops.push(LoopScope {
+            scope_depth: self.scope_depth,
+            breaks: Vec::new(),
+            continues: Vec::new(),
+        });
+    }
+
+    /// The jumps of the innermost loop's `break`s and `continue`s
+    pub fn end_loop(&mut self) -> (Vec<usize>, Vec<usize>) {
+        self.loops.pop().map_or((Vec::new(), Vec::new()), |loop_| {
+            (loop_.breaks, loop_.continues)
+        })
+    }
+
+    pub fn add_break(&mut self, jump: usize) {
+        if let Some(loop_) = self.loops.last_mut() {
+            loop_.breaks.push(jump);
+        }
+    }
+
+    pub fn add_continue(&mut self, jump: usize) {
+        if let Some(loop_) = self.loops.last_mut() {
+            loop_.continues.push(jump);
+        }
+    }
+
+    /// How many locals were declared inside the innermost loop, a `break`
+    /// or `continue` skips the end of their scopes so it pops them itself. None outside
+    /// of a loop
+    pub fn loop_locals(&self) -> Option<usize> {
+        let scope_depth = self.loops.last()?.scope_depth;
+        Some(
+            (*self.locals)
+                .borrow()
+                .iter()
+                .filter(|local| local.depth > scope_depth)
+                .count(),
+        )
+    }
+
+    pub fn add_local<'b>(&mut self, local: String, const_: bool, line: usize) -> DefinitionScope {
         (*self.locals).borrow_mut().push(Local {
             name: local.clone(),
             depth: self.scope_depth,
             uninit: true,
             const_,
+            used: false,
+            line,
         });
         self.locals_count += 1;
         if self.scope_depth == 0 {
@@ -130,9 +268,11 @@ impl<'a> Compiler<'a> {
     }
 
     fn add_upvalue(&self, idx: usize) -> usize {
+        // the local belongs to the enclosing function
         (*self.upvalues).borrow_mut().push(UpValue {
             index: idx,
-            value: Value::Nil,
+            depth: self.depth.saturating_sub(1),
+            value: Rc::new(RefCell::new(Capture::Closed(Value::Nil))),
         });
         (*self.upvalues).borrow().len() - 1
     }
@@ -141,12 +281,21 @@ impl<'a> Compiler<'a> {
         self.scope_depth
     }
 
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn locals_count(&self) -> usize {
+        self.locals_count
+    }
+
     pub fn resolve(&self, ident: &Token) -> Option<DefinitionScope> {
         if self.locals_count == 0 && (*self.globals).borrow().keys() == 0 {
             return None;
         }
         let ident_str = format!("{}", ident);
-        for (idx, local) in (*self.locals).borrow().iter().enumerate() {
+        // innermost first, so a local shadowing an outer one wins
+        for (idx, local) in (*self.locals).borrow().iter().enumerate().rev() {
             if local.name == ident_str {
                 if local.uninit {
                     return None;
@@ -157,12 +306,14 @@ impl<'a> Compiler<'a> {
                 }
             }
         }
-        match (*self.globals).borrow().exists(&ident_str) {
+        match (*self.globals).borrow().exists(&Name::new(&ident_str)) {
             true => Some(DefinitionScope::Global),
             false => match self.enclosing_compiler {
                 Some(compiler) => match compiler.resolve(ident) {
                     Some(scope) => match scope {
                         DefinitionScope::Local(idx) => {
+                            // being captured counts as a use
+                            compiler.mark_used(idx);
                             let upvalue_idx = self.add_upvalue(idx);
                             Some(DefinitionScope::UpValue(upvalue_idx))
                         }
@@ -176,12 +327,52 @@ impl<'a> Compiler<'a> {
         }
     }
 
+    /// Resolves a name a function body uses before it's declared as a global,
+    /// `undefined` is raised by `undefined_forward_refs` if the script never
+    /// declares it
+    pub fn forward_ref(&self, ident: &Token, undefined: Box<dyn ErrTrait>) -> DefinitionScope {
+        (*self.forward_refs)
+            .borrow_mut()
+            .push((format!("{}", ident), undefined));
+        DefinitionScope::Global
+    }
+
+    /// The errors for forward references that still don't name a global
+    /// now that the script is compiled
+    pub fn undefined_forward_refs(&self) -> Vec<Box<dyn ErrTrait>> {
+        let locals = (*self.locals).borrow();
+        (*self.forward_refs)
+            .borrow_mut()
+            .drain(..)
+            .filter(|(name, _)| {
+                !locals
+                    .iter()
+                    .any(|local| local.depth == 0 && &local.name == name)
+                    && !(*self.globals).borrow().exists(&Name::new(name))
+            })
+            .map(|(_, undefined)| undefined)
+            .collect()
+    }
+
+    /// The depth `ident` was declared at when its initializer is still being
+    /// compiled, so `var a = a;` can be told apart from an undefined name
+    pub fn uninit_depth(&self, ident: &Token) -> Option<usize> {
+        let ident_str = format!("{}", ident);
+        (*self.locals)
+            .borrow()
+            .iter()
+            .rev()
+            .find(|local| local.name == ident_str)
+            .filter(|local| local.uninit)
+            .map(|local| local.depth)
+    }
+
     fn resolve_idx(&self, ident: &Token) -> Option<usize> {
         if self.locals_count == 0 {
             return None;
         }
         let ident_str = format!("{}", ident);
-        for (idx, local) in (*self.locals).borrow().iter().enumerate() {
+        for (idx, local) in (*self.locals).borrow().iter().enumerate().rev() {
             if local.name == ident_str {
                 if local.uninit {
                     return None;
@@ -192,6 +383,13 @@ impl<'a> Compiler<'a> {
         None
     }
 
+    /// Marks the local at `idx` as read so it isn't reported as unused
+    pub fn mark_used(&self, idx: usize) {
+        if let Some(local) = (*self.locals).borrow_mut().get_mut(idx) {
+            local.used = true;
+        }
+    }
+
     pub fn check_const(&self, idx: usize) -> bool {
         (*self.locals).borrow()[idx].const_
     }
@@ -203,6 +401,25 @@ impl<'a> Compiler<'a> {
         }
     }
 
+    /// Whether `ident` already names a local from an outer scope, a local of
+    /// an enclosing function or a global. Unlike `resolve` this doesn't
+    /// capture anything
+    pub fn shadows(&self, ident: &Token) -> bool {
+        let ident_str = format!("{}", ident);
+        if (*self.locals)
+            .borrow()
+            .iter()
+            .any(|local| local.name == ident_str)
+            || (*self.globals).borrow().exists(&Name::new(&ident_str))
+        {
+            return true;
+        }
+        match self.enclosing_compiler {
+            Some(compiler) => compiler.shadows(ident),
+            None => false,
+        }
+    }
+
     pub fn resolve_in_scope(&self, ident: &Token) -> Option<usize> {
         if self.locals_count == 0 {
             return None;
@@ -224,7 +441,7 @@ impl<'a> Compiler<'a> {
         if self.locals_count > 0 {
             if self.scope_depth == 0 {
                 let name = &(*self.locals).borrow_mut()[self.locals_count - 1].name;
-                (*self.globals).borrow_mut().add(name.clone(), Value::Nil);
+                (*self.globals).borrow_mut().declare(Name::new(name));
             }
             (*self.locals).borrow_mut()[self.locals_count - 1].uninit = false;
         }
@@ -236,7 +453,7 @@ impl<'a> Compiler<'a> {
 
     pub fn inheriting(&self) -> Option<Token> {
         match &self.inheriting {
-            Some(ident) => Some(Token::new(TokenType::IDENTIFIER, ident.as_bytes(), 0)),
+            Some(ident) => Some(Token::new(TokenType::IDENTIFIER, ident.as_bytes(), 0, 0)),
             None => None,
         }
     }
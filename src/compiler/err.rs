@@ -24,27 +24,24 @@ impl ErrTraitBase for ScannerErr {
     fn raise(&self) {
         println!("{}", self);
     }
+
+    fn position(&self) -> Option<(usize, Option<usize>, String)> {
+        Some((self.line, Some(self.offset.max(1)), self.message.clone()))
+    }
 }
 
 impl Display for ScannerErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let get_offset = || {
-            if self.offset < 2 {
-                return self.offset;
-            }
-            self.offset - 2
-        };
+        let prefix = format!("Line {}: ", self.line);
+        // the caret sits under column `offset` of the line
+        let margin = " ".repeat(prefix.len() + self.offset.max(1) - 1);
         write!(
             f,
-            "
-Line {}: {}
-       {}^
-       {}------- {}
-",
-            self.line,
-            self.line_contents,
-            " ".repeat(get_offset()),
-            " ".repeat(get_offset()),
+            "\n{}{}\n{}^\n{}------- {}\n",
+            prefix,
+            self.line_contents.trim_end(),
+            margin,
+            margin,
             self.message
         )
     }
@@ -52,19 +49,7 @@ Line {}: {}
 
 impl Debug for ScannerErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "
-Line {}: {}
-        {}^
-        {}------- {}
-",
-            self.line,
-            self.line_contents,
-            " ".repeat(self.offset),
-            " ".repeat(self.offset),
-            self.message
-        )
+        Display::fmt(self, f)
     }
 }
 
@@ -77,7 +62,7 @@ pub struct GroupErr {
 }
 
 impl GroupErr {
-    pub fn _new(label: &'static str, message: String, errs: Vec<Box<dyn ErrTrait>>) -> Self {
+    pub fn new(label: &'static str, message: String, errs: Vec<Box<dyn ErrTrait>>) -> Self {
         GroupErr {
             errs,
             message,
@@ -97,6 +82,26 @@ impl ErrTraitBase for GroupErr {
             err.raise()
         }
     }
+
+    fn grouped(&self) -> Option<&[Box<dyn ErrTrait>]> {
+        Some(&self.errs)
+    }
+}
+
+impl Display for GroupErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\n{}:::   {}\n", self.label, self.message)?;
+        for err in &self.errs {
+            write!(f, "{}", err)?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for GroupErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<{} {:?}>", self.label, self.errs)
+    }
 }
 
 #[derive(Debug)]
@@ -121,3 +126,36 @@ impl Display for InterpreterErr {
         write!(f, "{:?}", self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::err::ErrorFormat;
+
+    use super::*;
+
+    #[test]
+    fn test_error_formats() {
+        let err: Box<dyn ErrTrait> = Box::new(ScannerErr::new(
+            "Unterminated \"string\"".to_string(),
+            "var s = \"abc".to_string(),
+            3,
+            9,
+        ));
+        assert_eq!(
+            err.formatted(ErrorFormat::Plain, "prog.lox"),
+            "prog.lox:3:9: Unterminated \"string\""
+        );
+        assert_eq!(
+            err.formatted(ErrorFormat::Json, "prog.lox"),
+            r#"{"file": "prog.lox", "line": 3, "column": 9, "message": "Unterminated \"string\""}"#
+        );
+        assert_eq!(
+            err.formatted(ErrorFormat::Pretty, "prog.lox"),
+            format!("{}", err)
+        );
+
+        // errors without a position still fit on one line
+        let err: Box<dyn ErrTrait> = Box::new(InterpreterErr::new("out of\nmemory".to_string()));
+        assert!(!err.formatted(ErrorFormat::Plain, "prog.lox").contains('\n'));
+    }
+}
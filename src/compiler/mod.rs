@@ -4,3 +4,4 @@ mod parser;
 mod rules;
 mod scanner;
 pub mod token;
+pub mod warnings;
@@ -10,24 +10,34 @@ use crate::{
         binary::{Binary, BinaryOp},
         call::Call,
         chunk::Chunk,
+        closure::{CloseUpValue, Closure},
+        collections::{BuildArray, BuildMap, IndexGet, IndexSet},
         constant::Constant,
         define::{Define, DefinitionScope, Override, Resolve},
-        instructions::{Instruction, None, Pop},
+        instructions::{Pop, PopN},
         jump::{ForceJump, Jump},
+        op::Op,
         print::Print,
         properties::{Get, Inherit, Set},
         return_inst::Return,
         unary::{Unary, UnaryOp},
     },
-    values::{func::Func, obj::Class, values::Value},
+    values::{
+        func::Func,
+        intern::intern,
+        obj::Class,
+        values::{unsafe_int, Value},
+    },
+    vm::config::with_config,
 };
 
 use super::{
     compiler::{Compiler, FunctionType},
-    err::ParserErr,
+    err::{GroupErr, ParserErr},
     rules::{construct_rule, Precendence},
     scanner::Scanner,
     token::{Token, TokenType},
+    warnings::warn,
 };
 
 /// Scans and parses the lox language
@@ -35,43 +45,59 @@ use super::{
 ///
 /// Precendence:
 /// ---------------
+/// Ternary [? :]          -> Right
 /// Equality [== !=]       -> Left
 /// Comparison [< > <= >=] -> Left
 /// Term [+ -]             -> Left
-/// Factor [/ *]           -> Left
+/// Factor [/ * %]         -> Left
 /// Unary [! -]            -> Right
+/// Power [**]             -> Right
 ///
 /// Supported CFG:
 /// --------------
 /// program     -> declaration* EOF
-/// declaration -> varDecl | statement | funDecl | classDecl
-/// classDecl   -> class IDENTIFIER ( "<" IDENTIFIER )? "{" function* "}"
+/// declaration -> varDecl | statement | funDecl | classDecl | lazyDecl
+/// classDecl   -> class IDENTIFIER ( "<" IDENTIFIER )? "{" ( function | getter )* "}"
+/// getter      -> "get" IDENTIFIER block
 /// funDecl     -> "fun" function
 /// function    -> IDENTIFIER "(" parameters? ")" block
 /// paramters   -> IDENTIFIER ("," IDENTIFIER)*
 /// varDecl     -> "var" IDENTIFIER ( "=" expression )? ";"
-/// statement   -> exprStmt | printStmt | block | ifStmt | whileStmt | forStmt | returnStmt
+/// lazyDecl    -> "lazy" "var" IDENTIFIER "=" expression ";"
+/// statement   -> exprStmt | printStmt | block | ifStmt | switchStmt | whileStmt | doWhileStmt
+///                 | forStmt | returnStmt | breakStmt | continueStmt | passStmt
 /// returnStmt  -> "return" expression? ";"
+/// breakStmt   -> "break" ";"
+/// continueStmt -> "continue" ";"
+/// passStmt    -> "pass" ";"
 /// forStmt     -> "for" "(" (declaration | exprStmt)? ";" expression? ";" expression? ";"
 /// whileStmt   -> "while" expression statement
+/// doWhileStmt -> "do" statement "while" "(" expression ")" ";"
+/// switchStmt  -> "switch" "(" expression ")" "{" ("case" expression ":" declaration*)*
+///                 ("default" ":" declaration*)? "}"
 /// ifStmt      -> if "(" expression ")" statement ("else" statement)?
 /// block       -> "{" declaration* "}"
 /// exprStmt    -> expression ";"
 /// printStmt   -> "print" expression ";"
 /// expression  -> assignment
-/// assignment  -> (call ".") IDENTIFIER '=' assignment | logic_or
+/// assignment  -> (call ".") IDENTIFIER '=' assignment
+///                 | call "[" expression "]" '=' assignment
+///                 | IDENTIFIER ( "+=" | "-=" | "*=" | "/=" ) assignment | ternary
+/// ternary     -> logic_or ("?" ternary ":" ternary)?
 /// logic_or    -> logic_or ("or" logic_and)*
 /// logic_and   -> equality ("and" equality)*
 /// equality    -> comparison ( (!= | ==) comparison )*
-/// comparison  -> term ( (> | >= | < | <=) term)*
+/// comparison  -> term ( (> | >= | < | <= | is) term)*
 /// term        -> factor ( (-|+) factor)*
-/// factor      -> unary ( (/|*) unary )*
-/// unary       -> ( (!|-) )unary | call
-/// call        -> primary ( "(" arguments? ")" | "." IDENTIFIER )*
+/// factor      -> unary ( (/|~/|*|%) unary )*
+/// unary       -> ( (!|-) )unary | power
+/// power       -> call ( "**" unary )?
+/// call        -> primary ( "(" arguments? ")" | "." IDENTIFIER | "[" expression "]" )*
 /// arguments   -> expresion ("," expression)*
 /// primary     -> number | string | "true" | "false" | "nil" |
-///                 "(" expression ")" | IDENTIFIER |
+///                 "(" expression ")" | "[" arguments? "]" | map | IDENTIFIER |
 ///                 "super" "." IDENTIFIER
+/// map         -> "{" ( expression ":" expression ("," expression ":" expression)* )? "}"
 
 #[derive(Debug)]
 pub struct Parser<'a> {
@@ -80,6 +106,13 @@ pub struct Parser<'a> {
     previous: RefCell<Option<Token<'a>>>,
     chunk: RefCell<&'a mut Chunk>,
     pub compiler: RefCell<&'a mut Compiler<'a>>,
+    // where the latest expression statement popped its value
+    last_expr_pop: RefCell<Option<usize>>,
+    // every `{` still waiting for its `}`
+    open_braces: RefCell<Vec<Token<'a>>>,
+    // set when the scanner failed, `current` is then a token that's
+    // already been parsed
+    scan_failed: RefCell<bool>,
 }
 
 impl<'a> Parser<'a> {
@@ -95,6 +128,9 @@ impl<'a> Parser<'a> {
             previous: RefCell::new(None),
             chunk: RefCell::new(chunk),
             compiler: RefCell::new(compiler),
+            last_expr_pop: RefCell::new(None),
+            open_braces: RefCell::new(Vec::new()),
+            scan_failed: RefCell::new(false),
         })
     }
 
@@ -111,7 +147,14 @@ impl<'a> Parser<'a> {
     }
 
     fn advance(&'a self) -> Result<(), Box<dyn ErrTrait>> {
-        let next = self.scanner.next()?;
+        let next = match self.scanner.next() {
+            Ok(next) => next,
+            Err(err) => {
+                self.scan_failed.replace(true);
+                return Err(err);
+            }
+        };
+        self.scan_failed.replace(false);
         self.previous
             .replace_with(|_| Some(self.current.replace(next)));
 
@@ -124,13 +167,46 @@ impl<'a> Parser<'a> {
             self.advance()?;
             return Ok(());
         }
-        let scan_line = self.scanner.line();
-        return Err(Box::new(ParserErr::new(
-            format!("Expected {} but found {}", expected, token),
-            self.scanner.line_to_string(),
-            scan_line.number,
-            scan_line.offset,
-        )));
+        return Err(self.error(format!("Expected {} but found {}", expected, token)));
+    }
+
+    /// An error pointing at the token about to be consumed
+    fn error(&self, message: String) -> Box<dyn ErrTrait> {
+        self.error_at(&self.current.borrow(), message)
+    }
+
+    fn error_at(&self, token: &Token, message: String) -> Box<dyn ErrTrait> {
+        Box::new(ParserErr::new(
+            message,
+            self.scanner.line_at(token.line as usize),
+            token.line as usize,
+            token.column as usize,
+        ))
+    }
+
+    /// Consumes a `{`, remembering where it was so an unclosed block can
+    /// point back at it
+    fn open_brace(&'a self) -> Result<(), Box<dyn ErrTrait>> {
+        let brace = self.current.borrow().clone();
+        self.consume(TokenType::LEFT_BRACE)?;
+        self.open_braces.borrow_mut().push(brace);
+        Ok(())
+    }
+
+    /// Consumes the `}` closing the latest `{`, running out of tokens
+    /// first reports the unmatched `{`
+    fn close_brace(&'a self) -> Result<(), Box<dyn ErrTrait>> {
+        if self.check(TokenType::EOF) {
+            if let Some(brace) = self.open_braces.borrow_mut().pop() {
+                return Err(self.error_at(
+                    &brace,
+                    "Unmatched '{', expected a '}' before the end of the file".to_string(),
+                ));
+            }
+        }
+        self.consume(TokenType::RIGHT_BRACE)?;
+        self.open_braces.borrow_mut().pop();
+        Ok(())
     }
 
     fn get_previous(&self) -> Result<Token, Box<dyn ErrTrait>> {
@@ -138,26 +214,50 @@ impl<'a> Parser<'a> {
         match prev {
             Some(token) => Ok(token.clone()),
             None => {
-                let scan_line = self.scanner.line();
-                return Err(Box::new(ParserErr::new(
-                    format!(
-                        "Interpreter Error:: Could consume previous number, Parser dump: {}",
-                        self
-                    ),
-                    self.scanner.line_to_string(),
-                    scan_line.number,
-                    scan_line.offset,
+                return Err(self.error(format!(
+                    "Interpreter Error:: Could consume previous number, Parser dump: {}",
+                    self
                 )));
             }
         }
     }
 
-    fn push(&self, inst: impl Instruction + 'static) -> Result<(), Box<dyn ErrTrait>> {
+    fn push(&self, inst: impl Into<Op>) -> Result<(), Box<dyn ErrTrait>> {
         let mut chunk = self.chunk.borrow_mut();
-        chunk.write_to_chunk(Box::new(inst), self.scanner.line().number)?;
+        chunk.write_to_chunk(inst, self.scanner.line().number)?;
         Ok(())
     }
 
+    /// Writes a jump that `patch_jump` later points at its target, giving
+    /// back where it was written
+    fn emit_jump(&self, jump: impl Into<Op>) -> Result<usize, Box<dyn ErrTrait>> {
+        let at = self.chunk.borrow().code.len();
+        self.push(jump)?;
+        Ok(at)
+    }
+
+    /// A jump taken depending on the value on top of the stack, knowing
+    /// the line it's on to report a value that isn't a valid condition
+    fn condition_jump(&self, continue_condition: bool) -> Jump {
+        Jump::new(
+            0,
+            continue_condition,
+            self.scanner.line().number,
+            self.scanner.line_to_string(),
+        )
+    }
+
+    /// Points the jump written at `at` to the next instruction
+    fn patch_jump(&self, at: usize) -> Result<(), Box<dyn ErrTrait>> {
+        self.chunk.borrow_mut().patch_jump(at)
+    }
+
+    /// Writes a jump back to `start`, which has already been compiled
+    fn emit_loop(&self, start: usize) -> Result<(), Box<dyn ErrTrait>> {
+        let offset = start as isize - self.chunk.borrow().code.len() as isize;
+        self.push(ForceJump::new(offset))
+    }
+
     fn start_scope(&'a self) {
         self.compiler.borrow_mut().start_scope();
     }
@@ -186,13 +286,7 @@ impl<'a> Parser<'a> {
             self.advance()?;
             if self.match_(TokenType::EOF)? {
                 let token = self.get_previous()?;
-                let scan_line = self.scanner.line();
-                return Err(Box::new(ParserErr::new(
-                    "Unexpected EOF".to_string(),
-                    String::from_utf8_lossy(token.literal).to_string(),
-                    scan_line.number,
-                    scan_line.offset,
-                )));
+                return Err(self.error_at(&token, "Unexpected EOF".to_string()));
             }
         }
         Ok(())
@@ -203,19 +297,21 @@ impl<'a> Parser<'a> {
         let val = match String::from_utf8_lossy(token.literal).parse::<f64>() {
             Ok(float) => float,
             Err(err) => {
-                let scan_line = self.scanner.line();
-                return Err(Box::new(ParserErr::new(
-                    format!(
-                        "Expected Number: couldn't convert {} to a valid Number, {}",
-                        String::from_utf8_lossy(token.literal),
-                        err.to_string()
-                    ),
-                    self.scanner.line_to_string(),
-                    scan_line.number,
-                    scan_line.offset,
+                return Err(self.error(format!(
+                    "Expected Number: couldn't convert {} to a valid Number, {}",
+                    String::from_utf8_lossy(token.literal),
+                    err.to_string()
                 )));
             }
         };
+        if unsafe_int(val) && with_config(|config| config.warn_unsafe_int) {
+            warn(format!(
+                "Line {}: `{}` is past 2^53 and is stored as {}",
+                self.scanner.line().number,
+                String::from_utf8_lossy(token.literal),
+                val
+            ));
+        }
         self.push(Constant::new(Value::Number(val)))?;
         return Ok(());
     }
@@ -226,17 +322,11 @@ impl<'a> Parser<'a> {
             TokenType::TRUE => Value::Bool(true),
             TokenType::FALSE => Value::Bool(false),
             TokenType::NIL => Value::Nil,
-            TokenType::STRING => Value::String(String::from_utf8_lossy(token.literal).to_string()),
+            TokenType::STRING => Value::String(intern(&Scanner::unescape(token.literal))),
             _ => {
-                let scan_line = self.scanner.line();
-                return Err(Box::new(ParserErr::new(
-                    format!(
-                        "Expected literal [true | false| nil] found {}",
-                        String::from_utf8_lossy(token.literal)
-                    ),
-                    self.scanner.line_to_string(),
-                    scan_line.number,
-                    scan_line.offset,
+                return Err(self.error(format!(
+                    "Expected literal [true | false| nil] found {}",
+                    String::from_utf8_lossy(token.literal)
                 )));
             }
         };
@@ -256,13 +346,9 @@ impl<'a> Parser<'a> {
             match self.compiler.borrow().type_ {
                 FunctionType::Method(_, _) => {}
                 _ => {
-                    let scan_line = self.scanner.line();
-                    return Err(Box::new(ParserErr::new(
+                    return Err(self.error(
                         "`this` can only be used in the context of a class method".to_string(),
-                        self.scanner.line_to_string(),
-                        scan_line.number,
-                        scan_line.offset,
-                    )));
+                    ));
                 }
             }
         }
@@ -273,44 +359,67 @@ impl<'a> Parser<'a> {
         let scope = match self.compiler.borrow().resolve(&token) {
             Some(scope_val) => scope_val,
             None => {
-                let scan_line = self.scanner.line();
-                return Err(Box::new(ParserErr::new(
-                    format!(
+                let uninit_depth = self.compiler.borrow().uninit_depth(&token);
+                let undefined = self.error(match uninit_depth {
+                    Some(0) => format!("Can not read variable `{}` in its own initializer", token),
+                    Some(_) => format!(
+                        "Can not read local variable `{}` in its own initializer",
+                        token
+                    ),
+                    None => format!(
                         "Can not access or overwrite undefined variable: `{}`",
                         token
                     ),
-                    self.scanner.line_to_string(),
-                    scan_line.number,
-                    scan_line.offset,
-                )));
+                });
+                // a function body can name a global declared after it, like
+                // the other half of a pair of mutually recursive functions
+                if uninit_depth.is_some() || self.compiler.borrow().depth() == 0 {
+                    return Err(undefined);
+                }
+                self.compiler.borrow().forward_ref(&token, undefined)
             }
         };
 
-        let match_ = self.match_(TokenType::EQUAL)?;
+        // `x op= value` reads `x`, applies `op` and writes the result back
+        let mut compound = Option::None;
+        for (assign, op) in [
+            (TokenType::PLUS_EQUAL, BinaryOp::ADD),
+            (TokenType::MINUS_EQUAL, BinaryOp::SUBTRACT),
+            (TokenType::STAR_EQUAL, BinaryOp::MULTIPLY),
+            (TokenType::SLASH_EQUAL, BinaryOp::DIVIDE),
+        ] {
+            if self.match_(assign)? {
+                compound = Some(op);
+                break;
+            }
+        }
+        let match_ = compound.is_some() || self.match_(TokenType::EQUAL)?;
+        // a plain assignment writes the variable without reading it
+        if !match_ || compound.is_some() {
+            if let DefinitionScope::Local(idx) = scope {
+                self.compiler.borrow().mark_used(idx);
+            }
+        }
         if match_ && can_assign && !is_const {
+            if compound.is_some() {
+                self.push(Resolve::new(format!("{}", token), scope.clone()))?;
+            }
             self.expression()?;
+            if let Some(op) = compound {
+                self.push(Binary::new(op))?;
+            }
             return self.push(Override::new(format!("{}", token), scope));
         }
         if match_ && !can_assign {
-            let scan_line = self.scanner.line();
-            return Err(Box::new(ParserErr::new(
+            return Err(self.error(
                 "Invalid assignment target. Can only assign to previously defined variables."
                     .to_string(),
-                self.scanner.line_to_string(),
-                scan_line.number,
-                scan_line.offset,
-            )));
+            ));
         }
         if match_ && is_const {
-            let scan_line = self.scanner.line();
-            return Err(Box::new(ParserErr::new(
-                format!(
-                    "Invalid assignment target. Can not assign to `const` `{}`",
-                    token
-                ),
-                self.scanner.line_to_string(),
-                scan_line.number,
-                scan_line.offset,
+            return Err(self.error(format!(
+                "Invalid assignment target. Can not assign to `const` `{}`",
+                token
             )));
         }
         self.push(Resolve::new(format!("{}", token), scope))
@@ -318,15 +427,17 @@ impl<'a> Parser<'a> {
 
     pub fn super_(&'a self) -> Result<(), Box<dyn ErrTrait>> {
         match self.compiler.borrow().inheriting {
-            Some(_) => self.var(false, self.compiler.borrow().inheriting())?,
+            Some(_) => {
+                // the parent's method runs against the current instance, which
+                // sits below the callee the same way `Method::call` lays it out
+                self.var(false, Some(self.this_token()))?;
+                self.var(false, self.compiler.borrow().inheriting())?
+            }
             None => {
-                let scan_line = self.scanner.line();
-                return Err(Box::new(ParserErr::new(
-                    "Invalid use of of super: can only use super in a class method of a child class".to_string(),
-                    self.scanner.line_to_string(),
-                    scan_line.number,
-                    scan_line.offset,
-                )));
+                return Err(self.error(
+                    "Invalid use of super: can only use super in a class method of a child class"
+                        .to_string(),
+                ));
             }
         }
         self.consume(TokenType::DOT)?;
@@ -336,32 +447,40 @@ impl<'a> Parser<'a> {
     }
 
     pub fn or(&'a self) -> Result<(), Box<dyn ErrTrait>> {
-        let origin = self.chunk.borrow().code.len();
-        self.push(None::new())?;
+        let end_jump = self.emit_jump(self.condition_jump(false))?;
         self.push(Pop::new())?;
 
         self.parse_expr(Precendence::Or)?;
 
-        let dest = self.chunk.borrow().code.len();
-        self.push(Jump::new(dest, false))?;
-
-        self.chunk.borrow_mut().swap_instructions(origin, dest)?;
-        Ok(())
+        self.patch_jump(end_jump)
     }
 
     pub fn and(&'a self) -> Result<(), Box<dyn ErrTrait>> {
-        let origin = self.chunk.borrow().code.len();
-        self.push(None::new())?;
+        let end_jump = self.emit_jump(self.condition_jump(true))?;
         self.push(Pop::new())?;
 
         self.parse_expr(Precendence::And)?;
 
-        let dest = self.chunk.borrow().code.len();
-        self.push(Jump::new(dest, true))?;
+        self.patch_jump(end_jump)
+    }
 
-        self.chunk.borrow_mut().swap_instructions(origin, dest)?;
+    /// `cond ? a : b`, laid out like an if/else where each branch pops
+    /// the condition before pushing its value
+    pub fn ternary(&'a self) -> Result<(), Box<dyn ErrTrait>> {
+        let else_jump = self.emit_jump(self.condition_jump(true))?;
+        self.push(Pop::new())?;
 
-        Ok(())
+        self.parse_expr(Precendence::Ternary)?;
+
+        let end_jump = self.emit_jump(ForceJump::new(0))?;
+        self.patch_jump(else_jump)?;
+        self.push(Pop::new())?;
+
+        self.consume(TokenType::COLON)?;
+        // parsing at the same precedence nests `a ? b : c ? d : e` to the right
+        self.parse_expr(Precendence::Ternary)?;
+
+        self.patch_jump(end_jump)
     }
 
     pub fn unary(&'a self) -> Result<(), Box<dyn ErrTrait>> {
@@ -370,13 +489,7 @@ impl<'a> Parser<'a> {
             TokenType::MINUS => UnaryOp::Negate,
             TokenType::BANG => UnaryOp::Bang,
             _ => {
-                let scan_line = self.scanner.line();
-                return Err(Box::new(ParserErr::new(
-                    format!("Invalid unary operator: {}", token.token_type),
-                    self.scanner.line_to_string(),
-                    scan_line.number,
-                    scan_line.offset,
-                )));
+                return Err(self.error(format!("Invalid unary operator: {}", token.token_type)));
             }
         };
         self.parse_expr(Precendence::Unary)?;
@@ -387,16 +500,26 @@ impl<'a> Parser<'a> {
     pub fn binary(&'a self) -> Result<(), Box<dyn ErrTrait>> {
         let token = self.get_previous()?;
         let rule = construct_rule(token.token_type);
-        self.parse_expr(rule.precedence.next()?)?;
+        // right associative operators take the right operand at their own
+        // precedence so `2 ** 3 ** 2` nests to the right
+        if token.token_type == TokenType::STAR_STAR {
+            self.parse_expr(rule.precedence)?;
+        } else {
+            self.parse_expr(rule.precedence.next()?)?;
+        }
         let mut after_push_hook: fn(&Parser) -> Result<(), Box<dyn ErrTrait>> = |_| Ok(());
         let op = match token.token_type {
             TokenType::PLUS => BinaryOp::ADD,
             TokenType::MINUS => BinaryOp::SUBTRACT,
             TokenType::STAR => BinaryOp::MULTIPLY,
             TokenType::SLASH => BinaryOp::DIVIDE,
+            TokenType::TILDE_SLASH => BinaryOp::QUOTIENT,
+            TokenType::PERCENT => BinaryOp::MODULO,
+            TokenType::STAR_STAR => BinaryOp::POW,
             TokenType::EQUAL_EQUAL => BinaryOp::EQUAL,
             TokenType::GREATER => BinaryOp::GREATER,
             TokenType::LESS => BinaryOp::LESS,
+            TokenType::IS => BinaryOp::IS,
             TokenType::BANG_EQUAL => {
                 after_push_hook = |parser| parser.push(Unary::new(UnaryOp::Bang));
                 BinaryOp::EQUAL
@@ -410,13 +533,7 @@ impl<'a> Parser<'a> {
                 BinaryOp::GREATER
             }
             _ => {
-                let scan_line = self.scanner.line();
-                return Err(Box::new(ParserErr::new(
-                    format!("Invalid Binary operator: {}", token.token_type),
-                    self.scanner.line_to_string(),
-                    scan_line.number,
-                    scan_line.offset,
-                )));
+                return Err(self.error(format!("Invalid Binary operator: {}", token.token_type)));
             }
         };
         self.push(Binary::new(op))?;
@@ -432,31 +549,15 @@ impl<'a> Parser<'a> {
 
     fn parse_expr(&'a self, prec: Precendence) -> Result<(), Box<dyn ErrTrait>> {
         let prefix_not_found_err = || {
-            println!("Parser [Prefix not found]]: {}", self);
-            let scan_line = self.scanner.line();
-            Box::new(ParserErr::new(
-                format!(
-                    "Expected expression, found {}",
-                    self.previous.borrow().as_ref().unwrap()
-                ),
-                self.scanner.line_to_string(),
-                scan_line.number,
-                scan_line.offset,
-            ))
+            let previous = self.previous.borrow();
+            let token = previous.as_ref().unwrap();
+            self.error_at(token, format!("Expected expression, found {}", token))
         };
 
         let infix_not_found_err = || {
-            println!("Parser [Infix not found]]: {}", self);
-            let scan_line = self.scanner.line();
-            Box::new(ParserErr::new(
-                format!(
-                    "Expected expression, found {}",
-                    self.previous.borrow().as_ref().unwrap()
-                ),
-                self.scanner.line_to_string(),
-                scan_line.number,
-                scan_line.offset,
-            ))
+            let previous = self.previous.borrow();
+            let token = previous.as_ref().unwrap();
+            self.error_at(token, format!("Expected expression, found {}", token))
         };
 
         self.advance()?;
@@ -508,6 +609,59 @@ impl<'a> Parser<'a> {
         Ok(())
     }
 
+    /// A list literal, `[a, b, c]`
+    pub fn array(&'a self) -> Result<(), Box<dyn ErrTrait>> {
+        let mut len: usize = 0;
+        if !self.check(TokenType::RIGHT_BRACKET) {
+            loop {
+                self.expression()?;
+                len += 1;
+                if !self.match_(TokenType::COMMA)? {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RIGHT_BRACKET)?;
+        self.push(BuildArray::new(len))
+    }
+
+    /// A map literal, `{"key": value}`
+    pub fn map(&'a self) -> Result<(), Box<dyn ErrTrait>> {
+        let mut len: usize = 0;
+        if !self.check(TokenType::RIGHT_BRACE) {
+            loop {
+                self.expression()?;
+                self.consume(TokenType::COLON)?;
+                self.expression()?;
+                len += 1;
+                if !self.match_(TokenType::COMMA)? {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RIGHT_BRACE)?;
+        let line = self.scanner.line();
+        self.push(BuildMap::new(
+            len,
+            line.number,
+            self.scanner.line_to_string(),
+        ))
+    }
+
+    /// `list[index]` or `map[key]`, assigned to the same way as a property
+    pub fn index(&'a self, can_assign: bool) -> Result<(), Box<dyn ErrTrait>> {
+        self.expression()?;
+        self.consume(TokenType::RIGHT_BRACKET)?;
+        if can_assign && self.match_(TokenType::EQUAL)? {
+            self.expression()?;
+            let line = self.scanner.line();
+            self.push(IndexSet::new(line.number, self.scanner.line_to_string()))
+        } else {
+            let line = self.scanner.line();
+            self.push(IndexGet::new(line.number, self.scanner.line_to_string()))
+        }
+    }
+
     pub fn dot(&'a self, can_assign: bool) -> Result<(), Box<dyn ErrTrait>> {
         self.consume(TokenType::IDENTIFIER)?;
         let id = self.previous.borrow().as_ref().unwrap().clone();
@@ -533,13 +687,40 @@ impl<'a> Parser<'a> {
 
     fn function(&'a self) -> Result<(), Box<dyn ErrTrait>> {
         self.start_scope();
+        let method = matches!(self.compiler.borrow().type_, FunctionType::Method(_, _));
+        if method {
+            self.this_decl()?;
+        }
+        // a getter's body follows straight after its name
+        let getter = method && self.check(TokenType::LEFT_BRACE);
+        if !getter {
+            self.parameters()?;
+        }
+        self.open_brace()?;
+        self.block()?;
+        self.end_scope()?;
+
+        Ok(())
+    }
+
+    fn parameters(&'a self) -> Result<(), Box<dyn ErrTrait>> {
         self.consume(TokenType::LEFT_PAREN)?;
         if !self.check(TokenType::RIGHT_PAREN) {
             loop {
                 self.consume(TokenType::IDENTIFIER)?;
                 let id = self.previous.borrow().as_ref().unwrap().clone();
 
+                // parameters live in the function's top scope, so a hit here
+                // can only be an earlier parameter with the same name
+                if self.compiler.borrow().resolve_in_scope(&id).is_some() {
+                    return Err(self.error(format!("duplicate parameter name `{}`", id)));
+                }
+
                 let scope = self.var_decl_inner(false, id.clone())?;
+                // parameters are part of the signature, leaving one unread is fine
+                if let DefinitionScope::Local(idx) = scope {
+                    self.compiler.borrow().mark_used(idx);
+                }
 
                 self.push(Define::new(scope, format!("{}", id)))?;
 
@@ -552,10 +733,6 @@ impl<'a> Parser<'a> {
             }
         }
         self.consume(TokenType::RIGHT_PAREN)?;
-        self.consume(TokenType::LEFT_BRACE)?;
-        self.block()?;
-        self.end_scope()?;
-
         Ok(())
     }
 
@@ -565,6 +742,7 @@ impl<'a> Parser<'a> {
         inheriting: Option<String>,
     ) -> Result<Func, Box<dyn ErrTrait>> {
         let type_: FunctionType;
+        let mut getter = false;
 
         match id {
             Some(token) => {
@@ -573,7 +751,14 @@ impl<'a> Parser<'a> {
             }
             None => {
                 self.consume(TokenType::IDENTIFIER)?;
-                let id = self.previous.borrow().as_ref().unwrap().clone();
+                let mut id = self.previous.borrow().as_ref().unwrap().clone();
+                // `get name { ... }` declares a getter, a method can still be
+                // called `get` itself
+                if format!("{}", id) == "get" && self.check(TokenType::IDENTIFIER) {
+                    self.advance()?;
+                    id = self.previous.borrow().as_ref().unwrap().clone();
+                    getter = true;
+                }
                 type_ = FunctionType::Method(format!("{}", id), self.scanner.line().number as u32);
             }
         };
@@ -588,18 +773,22 @@ impl<'a> Parser<'a> {
 
         // skip over function
         let mut arity: usize = 0;
-        self.consume(TokenType::LEFT_PAREN)?;
-
-        if !self.check(TokenType::RIGHT_PAREN) {
-            loop {
-                self.advance()?;
-                arity += 1;
-                if !self.match_(TokenType::COMMA)? {
-                    break;
+        if getter {
+            func.set_getter();
+        } else {
+            self.consume(TokenType::LEFT_PAREN)?;
+
+            if !self.check(TokenType::RIGHT_PAREN) {
+                loop {
+                    self.advance()?;
+                    arity += 1;
+                    if !self.match_(TokenType::COMMA)? {
+                        break;
+                    }
                 }
             }
+            self.consume(TokenType::RIGHT_PAREN)?;
         }
-        self.consume(TokenType::RIGHT_PAREN)?;
         self.consume(TokenType::LEFT_BRACE)?;
         self.escape_scope()?;
 
@@ -616,16 +805,29 @@ impl<'a> Parser<'a> {
     }
 
     fn block(&'a self) -> Result<(), Box<dyn ErrTrait>> {
+        let mut returned = false;
         while !self.check(TokenType::RIGHT_BRACE) && !self.check(TokenType::EOF) {
+            if returned {
+                return Err(self.error(
+                    "Unreachable code, nothing after a `return` in the same block runs".to_string(),
+                ));
+            }
+            returned = self.check(TokenType::RETURN);
             self.declaration()?;
         }
-        self.consume(TokenType::RIGHT_BRACE)?;
+        self.close_brace()?;
         Ok(())
     }
 
     fn expr_stmt(&'a self) -> Result<(), Box<dyn ErrTrait>> {
         self.expression()?;
+        if self.compiler.borrow().type_ == FunctionType::Repl && self.check(TokenType::EOF) {
+            // a bare expression typed into the REPL, show its value
+            self.push(Print::new())?;
+            return Ok(());
+        }
         self.consume(TokenType::SEMICOLON)?;
+        *self.last_expr_pop.borrow_mut() = Some(self.chunk.borrow().code.len());
         self.push(Pop::new())?;
         Ok(())
     }
@@ -641,22 +843,24 @@ impl<'a> Parser<'a> {
         if scope_depth > 0 {
             match self.compiler.borrow().resolve_in_scope(&id) {
                 Some(_) => {
-                    let scan_line = self.scanner.line();
-                    return Err(Box::new(ParserErr::new(
-                        format!("Can not redefine `{}` in the same scope", id),
-                        self.scanner.line_to_string(),
-                        scan_line.number,
-                        scan_line.offset,
-                    )));
+                    return Err(self.error(format!("Can not redefine `{}` in the same scope", id)));
                 }
                 None => {}
             }
+            if with_config(|config| config.warn_shadowing) && self.compiler.borrow().shadows(&id) {
+                warn(format!(
+                    "Line {}: `{}` shadows a variable from an outer scope",
+                    self.scanner.line().number,
+                    id
+                ));
+            }
         }
 
-        let scope = self
-            .compiler
-            .borrow_mut()
-            .add_local(format!("{}", id), const_);
+        let scope = self.compiler.borrow_mut().add_local(
+            format!("{}", id),
+            const_,
+            self.scanner.line().number,
+        );
 
         Ok(scope)
     }
@@ -682,15 +886,73 @@ impl<'a> Parser<'a> {
         Ok(())
     }
 
-    fn this_decl(&'a self) -> Result<(), Box<dyn ErrTrait>> {
+    /// Global whose initializer is compiled into a thunk and only
+    /// evaluated the first time the global is resolved
+    fn lazy_decl(&'a self) -> Result<(), Box<dyn ErrTrait>> {
+        self.consume(TokenType::VAR)?;
+        self.consume(TokenType::IDENTIFIER)?;
+        let id = self.previous.borrow().as_ref().unwrap().clone();
+
+        if self.compiler.borrow().scope() > 0 {
+            return Err(self.error(format!(
+                "`lazy` can only be used on global variables, found local `{}`",
+                id
+            )));
+        }
+        let scope = self.var_decl_inner(false, id.clone())?;
+
+        if !self.check(TokenType::EQUAL) {
+            self.consume(TokenType::EQUAL)?;
+        }
+        let thunk = Compiler::compile(
+            self.scanner.src_vec_from_current(),
+            FunctionType::Lazy(format!("{}", id), self.scanner.line().number as u32),
+            self.compiler.borrow().globals(),
+            Some(*self.compiler.borrow()),
+            self.compiler.borrow().upvalues.clone(),
+            None,
+        )?;
+
+        // skip over the initializer, the thunk owns it now
+        self.consume(TokenType::EQUAL)?;
+        while !self.check(TokenType::SEMICOLON) && !self.check(TokenType::EOF) {
+            self.advance()?;
+        }
+        self.consume(TokenType::SEMICOLON)?;
+
+        self.push(Constant::new(Value::Lazy(Rc::new(thunk))))?;
+        self.push(Define::new(scope, format!("{}", id)))?;
+        self.compiler.borrow().mark_latest_init();
+
+        Ok(())
+    }
+
+    fn thunk(&'a self) -> Result<(), Box<dyn ErrTrait>> {
+        self.consume(TokenType::EQUAL)?;
+        self.expression()?;
+        self.consume(TokenType::SEMICOLON)?;
+        self.push(Return::new())
+    }
+
+    fn this_token(&self) -> Token<'a> {
         let line = self.scanner.line().number;
-        let id = Token::new(
+        Token::new(
             TokenType::THIS,
             &['t' as u8, 'h' as u8, 'i' as u8, 's' as u8],
             line as u32,
-        );
+            0,
+        )
+    }
 
+    fn this_decl(&'a self) -> Result<(), Box<dyn ErrTrait>> {
+        let id = self.this_token();
+
+        // `this` is the method's first local, `Method::call` puts the
+        // instance in that slot ahead of the arguments
         let scope = self.var_decl_inner(true, id.clone())?;
+        if let DefinitionScope::Local(idx) = scope {
+            self.compiler.borrow().mark_used(idx);
+        }
         self.push(Define::new(scope, format!("{}", id)))?;
         self.compiler.borrow().mark_latest_init();
 
@@ -702,41 +964,95 @@ impl<'a> Parser<'a> {
         self.expression()?;
         self.consume(TokenType::RIGHT_PAREN)?;
 
-        // current instruction index + 1, where I expect the
-        // call to jump to be
-        let dest = self.chunk.borrow().code.len();
-        // creating a none instruction so we can swap the jump
-        // with something other than a valid instruction
-        self.push(None::new())?;
+        // skips the if clause when the condition is false, patched
+        // once we know where the else clause starts
+        let else_jump = self.emit_jump(self.condition_jump(true))?;
+        // each branch pops the condition before it runs so locals
+        // declared in it line up with their stack slots
+        self.push(Pop::new())?;
 
         self.statement()?;
 
         // just incase we do execute the if clause
-        // we need to force jump any possible else clause
-        let force_jump_dest = self.chunk.borrow().code.len();
-        self.push(None::new())?;
-
-        // create the jump instruction pointing to where
-        // the None instruction will eventually end up
-        // on the call stack
-        let origin = self.chunk.borrow().code.len();
-        self.push(Jump::new(origin, true))?;
+        // we need to force jump the else clause's pop
+        let end_jump = self.emit_jump(ForceJump::new(0))?;
 
-        //swap the None instruction with the jump instruction
-        self.chunk.borrow_mut().swap_instructions(origin, dest)?;
+        self.patch_jump(else_jump)?;
+        self.push(Pop::new())?;
 
         if self.match_(TokenType::ELSE)? {
             self.statement()?;
-            // replicates the jump semantics for else
-            let origin = self.chunk.borrow().code.len();
-            self.push(ForceJump::new(origin))?;
-            self.chunk
+        }
+
+        self.patch_jump(end_jump)
+    }
+
+    /// Compares the value switched on against each `case` in turn and runs
+    /// the first one that's equal, or `default` if none are. Cases don't
+    /// fall through, the end of each jumps past the rest of the switch
+    fn switch_stmt(&'a self) -> Result<(), Box<dyn ErrTrait>> {
+        // the value is kept in a hidden local so it's only evaluated once,
+        // the space keeps it from clashing with any identifier
+        let name = " switch".to_string();
+        self.start_scope();
+        let scope =
+            self.compiler
                 .borrow_mut()
-                .swap_instructions(origin, force_jump_dest)?;
+                .add_local(name.clone(), true, self.scanner.line().number);
+        if let DefinitionScope::Local(idx) = scope {
+            self.compiler.borrow().mark_used(idx);
         }
+        self.consume(TokenType::LEFT_PAREN)?;
+        self.expression()?;
+        self.consume(TokenType::RIGHT_PAREN)?;
+        self.push(Define::new(scope.clone(), name.clone()))?;
+        self.compiler.borrow().mark_latest_init();
 
-        self.push(Pop::new())?;
-        Ok(())
+        self.open_brace()?;
+        let mut end_jumps = Vec::new();
+        while self.match_(TokenType::CASE)? {
+            self.push(Resolve::new(name.clone(), scope.clone()))?;
+            self.expression()?;
+            self.consume(TokenType::COLON)?;
+            self.push(Binary::new(BinaryOp::EQUAL))?;
+
+            let next_case = self.emit_jump(self.condition_jump(true))?;
+            self.push(Pop::new())?;
+            self.case_body()?;
+            end_jumps.push(self.emit_jump(ForceJump::new(0))?);
+
+            self.patch_jump(next_case)?;
+            self.push(Pop::new())?;
+        }
+        if self.match_(TokenType::DEFAULT)? {
+            self.consume(TokenType::COLON)?;
+            self.case_body()?;
+            if self.check(TokenType::CASE) {
+                return Err(
+                    self.error("`default` has to be the last case of a `switch`".to_string())
+                );
+            }
+        }
+        self.close_brace()?;
+
+        for jump in end_jumps {
+            self.patch_jump(jump)?;
+        }
+        self.end_scope()
+    }
+
+    /// The declarations up to the next `case`, `default` or the end of the
+    /// switch, in a scope of their own
+    fn case_body(&'a self) -> Result<(), Box<dyn ErrTrait>> {
+        self.start_scope();
+        while !self.check(TokenType::CASE)
+            && !self.check(TokenType::DEFAULT)
+            && !self.check(TokenType::RIGHT_BRACE)
+            && !self.check(TokenType::EOF)
+        {
+            self.declaration()?;
+        }
+        self.end_scope()
     }
 
     /// Syntactic sugar for while loops
@@ -744,9 +1060,14 @@ impl<'a> Parser<'a> {
     /// format, if for(;;) or any other variation is needed
     /// use while
     fn for_stmt(&'a self) -> Result<(), Box<dyn ErrTrait>> {
+        // the loop gets its own scope, even at the top level, so the loop
+        // variable is a local that closures can capture per iteration
+        self.start_scope();
         // the initial decl/assignment section
         self.consume(TokenType::LEFT_PAREN)?;
+        let mut loop_local = Option::None;
         if self.match_(TokenType::VAR)? {
+            loop_local = Some(self.compiler.borrow().locals_count());
             self.var_decl(false)?;
         } else {
             self.expr_stmt()?;
@@ -758,11 +1079,10 @@ impl<'a> Parser<'a> {
         self.expression()?;
         self.consume(TokenType::SEMICOLON)?;
 
-        let pre_expr_pos = self.chunk.borrow().code.len();
-        self.push(None::new())?;
+        let exit_jump = self.emit_jump(self.condition_jump(true))?;
         self.push(Pop::new())?;
-        let force_jump_pos = self.chunk.borrow().code.len();
-        self.push(None::new())?;
+        // co-ordinates skipping over the incr expr
+        let body_jump = self.emit_jump(ForceJump::new(0))?;
 
         // the loop incr
         let pre_incr_pos = self.chunk.borrow().code.len();
@@ -771,28 +1091,65 @@ impl<'a> Parser<'a> {
 
         self.push(Pop::new())?;
         // jumps back to check the condition
-        self.push(ForceJump::new(jump_position))?;
-
-        // co-ordinates skipping over the incr expr
-        let body_start_pos = self.chunk.borrow().code.len();
-        self.push(ForceJump::new(body_start_pos))?;
-        self.chunk
-            .borrow_mut()
-            .swap_instructions(force_jump_pos, body_start_pos)?;
+        self.emit_loop(jump_position)?;
 
+        self.patch_jump(body_jump)?;
+        self.compiler.borrow_mut().start_loop();
         self.statement()?;
 
+        // `continue` lands here so the loop variable is still closed over
+        let iteration_end = self.chunk.borrow().code.len();
+        // closures made in the body keep this iteration's loop variable,
+        // the incr then works on a fresh slot
+        if let Some(local) = loop_local {
+            self.push(CloseUpValue::new(local))?;
+        }
+
         // jumps back to the incr after the body
-        self.push(ForceJump::new(pre_incr_pos))?;
+        self.emit_loop(pre_incr_pos)?;
 
         // condition jump for the loop break
-        let post_for_clause = self.chunk.borrow().code.len();
-        self.push(Jump::new(post_for_clause, true))?;
-        self.chunk
-            .borrow_mut()
-            .swap_instructions(pre_expr_pos, post_for_clause)?;
-
+        self.patch_jump(exit_jump)?;
         self.push(Pop::new())?;
+        self.end_loop(iteration_end)?;
+        self.end_scope()
+    }
+
+    /// `break` or `continue`, both leave the loop body early through a
+    /// jump that `end_loop` patches
+    fn loop_control(&'a self, keyword: TokenType) -> Result<(), Box<dyn ErrTrait>> {
+        let locals = match self.compiler.borrow().loop_locals() {
+            Some(locals) => locals,
+            None => {
+                return Err(self.error(format!(
+                    "`{}` outside of a loop, it can only appear inside a `while`, `do` or `for` loop",
+                    keyword
+                )));
+            }
+        };
+        if locals > 0 {
+            self.push(PopN::new(locals))?;
+        }
+
+        let jump = self.emit_jump(ForceJump::new(0))?;
+        match keyword {
+            TokenType::CONTINUE => self.compiler.borrow_mut().add_continue(jump),
+            _ => self.compiler.borrow_mut().add_break(jump),
+        }
+        self.consume(TokenType::SEMICOLON)?;
+        Ok(())
+    }
+
+    /// Points the innermost loop's `break`s at whatever comes after it and
+    /// its `continue`s at `next`, where the following iteration starts
+    fn end_loop(&'a self, next: usize) -> Result<(), Box<dyn ErrTrait>> {
+        let (breaks, continues) = self.compiler.borrow_mut().end_loop();
+        for jump in continues {
+            self.chunk.borrow_mut().patch_jump_to(jump, next)?;
+        }
+        for jump in breaks {
+            self.patch_jump(jump)?;
+        }
         Ok(())
     }
 
@@ -803,22 +1160,44 @@ impl<'a> Parser<'a> {
         self.expression()?;
         self.consume(TokenType::RIGHT_PAREN)?;
 
-        let origin = self.chunk.borrow().code.len();
-        self.push(None::new())?;
+        let exit_jump = self.emit_jump(self.condition_jump(true))?;
+        self.push(Pop::new())?;
+
+        self.compiler.borrow_mut().start_loop();
+        self.statement()?;
+
+        // the condition has already been compiled so the jump back
+        // needs no patching
+        self.emit_loop(jump_position)?;
+
+        self.patch_jump(exit_jump)?;
         self.push(Pop::new())?;
+        self.end_loop(jump_position)
+    }
 
+    /// Like `while` but the body runs before the condition is first
+    /// checked, so it always runs at least once
+    fn do_while_stmt(&'a self) -> Result<(), Box<dyn ErrTrait>> {
+        let body_start = self.chunk.borrow().code.len();
+
+        self.compiler.borrow_mut().start_loop();
         self.statement()?;
 
-        // jump position can be pre-determined so we don't need to swap
-        // with a none
-        self.push(ForceJump::new(jump_position))?;
+        // `continue` skips to the condition
+        let condition_start = self.chunk.borrow().code.len();
+        self.consume(TokenType::WHILE)?;
+        self.consume(TokenType::LEFT_PAREN)?;
+        self.expression()?;
+        self.consume(TokenType::RIGHT_PAREN)?;
+        self.consume(TokenType::SEMICOLON)?;
 
-        let dest = self.chunk.borrow().code.len();
-        self.push(Jump::new(dest, true))?;
-        self.chunk.borrow_mut().swap_instructions(origin, dest)?;
+        let exit_jump = self.emit_jump(self.condition_jump(true))?;
+        self.push(Pop::new())?;
+        self.emit_loop(body_start)?;
 
+        self.patch_jump(exit_jump)?;
         self.push(Pop::new())?;
-        Ok(())
+        self.end_loop(condition_start)
     }
 
     fn func_decl(&'a self) -> Result<(), Box<dyn ErrTrait>> {
@@ -826,45 +1205,64 @@ impl<'a> Parser<'a> {
         let id = self.previous.borrow().as_ref().unwrap().clone();
 
         // mark the new fun as init
-        let scope = self
-            .compiler
-            .borrow_mut()
-            .add_local(format!("{}", id), true);
+        let scope = self.compiler.borrow_mut().add_local(
+            format!("{}", id),
+            true,
+            self.scanner.line().number,
+        );
         self.compiler.borrow().mark_latest_init();
 
         // function decl semantics
+        let upvalues_start = (*self.compiler.borrow().upvalues).borrow().len();
         let func = self.method(Some(id.clone()), None)?;
 
         // push fun instructions
         self.push(Constant::new(Value::Func(Rc::new(func))))?;
+        let captures = self.captures(upvalues_start);
+        if !captures.is_empty() {
+            self.push(Closure::new(captures))?;
+        }
         self.push(Define::new(scope, format!("{}", id)))?;
 
         Ok(())
     }
 
+    /// The upvalues from `start` a closure needs filled in when it's created:
+    /// locals of the current function, or upvalues the current function
+    /// already holds. Upvalues for locals of deeper functions are left to
+    /// those functions
+    fn captures(&'a self, start: usize) -> Vec<(usize, DefinitionScope)> {
+        let compiler = self.compiler.borrow();
+        let depth = compiler.depth();
+        let upvalues = (*compiler.upvalues).borrow();
+        let mut captures = Vec::new();
+        for (slot, upvalue) in upvalues.iter().enumerate().skip(start) {
+            if upvalue.depth == depth {
+                captures.push((slot, DefinitionScope::Local(upvalue.index)));
+            } else if upvalue.depth < depth {
+                captures.push((slot, DefinitionScope::UpValue(slot)));
+            }
+        }
+        captures
+    }
+
     fn return_(&'a self) -> Result<(), Box<dyn ErrTrait>> {
-        match self.compiler.borrow().context.as_str() {
-            "__init__" => match self.compiler.borrow().type_ {
-                FunctionType::Method(_, _) => {
-                    let scan_line = self.scanner.line();
-                    return Err(Box::new(ParserErr::new(
-                            "Can not return from a class initializer, the iniatializer implicitly returns an instance".to_string(),
-                            self.scanner.line_to_string(),
-                            scan_line.number,
-                            scan_line.offset,
-                        )));
-                }
-                _ => {}
-            },
-            _ => {
-                if !self.check(TokenType::SEMICOLON) {
-                    self.expression()?;
-                }
-                self.consume(TokenType::SEMICOLON)?;
+        let in_initializer = self.compiler.borrow().context.as_str() == "__init__"
+            && matches!(self.compiler.borrow().type_, FunctionType::Method(_, _));
 
-                self.push(Return::new())?;
+        if self.check(TokenType::SEMICOLON) {
+            // a bare return hands back nil, the initializer's caller
+            // discards it for the instance
+            self.push(Constant::new(Value::Nil))?;
+        } else {
+            if in_initializer {
+                return Err(self.error("Can not return a value from a class initializer, the iniatializer implicitly returns an instance".to_string()));
             }
-        };
+            self.expression()?;
+        }
+        self.consume(TokenType::SEMICOLON)?;
+
+        self.push(Return::new())?;
         Ok(())
     }
 
@@ -872,10 +1270,11 @@ impl<'a> Parser<'a> {
         self.consume(TokenType::IDENTIFIER)?;
         let id = self.previous.borrow().as_ref().unwrap().clone();
 
-        let scope = self
-            .compiler
-            .borrow_mut()
-            .add_local(format!("{}", id), true);
+        let scope = self.compiler.borrow_mut().add_local(
+            format!("{}", id),
+            true,
+            self.scanner.line().number,
+        );
         self.compiler.borrow().mark_latest_init();
 
         let mut inherits: bool = false;
@@ -884,6 +1283,12 @@ impl<'a> Parser<'a> {
         if self.match_(TokenType::LESS)? {
             self.consume(TokenType::IDENTIFIER)?;
             parent_class = self.previous.borrow().as_ref().unwrap().clone();
+            if format!("{}", parent_class) == format!("{}", id) {
+                return Err(self.error(format!(
+                    "A class can't inherit from itself, `{}` < `{}`",
+                    id, id
+                )));
+            }
             inherits = true;
         }
 
@@ -893,12 +1298,12 @@ impl<'a> Parser<'a> {
             false => None,
         };
         // define the class methods
-        self.consume(TokenType::LEFT_BRACE)?;
+        self.open_brace()?;
         while !self.check(TokenType::RIGHT_BRACE) && !self.check(TokenType::EOF) {
             let func = self.method(None, inheriting.clone())?;
             class.set_method(func);
         }
-        self.consume(TokenType::RIGHT_BRACE)?;
+        self.close_brace()?;
 
         self.push(Constant::new(Value::Class(Rc::new(class))))?;
         self.push(Define::new(scope.clone(), format!("{}", id)))?;
@@ -921,7 +1326,38 @@ impl<'a> Parser<'a> {
         if self.match_(TokenType::PRINT)? {
             return self.print();
         }
-        if self.match_(TokenType::LEFT_BRACE)? {
+        // a placeholder body, it compiles to nothing
+        if self.match_(TokenType::PASS)? {
+            return self.consume(TokenType::SEMICOLON);
+        }
+        // statements rather than declarations so a brace-less body like
+        // `if (done) break;` can be one
+        if self.match_(TokenType::IF)? {
+            return self.if_stmt();
+        }
+        if self.match_(TokenType::SWITCH)? {
+            return self.switch_stmt();
+        }
+        if self.match_(TokenType::WHILE)? {
+            return self.while_stmt();
+        }
+        if self.match_(TokenType::DO)? {
+            return self.do_while_stmt();
+        }
+        if self.match_(TokenType::FOR)? {
+            return self.for_stmt();
+        }
+        if self.match_(TokenType::RETURN)? {
+            return self.return_();
+        }
+        if self.match_(TokenType::BREAK)? {
+            return self.loop_control(TokenType::BREAK);
+        }
+        if self.match_(TokenType::CONTINUE)? {
+            return self.loop_control(TokenType::CONTINUE);
+        }
+        if self.check(TokenType::LEFT_BRACE) {
+            self.open_brace()?;
             self.start_scope();
             let res = self.block();
             self.end_scope()?;
@@ -932,47 +1368,126 @@ impl<'a> Parser<'a> {
     }
 
     fn declaration(&'a self) -> Result<(), Box<dyn ErrTrait>> {
+        // blocks stop before their `}`, one showing up here closes nothing
+        if self.check(TokenType::RIGHT_BRACE) {
+            return Err(
+                self.error("Unexpected '}', there is no open '{' for it to close".to_string())
+            );
+        }
         if self.match_(TokenType::VAR)? {
             return self.var_decl(false);
         }
         if self.match_(TokenType::CONST)? {
             return self.var_decl(true);
         }
-        if self.match_(TokenType::IF)? {
-            return self.if_stmt();
-        }
-        if self.match_(TokenType::WHILE)? {
-            return self.while_stmt();
-        }
-        if self.match_(TokenType::FOR)? {
-            return self.for_stmt();
+        if self.match_(TokenType::LAZY)? {
+            return self.lazy_decl();
         }
         if self.match_(TokenType::FUN)? {
             return self.func_decl();
         }
-        if self.match_(TokenType::RETURN)? {
-            return self.return_();
-        }
         if self.match_(TokenType::CLASS)? {
             return self.class_decl();
         }
         self.statement()
     }
 
+    /// Skips the rest of a declaration that failed so parsing can carry on
+    /// from the next statement, a block the error left open is skipped
+    /// along with it. Errors scanning the skipped tokens are collected too
+    fn synchronize(&'a self, errs: &mut Vec<Box<dyn ErrTrait>>) {
+        let mut depth = self.open_braces.borrow().len();
+        self.open_braces.borrow_mut().clear();
+        self.compiler.borrow_mut().recover();
+        // the token after a scanner error was skipped, the one left in
+        // `current` was already parsed and starts nothing
+        if *self.scan_failed.borrow() {
+            if let Err(err) = self.advance() {
+                errs.push(err);
+            }
+        }
+
+        let mut moved = false;
+        while !self.check(TokenType::EOF) {
+            if depth == 0 {
+                let previous = self.previous.borrow().as_ref().map(|prev| prev.token_type);
+                if moved
+                    && matches!(
+                        previous,
+                        Some(TokenType::SEMICOLON) | Some(TokenType::RIGHT_BRACE)
+                    )
+                {
+                    return;
+                }
+                // every one of these gets consumed by `declaration`, so
+                // stopping before one can't get stuck on it
+                let current = self.current.borrow().token_type;
+                if matches!(
+                    current,
+                    TokenType::CLASS
+                        | TokenType::FUN
+                        | TokenType::VAR
+                        | TokenType::CONST
+                        | TokenType::LAZY
+                        | TokenType::FOR
+                        | TokenType::IF
+                        | TokenType::SWITCH
+                        | TokenType::WHILE
+                        | TokenType::DO
+                        | TokenType::PRINT
+                        | TokenType::RETURN
+                        | TokenType::BREAK
+                        | TokenType::CONTINUE
+                ) {
+                    return;
+                }
+            }
+            match self.current.borrow().token_type {
+                TokenType::LEFT_BRACE => depth += 1,
+                TokenType::RIGHT_BRACE => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            if let Err(err) = self.advance() {
+                errs.push(err);
+            }
+            moved = true;
+        }
+    }
+
     pub fn parse(&'a self) -> Result<(), Box<dyn ErrTrait>> {
         let compiler_type = self.compiler.borrow().type_.clone();
         match compiler_type {
             FunctionType::Function(_, _) => return self.function(),
-            FunctionType::Script => loop {
-                if self.scanner.is_at_end() {
-                    break;
+            FunctionType::Script | FunctionType::Repl => {
+                let mut errs = Vec::new();
+                while !self.check(TokenType::EOF) {
+                    if let Err(err) = self.declaration() {
+                        errs.push(err);
+                        self.synchronize(&mut errs);
+                    }
+                }
+                errs.extend(self.compiler.borrow().undefined_forward_refs());
+                match errs.len() {
+                    0 => {}
+                    1 => return Err(errs.remove(0)),
+                    count => {
+                        return Err(Box::new(GroupErr::new(
+                            "Parse Errors",
+                            format!("found {} errors", count),
+                            errs,
+                        )))
+                    }
+                }
+                // a script ending in an expression statement returns its
+                // value instead of popping it
+                let last = self.chunk.borrow().code.len().checked_sub(1);
+                if last.is_some() && *self.last_expr_pop.borrow() == last {
+                    self.chunk.borrow_mut().pop_instruction();
+                    self.push(Return::new())?;
                 }
-                self.declaration()?;
-            },
-            FunctionType::Method(_, _) => {
-                self.this_decl()?;
-                return self.function();
             }
+            FunctionType::Method(_, _) => return self.function(),
+            FunctionType::Lazy(_, _) => return self.thunk(),
         }
         Ok(())
     }
@@ -1001,3 +1516,699 @@ Chunk:
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{
+        compiler::{
+            compiler::{Compiler, FunctionType},
+            warnings::take_warnings,
+        },
+        errors::err::{ErrTrait, ErrorFormat},
+        values::{func::Func, values::Value},
+        vm::{
+            config::{set_config, Config},
+            stack::Stack,
+            table::Table,
+            vm::VM,
+        },
+    };
+
+    fn compile(src: &str) -> Result<Func, Box<dyn ErrTrait>> {
+        Compiler::compile(
+            src.as_bytes().to_vec(),
+            FunctionType::Script,
+            Rc::new(RefCell::new(Table::new())),
+            None,
+            Rc::new(RefCell::new(Vec::new())),
+            None,
+        )
+    }
+
+    #[test]
+    fn test_warn_shadowing() {
+        let src = "var x = 1;\n{\n    var y = 2;\n    {\n        var x = 3;\n        var y = 4;\n    }\n}\n";
+        compile(src).unwrap();
+        assert!(take_warnings().is_empty());
+
+        set_config(Config {
+            warn_shadowing: true,
+            ..Config::default()
+        });
+        compile(src).unwrap();
+        assert_eq!(
+            take_warnings(),
+            vec![
+                "Line 5: `x` shadows a variable from an outer scope",
+                "Line 6: `y` shadows a variable from an outer scope"
+            ]
+        );
+        set_config(Config::default());
+
+        // reads and writes go to the innermost variable with the name
+        let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+        VM::interprate_to(
+            b"var c = 0;\n{\n    const c = 1;\n    {\n        var c = 2;\n        print c;\n        c = 3;\n        print c;\n    }\n    print c;\n}\nprint c;\n".to_vec(),
+            VM::globals(),
+            output.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output.borrow().clone()).unwrap(),
+            "2\n3\n1\n0\n"
+        );
+    }
+
+    #[test]
+    fn test_warn_unused() {
+        let src = "fun f(unread_param) {
+    var used = 1;
+    var unused = 2;
+    var assigned = 0;
+    assigned = used + 1;
+    var captured = 3;
+    fun g() { return captured; }
+    return assigned + g();
+}
+";
+        compile(src).unwrap();
+        assert!(take_warnings().is_empty());
+
+        set_config(Config {
+            warn_unused: true,
+            ..Config::default()
+        });
+        compile(src).unwrap();
+        assert_eq!(
+            take_warnings(),
+            vec!["Line 3: local `unused` is never used"]
+        );
+
+        // only ever written to
+        compile("{\n    var x = 1;\n    x = 2;\n}\n").unwrap();
+        assert_eq!(take_warnings(), vec!["Line 2: local `x` is never used"]);
+        set_config(Config::default());
+    }
+
+    #[test]
+    fn test_warn_unsafe_int() {
+        let src = "var id = 9007199254740993;\nvar big = 9007199254740991 + 2;\nvar bigger = big * 2;\nvar fine = 9007199254740991;\n";
+        let globals = VM::globals();
+        let func = VM::compile(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        VM::new(&func, globals.clone()).run().unwrap();
+        assert!(take_warnings().is_empty());
+
+        set_config(Config {
+            warn_unsafe_int: true,
+            ..Config::default()
+        });
+        let func = VM::compile(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        VM::new(&func, globals.clone()).run().unwrap();
+        assert_eq!(
+            take_warnings(),
+            vec![
+                "Line 1: `9007199254740993` is past 2^53 and is stored as 9007199254740992",
+                "\"+\" produced 9007199254740992 which is past 2^53, it may not be exact"
+            ]
+        );
+        set_config(Config::default());
+    }
+
+    #[test]
+    fn test_inheritance() {
+        let globals = VM::globals();
+        let src = "class Animal {
+    speak() { return \"...\"; }
+    legs() { return 4; }
+}
+class Bird < Animal {
+    legs() { return 2; }
+}
+var bird = Bird();
+var sound = bird.speak();
+var legs = bird.legs();
+";
+        VM::interprate_with(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        let global = |name: &str| (*globals).borrow().resolve(&name.into());
+        assert_eq!(global("sound"), Some(Value::String("...".into())));
+        assert_eq!(global("legs"), Some(Value::Number(2.0)));
+
+        let err = compile("class Loop < Loop {}\n").unwrap_err();
+        assert!(format!("{}", err).contains("can't inherit from itself"));
+    }
+
+    #[test]
+    fn test_super_calls() {
+        let globals = VM::globals();
+        let src = "class Shape {
+    __init__(name) { this.name = name; }
+    describe() { return \"shape \" + this.name; }
+}
+class Square < Shape {
+    describe() { return super.describe() + \" with 4 sides\"; }
+}
+var described = Square(\"sq\").describe();
+";
+        VM::interprate_with(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        assert_eq!(
+            (*globals).borrow().resolve(&"described".into()),
+            Some(Value::String("shape sq with 4 sides".into()))
+        );
+
+        let err =
+            compile("class Shape {\n    describe() { return super.describe(); }\n}\n").unwrap_err();
+        assert!(format!("{}", err).contains("Invalid use of super"));
+    }
+
+    #[test]
+    fn test_forward_references() {
+        let globals = VM::globals();
+        let src = "fun isEven(n) {
+    if (n == 0) { return true; }
+    return isOdd(n - 1);
+}
+fun isOdd(n) {
+    if (n == 0) { return false; }
+    return isEven(n - 1);
+}
+var even = isEven(10);
+var odd = isEven(7);
+";
+        VM::interprate_with(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        assert_eq!(
+            (*globals).borrow().resolve(&"even".into()),
+            Some(Value::Bool(true))
+        );
+        assert_eq!(
+            (*globals).borrow().resolve(&"odd".into()),
+            Some(Value::Bool(false))
+        );
+
+        // a name that's never declared is still a compile error
+        let err = compile("fun f() {\n    return g();\n}\n").unwrap_err();
+        assert!(format!("{}", err).contains("undefined variable: `g`"));
+        assert_eq!(err.position().unwrap().0, 2);
+        // top level code still has to come after the declaration
+        let err = compile("print later;\nvar later = 1;\n").unwrap_err();
+        assert!(format!("{}", err).contains("undefined variable: `later`"));
+        // and calling a function before what it refers to is declared fails
+        // when the name is read rather than treating it as nil
+        let err = VM::interprate_with(
+            b"fun f() { return g(); }\nprint f();\nfun g() { return 1; }\n".to_vec(),
+            VM::globals(),
+        )
+        .unwrap_err();
+        assert!(
+            format!("{}", err).contains("undefined variable: `g`"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_initializer() {
+        let globals = VM::globals();
+        let src = "class Point {
+    __init__(x, y) {
+        this.x = x;
+        if (y == nil) { return; }
+        this.y = y;
+    }
+}
+var point = Point(1, 2);
+var x = point.x;
+var y = point.y;
+var early = Point(3, nil);
+var returned_early = early.x;
+";
+        VM::interprate_with(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        let global = |name: &str| (*globals).borrow().resolve(&name.into());
+        assert_eq!(global("x"), Some(Value::Number(1.0)));
+        assert_eq!(global("y"), Some(Value::Number(2.0)));
+        assert_eq!(global("returned_early"), Some(Value::Number(3.0)));
+
+        // the VM reports runtime errors itself, so the script is called directly
+        let run_err = |src: &str| {
+            let globals = VM::globals();
+            let func = VM::compile(src.as_bytes().to_vec(), globals.clone()).unwrap();
+            let stack = Rc::new(RefCell::new(Stack::new()));
+            let frames = Rc::new(RefCell::new(Vec::new()));
+            format!("{}", func.call(stack, globals, frames, 0).unwrap_err())
+        };
+        assert!(run_err("class Empty {}\nEmpty(1);\n").contains("Expected 0 argument(s)"));
+        assert!(run_err("class Point { __init__(x) {} }\nPoint();\n")
+            .contains("Expected 1 argument(s)"));
+
+        let err = compile("class Point {\n    __init__() { return 1; }\n}\n").unwrap_err();
+        assert!(format!("{}", err).contains("Can not return a value from a class initializer"));
+    }
+
+    #[test]
+    fn test_getters() {
+        let globals = VM::globals();
+        let src = "class Rect {
+    __init__(w, h) {
+        this.w = w;
+        this.h = h;
+    }
+    get area { return this.w * this.h; }
+    get(key) { return key; }
+}
+var rect = Rect(2, 3);
+var area = rect.area;
+rect.w = 5;
+var grown = rect.area + 1;
+var plain = rect.get(\"w\");
+rect.area = 0;
+var shadowed = rect.area;
+";
+        VM::interprate_with(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        let global = |name: &str| (*globals).borrow().resolve(&name.into());
+        assert_eq!(global("area"), Some(Value::Number(6.0)));
+        assert_eq!(global("grown"), Some(Value::Number(16.0)));
+        // `get` is still usable as a method name
+        assert_eq!(global("plain"), Some(Value::String("w".into())));
+        // a field of the same name hides the getter
+        assert_eq!(global("shadowed"), Some(Value::Number(0.0)));
+    }
+
+    #[test]
+    fn test_break() {
+        let globals = VM::globals();
+        let src = "var i = 0;
+while (true) {
+    var square = i * i;
+    if (square > 20) { break; }
+    i = i + 1;
+}
+var pairs = 0;
+var last = nil;
+{
+    for (var a = 0; a < 3; a = a + 1) {
+        for (var b = 0; b < 10; b = b + 1) {
+            var sum = a + b;
+            if (b == 2) { break; }
+            pairs = pairs + 1;
+        }
+        last = a;
+    }
+    var after = \"locals still line up\";
+    last = after;
+}
+";
+        VM::interprate_with(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        let global = |name: &str| (*globals).borrow().resolve(&name.into());
+        assert_eq!(global("i"), Some(Value::Number(5.0)));
+        assert_eq!(global("pairs"), Some(Value::Number(6.0)));
+        assert_eq!(
+            global("last"),
+            Some(Value::String("locals still line up".into()))
+        );
+
+        // a brace-less body
+        let globals = VM::globals();
+        let src = "var n = 0;
+while (true) {
+    n = n + 1;
+    if (n > 3) break;
+}
+var m = 0;
+for (var k = 0; k < 10; k = k + 1) if (k == 2) break; else m = m + 1;
+fun first_over(limit) {
+    var x = 0;
+    while (true) if (x * x > limit) return x; else x = x + 1;
+}
+var over = first_over(10);
+";
+        VM::interprate_with(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        let global = |name: &str| (*globals).borrow().resolve(&name.into());
+        assert_eq!(global("n"), Some(Value::Number(4.0)));
+        assert_eq!(global("m"), Some(Value::Number(2.0)));
+        assert_eq!(global("over"), Some(Value::Number(4.0)));
+
+        let err = compile("break;\n").unwrap_err();
+        assert!(format!("{}", err).contains(
+            "`break` outside of a loop, it can only appear inside a `while`, `do` or `for` loop"
+        ));
+        let err = compile("while (true) {\n    fun f() { break; }\n}\n").unwrap_err();
+        assert!(format!("{}", err).contains("`break` outside of a loop"));
+    }
+
+    #[test]
+    fn test_continue() {
+        let globals = VM::globals();
+        let src = "var odds = 0;
+var i = 0;
+while (i < 10) {
+    var current = i;
+    i = i + 1;
+    if (current % 2 == 0) { continue; }
+    odds = odds + current;
+}
+var skipped = 0;
+var f;
+{
+    for (var j = 0; j < 5; j = j + 1) {
+        var doubled = j * 2;
+        if (j == 3) {
+            fun get() { return j; }
+            f = get;
+            continue;
+        }
+        skipped = skipped + doubled;
+    }
+}
+var captured = f();
+";
+        VM::interprate_with(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        let global = |name: &str| (*globals).borrow().resolve(&name.into());
+        assert_eq!(global("odds"), Some(Value::Number(25.0)));
+        assert_eq!(global("skipped"), Some(Value::Number(14.0)));
+        assert_eq!(global("captured"), Some(Value::Number(3.0)));
+
+        // skipping an iteration from a brace-less `if`
+        let globals = VM::globals();
+        let src = "var odds = 0;
+for (var i = 0; i < 10; i = i + 1) {
+    if (i % 2 == 0) continue;
+    odds = odds + 1;
+}
+var j = 0;
+var skipped = 0;
+while (j < 5) {
+    j = j + 1;
+    if (j == 3) continue;
+    else skipped = skipped + j;
+}
+";
+        VM::interprate_with(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        let global = |name: &str| (*globals).borrow().resolve(&name.into());
+        assert_eq!(global("odds"), Some(Value::Number(5.0)));
+        assert_eq!(global("skipped"), Some(Value::Number(12.0)));
+
+        let err = compile("continue;\n").unwrap_err();
+        assert!(format!("{}", err).contains(
+            "`continue` outside of a loop, it can only appear inside a `while`, `do` or `for` loop"
+        ));
+    }
+
+    #[test]
+    fn test_do_while() {
+        let globals = VM::globals();
+        let src = "var runs = 0;
+do {
+    runs = runs + 1;
+} while (false);
+var total = 0;
+var after;
+{
+    var n = 0;
+    do {
+        var step = n * 2;
+        n = n + 1;
+        if (n == 2) { continue; }
+        if (n == 5) { break; }
+        total = total + step;
+    } while (n < 10);
+    var kept = \"locals still line up\";
+    after = kept + \" \" + n;
+}
+var count = 0;
+do count = count + 1; while (count < 3);
+";
+        VM::interprate_with(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        let global = |name: &str| (*globals).borrow().resolve(&name.into());
+        assert_eq!(global("runs"), Some(Value::Number(1.0)));
+        assert_eq!(global("total"), Some(Value::Number(10.0)));
+        assert_eq!(
+            global("after"),
+            Some(Value::String("locals still line up 5".into()))
+        );
+        assert_eq!(global("count"), Some(Value::Number(3.0)));
+
+        let err = compile("do { print 1; } (true);\n").unwrap_err();
+        assert!(format!("{}", err).contains("Expected while but found ("));
+    }
+
+    #[test]
+    fn test_switch() {
+        let globals = VM::globals();
+        let src = "fun name(n) {
+    var result = \"none\";
+    switch (n) {
+        case 1:
+            result = \"one\";
+        case 1 + 1:
+            var text = \"two\";
+            result = text;
+        default:
+            result = \"many\";
+    }
+    return result;
+}
+var one = name(1);
+var two = name(2);
+var many = name(7);
+var calls = 0;
+fun next() { calls = calls + 1; return calls; }
+var untouched = \"untouched\";
+switch (next()) {
+    case 5: untouched = \"five\";
+    case 6: untouched = \"six\";
+}
+";
+        VM::interprate_with(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        let global = |name: &str| (*globals).borrow().resolve(&name.into());
+        assert_eq!(global("one"), Some(Value::String("one".into())));
+        assert_eq!(global("two"), Some(Value::String("two".into())));
+        assert_eq!(global("many"), Some(Value::String("many".into())));
+        // no case matched and there's no default, the value is still only
+        // evaluated once
+        assert_eq!(global("untouched"), Some(Value::String("untouched".into())));
+        assert_eq!(global("calls"), Some(Value::Number(1.0)));
+
+        let err =
+            compile("switch (1) {\n    default: print 1;\n    case 1: print 2;\n}\n").unwrap_err();
+        assert!(format!("{}", err).contains("`default` has to be the last case of a `switch`"));
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let globals = VM::globals();
+        let src = "var x = 1;
+x += 3;
+var y = 5;
+y *= 2;
+var z = 10;
+z -= 2 + 3;
+var w = 9;
+w /= 3;
+var s = \"a\";
+s += \"b\";
+var in_local;
+{
+    var l = 2;
+    l *= l += 1;
+    in_local = l;
+}
+";
+        VM::interprate_with(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        let global = |name: &str| (*globals).borrow().resolve(&name.into());
+        assert_eq!(global("x"), Some(Value::Number(4.0)));
+        assert_eq!(global("y"), Some(Value::Number(10.0)));
+        assert_eq!(global("z"), Some(Value::Number(5.0)));
+        assert_eq!(global("w"), Some(Value::Number(3.0)));
+        assert_eq!(global("s"), Some(Value::String("ab".into())));
+        assert_eq!(global("in_local"), Some(Value::Number(6.0)));
+
+        let err = compile("const c = 1;\nc += 1;\n").unwrap_err();
+        assert!(format!("{}", err).contains("Can not assign to `const` `c`"));
+        let err = compile("var a = 1;\nprint 1 + a += 1;\n").unwrap_err();
+        assert!(format!("{}", err).contains("Invalid assignment target"));
+    }
+
+    #[test]
+    fn test_pass() {
+        let globals = VM::globals();
+        let src = "fun todo() { pass; }
+fun later(x) { if (x) pass; }
+var branch = \"skipped\";
+if (false) pass; else branch = \"else\";
+if (true) { pass; }
+var done = todo();
+var also_done = later(true);
+";
+        VM::interprate_with(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        let global = |name: &str| (*globals).borrow().resolve(&name.into());
+        assert_eq!(global("branch"), Some(Value::String("else".into())));
+        assert_eq!(global("done"), Some(Value::Nil));
+        assert_eq!(global("also_done"), Some(Value::Nil));
+    }
+
+    #[test]
+    fn test_ternary() {
+        let globals = VM::globals();
+        let src = "var yes = true ? \"a\" : \"b\";
+var no = 1 > 2 ? \"a\" : \"b\";
+fun sign(n) { return n > 0 ? 1 : n < 0 ? -1 : 0; }
+var signs = sign(5) * 100 + sign(-5) * 10 + sign(0);
+var x;
+x = nil ? 1 : 2;
+{
+    var local = false ? 1 : 2;
+    var after = \"local\";
+    x = x + local;
+    yes = yes + after;
+}
+";
+        VM::interprate_with(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        let global = |name: &str| (*globals).borrow().resolve(&name.into());
+        assert_eq!(global("yes"), Some(Value::String("alocal".into())));
+        assert_eq!(global("no"), Some(Value::String("b".into())));
+        assert_eq!(global("signs"), Some(Value::Number(90.0)));
+        assert_eq!(global("x"), Some(Value::Number(4.0)));
+
+        let err = compile("var x = true ? 1;\n").unwrap_err();
+        assert!(format!("{}", err).contains("Expected :"));
+    }
+
+    #[test]
+    fn test_duplicate_parameter() {
+        let err = compile("fun f(a, a){}\n").unwrap_err();
+        assert!(format!("{}", err).contains("duplicate parameter name `a`"));
+        assert!(compile("fun f(a, b){}\n").is_ok());
+    }
+
+    #[test]
+    fn test_read_in_own_initializer() {
+        let err = compile("{\n    var a = a;\n}\n").unwrap_err();
+        assert!(
+            format!("{}", err).contains("Can not read local variable `a` in its own initializer")
+        );
+
+        let err = compile("var a = a;\n").unwrap_err();
+        assert!(format!("{}", err).contains("Can not read variable `a` in its own initializer"));
+
+        // an outer variable with the same name doesn't hide the mistake
+        let err = compile("{\n    var a = 1;\n    {\n        var a = a;\n    }\n}\n").unwrap_err();
+        assert!(
+            format!("{}", err).contains("Can not read local variable `a` in its own initializer")
+        );
+        assert_eq!(err.position().unwrap().0, 4);
+
+        let err = compile("{\n    var a = b;\n}\n").unwrap_err();
+        assert!(format!("{}", err).contains("undefined variable: `b`"));
+    }
+
+    #[test]
+    fn test_unbalanced_braces() {
+        let err = compile("var a = 1;\n{\n    print a;\n}\n}\nprint a;\n").unwrap_err();
+        assert!(format!("{}", err).contains("Unexpected '}'"));
+        assert_eq!(err.position().unwrap().0, 5);
+
+        let err = compile("var a = 1;\n{\n    {\n        print a;\n    }\nprint a;\n").unwrap_err();
+        assert!(format!("{}", err).contains("Unmatched '{'"));
+        let (line, offset, _) = err.position().unwrap();
+        assert_eq!((line, offset), (2, Some(1)));
+
+        let err = compile("class A {\n    method() {}\n").unwrap_err();
+        assert!(format!("{}", err).contains("Unmatched '{'"));
+        assert_eq!(err.position().unwrap().0, 1);
+    }
+
+    #[test]
+    fn test_error_caret_column() {
+        let err = compile("var a = 1;\nvar x = a 2;\n").unwrap_err();
+        assert_eq!(err.position().unwrap().0, 2);
+        assert_eq!(err.position().unwrap().1, Some(11));
+        assert!(format!("{}", err).contains(
+            "Line 2: var x = a 2;\n                  ^\n                  ------- Expected ; but found 2"
+        ));
+
+        let err = compile("// a comment\nvar x = 1 2;\n").unwrap_err();
+        let (line, column, _) = err.position().unwrap();
+        assert_eq!((line, column), (2, Some(11)));
+
+        // tokens in a function body keep their place in the file
+        let err = compile("fun f() {\n    var y = (1 + ;\n}\n").unwrap_err();
+        let (line, column, _) = err.position().unwrap();
+        assert_eq!((line, column), (2, Some(18)));
+        assert!(format!("{}", err).contains("Line 2:     var y = (1 + ;\n"));
+
+        // a multi-byte char before the error is a single column
+        let err = compile("print \"héllo\" + ;\n").unwrap_err();
+        let (line, column, _) = err.position().unwrap();
+        assert_eq!((line, column), (1, Some(17)));
+        assert!(
+            format!("{}", err).contains("Line 1: print \"héllo\" + ;\n                        ^\n")
+        );
+    }
+
+    #[test]
+    fn test_lines_after_multi_line_string() {
+        let err = compile("var s = \"first\nsecond\";\nvar x = s 2;\n").unwrap_err();
+        let (line, column, _) = err.position().unwrap();
+        assert_eq!((line, column), (3, Some(11)));
+
+        // a bad escape doesn't throw off the lines of later errors
+        let err = compile("var s = \"a\\q\nb\";\nvar x = s 2;\n").unwrap_err();
+        let errs = err.grouped().unwrap();
+        assert_eq!(errs.len(), 2);
+        assert_eq!(errs[0].position().unwrap().0, 1);
+        assert_eq!(errs[1].position().unwrap().0, 3);
+    }
+
+    #[test]
+    fn test_unreachable_after_return() {
+        let err = compile("fun f(n) {\n    return n;\n    print n;\n}\n").unwrap_err();
+        assert!(format!("{}", err).contains("Unreachable code"));
+        assert_eq!(err.position().unwrap().0, 3);
+
+        let err =
+            compile("fun f(n) {\n    if (n) {\n        return 1;\n        n = 2;\n    }\n}\n")
+                .unwrap_err();
+        assert!(format!("{}", err).contains("Unreachable code"));
+
+        // a return ending its block, or inside a nested one, leaves nothing dead
+        assert!(compile("fun f(n) {\n    print n;\n    return n;\n}\n").is_ok());
+        assert!(compile("fun f(n) {\n    if (n) { return 1; }\n    return 2;\n}\n").is_ok());
+    }
+
+    #[test]
+    fn test_multiple_parse_errors() {
+        let err = compile(
+            "var = 1;
+print \"fine\";
+{
+    print (1;
+}
+print 1 2;
+print \"also fine\";
+var x = ;
+print x;
+",
+        )
+        .unwrap_err();
+        let errs = err.grouped().unwrap();
+        // `x` failed to compile, using it afterwards isn't another error
+        assert_eq!(errs.len(), 4);
+        let lines: Vec<String> = errs
+            .iter()
+            .map(|err| err.formatted(ErrorFormat::Plain, "prog.lox"))
+            .collect();
+        assert!(lines[0].contains("Expected <var> but found ="));
+        assert!(lines[1].contains("Expected ) but found ;"));
+        assert!(lines[2].contains("Expected ; but found 2"));
+        assert!(lines[3].contains("Expected expression, found ;"));
+        assert_eq!(
+            err.formatted(ErrorFormat::Plain, "prog.lox"),
+            lines.join("\n")
+        );
+
+        // a single error is still reported on its own
+        let err = compile("var = 1;\nprint 1;\n").unwrap_err();
+        assert!(err.grouped().is_none());
+    }
+}
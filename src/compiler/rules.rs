@@ -9,15 +9,17 @@ use super::{err::InterpreterErr, parser::Parser, token::TokenType};
 pub enum Precendence {
     None = 0,
     Assignment = 1,
-    Or = 2,
-    And = 3,
-    Equality = 4,
-    Comparison = 5,
-    Term = 6,
-    Factor = 7,
-    Unary = 8,
-    Call = 9,
-    Primary = 10,
+    Ternary = 2,
+    Or = 3,
+    And = 4,
+    Equality = 5,
+    Comparison = 6,
+    Term = 7,
+    Factor = 8,
+    Unary = 9,
+    Power = 10,
+    Call = 11,
+    Primary = 12,
 }
 
 impl Precendence {
@@ -33,15 +35,17 @@ impl TryFrom<u8> for Precendence {
         match value {
             0 => Ok(Self::None),
             1 => Ok(Self::Assignment),
-            2 => Ok(Self::Or),
-            3 => Ok(Self::And),
-            4 => Ok(Self::Equality),
-            5 => Ok(Self::Comparison),
-            6 => Ok(Self::Term),
-            7 => Ok(Self::Factor),
-            8 => Ok(Self::Unary),
-            9 => Ok(Self::Call),
-            10 => Ok(Self::Primary),
+            2 => Ok(Self::Ternary),
+            3 => Ok(Self::Or),
+            4 => Ok(Self::And),
+            5 => Ok(Self::Equality),
+            6 => Ok(Self::Comparison),
+            7 => Ok(Self::Term),
+            8 => Ok(Self::Factor),
+            9 => Ok(Self::Unary),
+            10 => Ok(Self::Power),
+            11 => Ok(Self::Call),
+            12 => Ok(Self::Primary),
             _ => Err(Box::new(InterpreterErr::new(format!(
                 "Couldn't construct precedence from {}, invalid value passed",
                 value
@@ -76,8 +80,10 @@ pub fn construct_rule<'a>(token_type: TokenType) -> ParseRule<'a> {
             precedence: Precendence::None,
         },
 
+        // a statement starting with `{` is a block, anywhere an expression
+        // is expected it's a map literal
         TokenType::LEFT_BRACE => ParseRule {
-            prefix: None,
+            prefix: Some(Box::new(|parser, _| parser.map())),
             infix: None,
             precedence: Precendence::None,
         },
@@ -88,6 +94,18 @@ pub fn construct_rule<'a>(token_type: TokenType) -> ParseRule<'a> {
             precedence: Precendence::None,
         },
 
+        TokenType::LEFT_BRACKET => ParseRule {
+            prefix: Some(Box::new(|parser, _| parser.array())),
+            infix: Some(Box::new(|parser, can_assign| parser.index(can_assign))),
+            precedence: Precendence::Call,
+        },
+
+        TokenType::RIGHT_BRACKET => ParseRule {
+            prefix: None,
+            infix: None,
+            precedence: Precendence::None,
+        },
+
         TokenType::COMMA => ParseRule {
             prefix: None,
             infix: None,
@@ -130,6 +148,46 @@ pub fn construct_rule<'a>(token_type: TokenType) -> ParseRule<'a> {
             precedence: Precendence::Factor,
         },
 
+        TokenType::PERCENT => ParseRule {
+            prefix: None,
+            infix: Some(Box::new(|parser, _| parser.binary())),
+            precedence: Precendence::Factor,
+        },
+
+        TokenType::TILDE_SLASH => ParseRule {
+            prefix: None,
+            infix: Some(Box::new(|parser, _| parser.binary())),
+            precedence: Precendence::Factor,
+        },
+
+        TokenType::STAR_STAR => ParseRule {
+            prefix: None,
+            infix: Some(Box::new(|parser, _| parser.binary())),
+            precedence: Precendence::Power,
+        },
+
+        // compound assignments are picked up by `Parser::var`
+        TokenType::PLUS_EQUAL
+        | TokenType::MINUS_EQUAL
+        | TokenType::STAR_EQUAL
+        | TokenType::SLASH_EQUAL => ParseRule {
+            prefix: None,
+            infix: None,
+            precedence: Precendence::None,
+        },
+
+        TokenType::QUESTION => ParseRule {
+            prefix: None,
+            infix: Some(Box::new(|parser, _| parser.ternary())),
+            precedence: Precendence::Ternary,
+        },
+
+        TokenType::COLON => ParseRule {
+            prefix: None,
+            infix: None,
+            precedence: Precendence::None,
+        },
+
         TokenType::BANG => ParseRule {
             prefix: Some(Box::new(|parser, _| parser.unary())),
             infix: None,
@@ -202,6 +260,18 @@ pub fn construct_rule<'a>(token_type: TokenType) -> ParseRule<'a> {
             precedence: Precendence::And,
         },
 
+        TokenType::BREAK => ParseRule {
+            prefix: None,
+            infix: None,
+            precedence: Precendence::None,
+        },
+
+        TokenType::CONTINUE => ParseRule {
+            prefix: None,
+            infix: None,
+            precedence: Precendence::None,
+        },
+
         TokenType::CLASS => ParseRule {
             prefix: None,
             infix: None,
@@ -238,6 +308,18 @@ pub fn construct_rule<'a>(token_type: TokenType) -> ParseRule<'a> {
             precedence: Precendence::None,
         },
 
+        TokenType::IS => ParseRule {
+            prefix: None,
+            infix: Some(Box::new(|parser, _| parser.binary())),
+            precedence: Precendence::Comparison,
+        },
+
+        TokenType::LAZY => ParseRule {
+            prefix: None,
+            infix: None,
+            precedence: Precendence::None,
+        },
+
         TokenType::NIL => ParseRule {
             prefix: Some(Box::new(|parser, _| parser.literal())),
             infix: None,
@@ -250,6 +332,12 @@ pub fn construct_rule<'a>(token_type: TokenType) -> ParseRule<'a> {
             precedence: Precendence::Or,
         },
 
+        TokenType::PASS => ParseRule {
+            prefix: None,
+            infix: None,
+            precedence: Precendence::None,
+        },
+
         TokenType::PRINT => ParseRule {
             prefix: None,
             infix: None,
@@ -298,6 +386,30 @@ pub fn construct_rule<'a>(token_type: TokenType) -> ParseRule<'a> {
             precedence: Precendence::None,
         },
 
+        TokenType::DO => ParseRule {
+            prefix: None,
+            infix: None,
+            precedence: Precendence::None,
+        },
+
+        TokenType::SWITCH => ParseRule {
+            prefix: None,
+            infix: None,
+            precedence: Precendence::None,
+        },
+
+        TokenType::CASE => ParseRule {
+            prefix: None,
+            infix: None,
+            precedence: Precendence::None,
+        },
+
+        TokenType::DEFAULT => ParseRule {
+            prefix: None,
+            infix: None,
+            precedence: Precendence::None,
+        },
+
         TokenType::EOF => ParseRule {
             prefix: None,
             infix: None,
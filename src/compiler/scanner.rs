@@ -9,7 +9,6 @@ use super::{
 
 pub struct Line {
     pub number: usize,
-    pub offset: usize,
 }
 
 const FORWARD: u8 = 0;
@@ -21,21 +20,37 @@ pub struct Scanner<'a> {
     current: RefCell<usize>,
     start: RefCell<usize>,
     line: RefCell<usize>,
+    first_line: usize,
     // Forcing Scanner to have 'a lifetime
     phantom: PhantomData<&'a ()>,
 }
 
 impl<'a> Scanner<'a> {
-    pub fn new(stream: Vec<u8>) -> Self {
+    pub fn new(mut stream: Vec<u8>) -> Self {
+        // the last byte is never scanned, a closing newline keeps empty
+        // input from underflowing and files without one from losing
+        // their final character
+        if stream.last() != Some(&b'\n') {
+            stream.push(b'\n');
+        }
         Scanner {
             input_stream: stream,
             current: RefCell::new(0),
             start: RefCell::new(0),
             line: RefCell::new(1),
+            first_line: 1,
             phantom: PhantomData,
         }
     }
 
+    /// For source that begins part way into a file, e.g. a function body, so
+    /// its tokens carry the line they're on in the whole file
+    pub fn starting_at(mut self, line: usize) -> Self {
+        *self.line.borrow_mut() = line;
+        self.first_line = line;
+        self
+    }
+
     fn is_alpha(c: char) -> bool {
         match c {
             'a'..='z' | 'A'..='Z' | '_' => return true,
@@ -80,10 +95,33 @@ impl<'a> Scanner<'a> {
     pub(super) fn line(&self) -> Line {
         Line {
             number: *self.line.borrow(),
-            offset: *self.current.borrow() - self.seek('\n', BACKWARD, None),
         }
     }
 
+    /// 1 based column of the byte at `index`, counted in chars so a
+    /// multi-byte char earlier on the line takes up a single column
+    fn column(&self, index: usize) -> usize {
+        let line_start = self.input_stream[..index]
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+            .map_or(0, |newline| newline + 1);
+        // continuation bytes of a UTF-8 char are 0b10xxxxxx
+        self.input_stream[line_start..index]
+            .iter()
+            .filter(|&&byte| byte & 0xC0 != 0x80)
+            .count()
+            + 1
+    }
+
+    /// Contents of line `number`, without its newline
+    pub(super) fn line_at(&self, number: usize) -> String {
+        self.input_stream
+            .split(|&byte| byte == b'\n')
+            .nth(number.saturating_sub(self.first_line))
+            .map(|line| String::from_utf8_lossy(line).trim_end().to_string())
+            .unwrap_or_default()
+    }
+
     pub(super) fn line_to_string(&self) -> String {
         let curr = match *self.current.borrow() >= self.input_stream.len() {
             true => self.input_stream.len() - 1,
@@ -141,17 +179,21 @@ impl<'a> Scanner<'a> {
         expected: &[u8],
         token_type: TokenType,
     ) -> Result<TokenType, Box<dyn ErrTrait>> {
-        if self.input_stream.len() > *self.current.borrow() + offset {
-            if expected
-                == &self.input_stream[*self.current.borrow()..=*self.current.borrow() + offset]
-            {
+        let end = *self.current.borrow() + offset;
+        if self.input_stream.len() > end {
+            // the keyword has to be the whole word, `lazyValue` is an identifier
+            let followed_by_ident = match self.input_stream.get(end + 1) {
+                Some(c) => Self::is_alpha(*c as char) || Self::is_digit(*c as char),
+                None => false,
+            };
+            if expected == &self.input_stream[*self.current.borrow()..=end] && !followed_by_ident {
                 return Ok(token_type);
             }
         }
         Ok(TokenType::IDENTIFIER)
     }
 
-    fn skip_whitespace(&self) {
+    fn skip_whitespace(&self) -> Result<(), Box<dyn ErrTrait>> {
         loop {
             if self.input_stream.len() <= *self.current.borrow() {
                 break;
@@ -162,17 +204,20 @@ impl<'a> Scanner<'a> {
                     self.advance();
                 }
                 '/' => {
-                    if self.peek_next() == '/' {
-                        loop {
-                            if self.peek() != '\n' && !self.is_at_end() {
-                                self.advance()
-                            } else {
-                                if self.peek() == '\n' {
-                                    self.advance()
-                                }
-                                break;
-                            }
-                        }
+                    if self.is_at_end() {
+                        break;
+                    }
+                    if self.peek_next() == '*' {
+                        self.block_comment()?;
+                        continue;
+                    }
+                    // a lone slash is division, left for `scan`
+                    if self.peek_next() != '/' {
+                        break;
+                    }
+                    // the newline is left for the arm below to count
+                    while self.peek() != '\n' && !self.is_at_end() {
+                        self.advance()
                     }
                 }
                 '\n' => {
@@ -182,6 +227,53 @@ impl<'a> Scanner<'a> {
                 _ => break,
             }
         }
+        Ok(())
+    }
+
+    /// Skips a `/* ... */` comment. Comments nest, so `/* a /* b */ c */`
+    /// is skipped as a whole and commenting out code that already has a
+    /// block comment in it works
+    fn block_comment(&self) -> Result<(), Box<dyn ErrTrait>> {
+        let start_line = *self.line.borrow();
+        let start_line_contents = self.line_to_string();
+        let start_offset = self.column(*self.current.borrow());
+
+        // step over the opening `/*`
+        self.advance();
+        self.advance();
+        let mut depth: usize = 1;
+        while depth > 0 {
+            let current = *self.current.borrow();
+            match (
+                self.input_stream.get(current),
+                self.input_stream.get(current + 1),
+            ) {
+                (None, _) => {
+                    return Err(Box::new(ScannerErr::new(
+                        "Unterminated block comment".to_string(),
+                        start_line_contents,
+                        start_line,
+                        start_offset,
+                    )));
+                }
+                (Some(b'*'), Some(b'/')) => {
+                    depth -= 1;
+                    self.advance();
+                    self.advance();
+                }
+                (Some(b'/'), Some(b'*')) => {
+                    depth += 1;
+                    self.advance();
+                    self.advance();
+                }
+                (Some(b'\n'), _) => {
+                    self.line.replace_with(|&mut old| old + 1);
+                    self.advance();
+                }
+                _ => self.advance(),
+            }
+        }
+        Ok(())
     }
 
     fn make_token(&'a self, token_type: TokenType) -> Token<'a> {
@@ -190,63 +282,173 @@ impl<'a> Scanner<'a> {
             token_type,
             &self.input_stream[*self.start.borrow()..*self.current.borrow()],
             *self.line.borrow() as u32,
+            self.column(*self.start.borrow()) as u32,
         )
     }
 
+    fn digits(&self) {
+        while !self.is_at_end() && Self::is_digit(self.peek_next()) {
+            self.advance();
+        }
+    }
+
     fn number(&'a self) -> Result<Token<'a>, Box<dyn ErrTrait>> {
-        loop {
-            if (Self::is_digit(self.peek_next()) || self.peek_next() == '.') && !self.is_at_end() {
+        self.digits();
+        // the dot is only part of the number when a digit follows it, `10.`
+        // leaves the dot to the parser
+        let fraction_start = *self.current.borrow() + 2;
+        if !self.is_at_end()
+            && self.peek_next() == '.'
+            && fraction_start < self.input_stream.len()
+            && Self::is_digit(self.input_stream[fraction_start] as char)
+        {
+            self.advance();
+            self.digits();
+        }
+        // `e`/`E` starts an exponent, which needs digits after its sign
+        // so `1e` is an error rather than a number and an identifier
+        if !self.is_at_end() && matches!(self.peek_next(), 'e' | 'E') {
+            self.advance();
+            if !self.is_at_end() && matches!(self.peek_next(), '+' | '-') {
                 self.advance();
-            } else {
-                break;
             }
+            if self.is_at_end() || !Self::is_digit(self.peek_next()) {
+                let err = Box::new(ScannerErr::new(
+                    format!(
+                        "malformed number {:?}: the exponent needs at least one digit",
+                        String::from_utf8_lossy(
+                            &self.input_stream[*self.start.borrow()..=*self.current.borrow()]
+                        )
+                    ),
+                    self.line_to_string(),
+                    *self.line.borrow(),
+                    self.column(*self.current.borrow() + 1),
+                ));
+                // step past the `e` and its sign so scanning carries on
+                // after them rather than reading the `e` as a name
+                self.advance();
+                return Err(err);
+            }
+            self.digits();
         }
-        self.skip_whitespace();
+        self.skip_whitespace()?;
         Ok(self.make_token(TokenType::NUMBER))
     }
 
+    /// Scans a string literal, the token keeps the raw source between the
+    /// quotes, escapes are only checked here and decoded by `unescape`
     fn string(&'a self) -> Result<Token<'a>, Box<dyn ErrTrait>> {
-        let mut at_begining = true;
-        while self.peek_next() != '"' && !self.is_at_end() {
-            if self.peek_next() == '\n' {
-                self.line.replace_with(|&mut old| old + 1);
-            }
-            if at_begining {
-                let current_start = *self.start.borrow();
-                self.start.replace(current_start + 1);
-                at_begining = false;
+        let start_line = *self.line.borrow();
+        let start_column = self.column(*self.start.borrow());
+        // leave the opening quote out of the literal
+        self.start.replace_with(|&mut old| old + 1);
+        // a bad escape is only reported once the whole string is scanned,
+        // so scanning picks back up after the closing quote
+        let mut bad_escape: Option<Box<dyn ErrTrait>> = None;
+        while !self.is_at_end() && self.peek_next() != '"' {
+            match self.peek_next() {
+                '\n' => {
+                    self.line.replace_with(|&mut old| old + 1);
+                }
+                '\\' => {
+                    self.advance();
+                    if self.is_at_end() {
+                        break;
+                    }
+                    let escaped = self.peek_next();
+                    if !matches!(escaped, 'n' | 't' | 'r' | '\\' | '"' | '0')
+                        && bad_escape.is_none()
+                    {
+                        bad_escape = Some(Box::new(ScannerErr::new(
+                            format!("Unknown escape sequence `\\{}` in string", escaped),
+                            self.line_to_string(),
+                            *self.line.borrow(),
+                            self.column(*self.current.borrow()),
+                        )));
+                    }
+                    if escaped == '\n' {
+                        self.line.replace_with(|&mut old| old + 1);
+                    }
+                }
+                _ => {}
             }
             self.advance();
         }
-        if self.peek_next() != '"' && self.is_at_end() {
+        if self.is_at_end() || self.peek_next() != '"' {
+            // point back at the opening quote, like an unclosed block comment
             return Err(Box::new(ScannerErr::new(
                 "Unterminated string".to_string(),
-                self.line_to_string(),
-                *self.line.borrow(),
-                *self.current.borrow() - self.seek('\n', BACKWARD, None),
+                self.line_at(start_line),
+                start_line,
+                start_column,
             )));
         }
         let token = self.make_token(TokenType::STRING);
         self.advance();
-        Ok(token)
+        match bad_escape {
+            Some(err) => Err(err),
+            None => Ok(token),
+        }
+    }
+
+    /// Decodes the escapes in a string literal the scanner has already checked
+    pub fn unescape(literal: &[u8]) -> String {
+        let raw = String::from_utf8_lossy(literal);
+        let mut decoded = String::with_capacity(raw.len());
+        let mut chars = raw.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                decoded.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => decoded.push('\n'),
+                Some('t') => decoded.push('\t'),
+                Some('r') => decoded.push('\r'),
+                Some('0') => decoded.push('\0'),
+                Some(c) => decoded.push(c),
+                None => decoded.push('\\'),
+            }
+        }
+        decoded
     }
 
     fn identifier(&'a self) -> Result<Token<'a>, Box<dyn ErrTrait>> {
         let token_type: TokenType = match self.peek() {
             'a' => self.check_keyword(2, &['a' as u8, 'n' as u8, 'd' as u8], TokenType::AND)?,
+            'b' => self.check_keyword(
+                4,
+                &['b' as u8, 'r' as u8, 'e' as u8, 'a' as u8, 'k' as u8],
+                TokenType::BREAK,
+            )?,
             'c' => match self.peek_next() {
+                'a' => self.check_keyword(3, b"case", TokenType::CASE)?,
                 'l' => self.check_keyword(
                     4,
                     &['c' as u8, 'l' as u8, 'a' as u8, 's' as u8, 's' as u8],
                     TokenType::CLASS,
                 )?,
-                'o' => self.check_keyword(
+                'o' => match self.check_keyword(
                     4,
                     &['c' as u8, 'o' as u8, 'n' as u8, 's' as u8, 't' as u8],
                     TokenType::CONST,
-                )?,
+                )? {
+                    TokenType::IDENTIFIER => self.check_keyword(
+                        7,
+                        &[
+                            'c' as u8, 'o' as u8, 'n' as u8, 't' as u8, 'i' as u8, 'n' as u8,
+                            'u' as u8, 'e' as u8,
+                        ],
+                        TokenType::CONTINUE,
+                    )?,
+                    token_type => token_type,
+                },
                 _ => TokenType::IDENTIFIER,
             },
+            'd' => match self.peek_next() {
+                'e' => self.check_keyword(6, b"default", TokenType::DEFAULT)?,
+                _ => self.check_keyword(1, b"do", TokenType::DO)?,
+            },
             'e' => self.check_keyword(
                 3,
                 &['e' as u8, 'l' as u8, 's' as u8, 'e' as u8],
@@ -262,14 +464,27 @@ impl<'a> Scanner<'a> {
                 'u' => self.check_keyword(2, &['f' as u8, 'u' as u8, 'n' as u8], TokenType::FUN)?,
                 _ => TokenType::IDENTIFIER,
             },
-            'i' => self.check_keyword(1, &['i' as u8, 'f' as u8], TokenType::IF)?,
+            'i' => match self.peek_next() {
+                'f' => self.check_keyword(1, &['i' as u8, 'f' as u8], TokenType::IF)?,
+                's' => self.check_keyword(1, b"is", TokenType::IS)?,
+                _ => TokenType::IDENTIFIER,
+            },
+            'l' => self.check_keyword(3, b"lazy", TokenType::LAZY)?,
             'n' => self.check_keyword(2, &['n' as u8, 'i' as u8, 'l' as u8], TokenType::NIL)?,
             'o' => self.check_keyword(1, &['o' as u8, 'r' as u8], TokenType::OR)?,
-            'p' => self.check_keyword(
-                4,
-                &['p' as u8, 'r' as u8, 'i' as u8, 'n' as u8, 't' as u8],
-                TokenType::PRINT,
-            )?,
+            'p' => match self.peek_next() {
+                'a' => self.check_keyword(
+                    3,
+                    &['p' as u8, 'a' as u8, 's' as u8, 's' as u8],
+                    TokenType::PASS,
+                )?,
+                'r' => self.check_keyword(
+                    4,
+                    &['p' as u8, 'r' as u8, 'i' as u8, 'n' as u8, 't' as u8],
+                    TokenType::PRINT,
+                )?,
+                _ => TokenType::IDENTIFIER,
+            },
             'r' => self.check_keyword(
                 5,
                 &[
@@ -277,11 +492,14 @@ impl<'a> Scanner<'a> {
                 ],
                 TokenType::RETURN,
             )?,
-            's' => self.check_keyword(
-                4,
-                &['s' as u8, 'u' as u8, 'p' as u8, 'e' as u8, 'r' as u8],
-                TokenType::SUPER,
-            )?,
+            's' => match self.peek_next() {
+                'w' => self.check_keyword(5, b"switch", TokenType::SWITCH)?,
+                _ => self.check_keyword(
+                    4,
+                    &['s' as u8, 'u' as u8, 'p' as u8, 'e' as u8, 'r' as u8],
+                    TokenType::SUPER,
+                )?,
+            },
             't' => match self.peek_next() {
                 'h' => self.check_keyword(
                     3,
@@ -310,13 +528,14 @@ impl<'a> Scanner<'a> {
     }
 
     fn scan(&'a self) -> Result<Token<'a>, Box<dyn ErrTrait>> {
-        self.skip_whitespace();
+        self.skip_whitespace()?;
         *self.start.borrow_mut() = *self.current.borrow();
         if self.is_at_end() {
             return Ok(Token::new(
                 TokenType::EOF,
                 &['e' as u8, 'o' as u8, 'f' as u8],
                 *self.line.borrow() as u32,
+                self.column(*self.start.borrow()) as u32,
             ));
         }
 
@@ -335,13 +554,46 @@ impl<'a> Scanner<'a> {
             ')' => Ok(self.make_token(TokenType::RIGHT_PAREN)),
             '{' => Ok(self.make_token(TokenType::LEFT_BRACE)),
             '}' => Ok(self.make_token(TokenType::RIGHT_BRACE)),
+            '[' => Ok(self.make_token(TokenType::LEFT_BRACKET)),
+            ']' => Ok(self.make_token(TokenType::RIGHT_BRACKET)),
             ';' => Ok(self.make_token(TokenType::SEMICOLON)),
             ',' => Ok(self.make_token(TokenType::COMMA)),
             '.' => Ok(self.make_token(TokenType::DOT)),
-            '-' => Ok(self.make_token(TokenType::MINUS)),
-            '+' => Ok(self.make_token(TokenType::PLUS)),
-            '/' => Ok(self.make_token(TokenType::SLASH)),
-            '*' => Ok(self.make_token(TokenType::STAR)),
+            '-' => {
+                if self.match_next('=') {
+                    Ok(self.make_token(TokenType::MINUS_EQUAL))
+                } else {
+                    Ok(self.make_token(TokenType::MINUS))
+                }
+            }
+            '+' => {
+                if self.match_next('=') {
+                    Ok(self.make_token(TokenType::PLUS_EQUAL))
+                } else {
+                    Ok(self.make_token(TokenType::PLUS))
+                }
+            }
+            '/' => {
+                if self.match_next('=') {
+                    Ok(self.make_token(TokenType::SLASH_EQUAL))
+                } else {
+                    Ok(self.make_token(TokenType::SLASH))
+                }
+            }
+            '*' => {
+                if self.match_next('*') {
+                    Ok(self.make_token(TokenType::STAR_STAR))
+                } else if self.match_next('=') {
+                    Ok(self.make_token(TokenType::STAR_EQUAL))
+                } else {
+                    Ok(self.make_token(TokenType::STAR))
+                }
+            }
+            // `//` already starts a comment so floor division is spelled `~/`
+            '~' if self.match_next('/') => Ok(self.make_token(TokenType::TILDE_SLASH)),
+            '%' => Ok(self.make_token(TokenType::PERCENT)),
+            '?' => Ok(self.make_token(TokenType::QUESTION)),
+            ':' => Ok(self.make_token(TokenType::COLON)),
             '!' => {
                 let token;
                 if self.match_next('=') {
@@ -390,7 +642,7 @@ impl<'a> Scanner<'a> {
                     ),
                     self.line_to_string(),
                     *self.line.borrow(),
-                    *self.current.borrow() - self.seek('\n', BACKWARD, None),
+                    self.column(*self.start.borrow()),
                 )));
             }
         };
@@ -413,3 +665,196 @@ impl<'a> Display for Scanner<'a> {
         write!(f, "> {}", self.line_to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{values::values::Value, vm::vm::VM};
+
+    fn scan_all(src: &str) -> Vec<(TokenType, String)> {
+        let scanner = Scanner::new(src.as_bytes().to_vec());
+        let mut tokens = Vec::new();
+        loop {
+            let token = scanner.next().unwrap();
+            let done = token.token_type == TokenType::EOF;
+            tokens.push((token.token_type, format!("{}", token)));
+            if done {
+                break;
+            }
+        }
+        tokens
+    }
+
+    #[test]
+    fn test_decimal_numbers() {
+        let number = |literal: &str| (TokenType::NUMBER, literal.to_string());
+
+        let tokens = scan_all("1.5\n");
+        assert_eq!(tokens[0], number("1.5"));
+        assert_eq!(tokens[1].0, TokenType::EOF);
+        assert_eq!(scan_all("0.001\n")[0], number("0.001"));
+        assert_eq!(
+            scan_all("10.\n")[0..2],
+            [number("10"), (TokenType::DOT, ".".to_string())]
+        );
+        assert_eq!(
+            scan_all("3.14.x\n")[0..3],
+            [
+                number("3.14"),
+                (TokenType::DOT, ".".to_string()),
+                (TokenType::IDENTIFIER, "x".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scientific_numbers() {
+        for (literal, value) in [
+            ("1e10", 1e10),
+            ("2.5e-3", 0.0025),
+            ("6.022E23", 6.022e23),
+            ("7e+2", 700.0),
+        ] {
+            let tokens = scan_all(&format!("{}\n", literal));
+            assert_eq!(tokens[0], (TokenType::NUMBER, literal.to_string()));
+            assert_eq!(tokens[1].0, TokenType::EOF);
+
+            let globals = VM::globals();
+            VM::interprate_with(
+                format!("var x = {};\n", literal).into_bytes(),
+                globals.clone(),
+            )
+            .unwrap();
+            assert_eq!(
+                (*globals).borrow().resolve(&"x".into()),
+                Some(Value::Number(value)),
+                "{}",
+                literal
+            );
+        }
+
+        for malformed in ["1e;\n", "1e+;\n", "2.5E-;\n"] {
+            let scanner = Scanner::new(malformed.as_bytes().to_vec());
+            let err = scanner.next().unwrap_err();
+            assert!(
+                format!("{}", err).contains("the exponent needs at least one digit"),
+                "{}",
+                err
+            );
+            // the bad exponent is skipped along with the number
+            assert_eq!(scanner.next().unwrap().token_type, TokenType::SEMICOLON);
+        }
+        // and it's the only error the script reports
+        let err = VM::compile(b"print 3e;\nprint 1;\n".to_vec(), VM::globals()).unwrap_err();
+        assert!(err.grouped().is_none(), "{}", err);
+        assert!(format!("{}", err).contains("the exponent needs at least one digit"));
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let decoded = |src: &str| {
+            let scanner = Scanner::new(src.as_bytes().to_vec());
+            let token = scanner.next().unwrap();
+            assert_eq!(token.token_type, TokenType::STRING);
+            Scanner::unescape(token.literal)
+        };
+
+        assert_eq!(decoded("\"line1\\nline2\"\n"), "line1\nline2");
+        assert_eq!(decoded("\"a\\tb\"\n"), "a\tb");
+        assert_eq!(decoded("\"a\\rb\"\n"), "a\rb");
+        assert_eq!(decoded("\"a\\\\b\"\n"), "a\\b");
+        assert_eq!(decoded("\"say \\\"hi\\\"\"\n"), "say \"hi\"");
+        assert_eq!(decoded("\"a\\0b\"\n"), "a\0b");
+        assert_eq!(decoded("\"\"\n"), "");
+    }
+
+    #[test]
+    fn test_unknown_escape() {
+        let scanner = Scanner::new(b"\"a\\qb\"\n".to_vec());
+        assert!(scanner.next().is_err());
+
+        // scanning carries on after the string rather than inside it
+        let scanner = Scanner::new(b"\"a\\q\nb\" x\n".to_vec());
+        assert!(scanner.next().is_err());
+        let token = scanner.next().unwrap();
+        assert_eq!(token.token_type, TokenType::IDENTIFIER);
+        assert_eq!(token.line, 2);
+    }
+
+    #[test]
+    fn test_multi_line_string_lines() {
+        let scanner = Scanner::new(b"\"one\ntwo\nthree\" x\ny\n".to_vec());
+        // the string ends on the line of its closing quote
+        assert_eq!(scanner.next().unwrap().line, 3);
+        assert_eq!(scanner.next().unwrap().line, 3);
+        assert_eq!(scanner.next().unwrap().line, 4);
+
+        let scanner = Scanner::new(b"x;\n\"never\nclosed\n".to_vec());
+        scanner.next().unwrap();
+        scanner.next().unwrap();
+        let err = scanner.next().unwrap_err();
+        assert_eq!(err.position().unwrap().0, 2);
+        assert!(format!("{}", err).contains("Line 2: \"never\n"));
+    }
+
+    #[test]
+    fn test_lone_slash() {
+        let tokens = scan_all("6 / 2\n");
+        assert_eq!(
+            tokens[0..3],
+            [
+                (TokenType::NUMBER, "6".to_string()),
+                (TokenType::SLASH, "/".to_string()),
+                (TokenType::NUMBER, "2".to_string())
+            ]
+        );
+        assert_eq!(tokens[3].0, TokenType::EOF);
+    }
+
+    #[test]
+    fn test_block_comments() {
+        let tokens = scan_all("1 /* one line */ 2\n");
+        assert_eq!(
+            tokens[0..2],
+            [
+                (TokenType::NUMBER, "1".to_string()),
+                (TokenType::NUMBER, "2".to_string())
+            ]
+        );
+
+        let scanner = Scanner::new(b"/* spans\ntwo lines */ x\n".to_vec());
+        let token = scanner.next().unwrap();
+        assert_eq!(token.token_type, TokenType::IDENTIFIER);
+        assert_eq!(token.line, 2);
+
+        let tokens = scan_all("/* a /* b */ c */ x\n");
+        assert_eq!(tokens[0], (TokenType::IDENTIFIER, "x".to_string()));
+    }
+
+    #[test]
+    fn test_empty_input() {
+        for src in [
+            "",
+            "  \t\n\n",
+            "// only a comment",
+            "/* block */\n// line\n",
+        ] {
+            let tokens = scan_all(src);
+            assert_eq!(tokens.len(), 1, "{:?}", src);
+            assert_eq!(tokens[0].0, TokenType::EOF, "{:?}", src);
+        }
+        // the final character is kept without a closing newline
+        assert_eq!(scan_all("x")[0], (TokenType::IDENTIFIER, "x".to_string()));
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let scanner = Scanner::new(b"var x;\n/* never\nclosed\n".to_vec());
+        for _ in 0..3 {
+            scanner.next().unwrap();
+        }
+        let err = scanner.next().unwrap_err();
+        assert!(format!("{}", err).contains("Line 2"));
+        assert!(format!("{}", err).contains("Unterminated block comment"));
+    }
+}
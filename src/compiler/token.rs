@@ -8,6 +8,8 @@ pub enum TokenType {
     RIGHT_PAREN,
     LEFT_BRACE,
     RIGHT_BRACE,
+    LEFT_BRACKET,
+    RIGHT_BRACKET,
     COMMA,
     DOT,
     MINUS,
@@ -15,10 +17,19 @@ pub enum TokenType {
     SEMICOLON,
     SLASH,
     STAR,
+    PERCENT,
+    QUESTION,
+    COLON,
 
     // One or two character tokens.
     BANG,
     BANG_EQUAL,
+    STAR_STAR,
+    TILDE_SLASH,
+    PLUS_EQUAL,
+    MINUS_EQUAL,
+    STAR_EQUAL,
+    SLASH_EQUAL,
     EQUAL,
     EQUAL_EQUAL,
     GREATER,
@@ -33,18 +44,27 @@ pub enum TokenType {
 
     // Keywords.
     AND,
+    BREAK,
+    CASE,
+    CONTINUE,
     CLASS,
     CONST,
+    DEFAULT,
+    DO,
     ELSE,
     FALSE,
     FUN,
     FOR,
     IF,
+    IS,
+    LAZY,
     NIL,
     OR,
+    PASS,
     PRINT,
     RETURN,
     SUPER,
+    SWITCH,
     THIS,
     TRUE,
     VAR,
@@ -60,6 +80,8 @@ impl Display for TokenType {
             TokenType::RIGHT_PAREN => write!(f, "{}", ")"),
             TokenType::LEFT_BRACE => write!(f, "{}", "{"),
             TokenType::RIGHT_BRACE => write!(f, "{}", "}"),
+            TokenType::LEFT_BRACKET => write!(f, "{}", "["),
+            TokenType::RIGHT_BRACKET => write!(f, "{}", "]"),
             TokenType::COMMA => write!(f, "{}", ","),
             TokenType::DOT => write!(f, "{}", "."),
             TokenType::MINUS => write!(f, "{}", "-"),
@@ -67,6 +89,15 @@ impl Display for TokenType {
             TokenType::SEMICOLON => write!(f, "{}", ";"),
             TokenType::SLASH => write!(f, "{}", "/"),
             TokenType::STAR => write!(f, "{}", "*"),
+            TokenType::STAR_STAR => write!(f, "{}", "**"),
+            TokenType::TILDE_SLASH => write!(f, "{}", "~/"),
+            TokenType::PLUS_EQUAL => write!(f, "{}", "+="),
+            TokenType::MINUS_EQUAL => write!(f, "{}", "-="),
+            TokenType::STAR_EQUAL => write!(f, "{}", "*="),
+            TokenType::SLASH_EQUAL => write!(f, "{}", "/="),
+            TokenType::PERCENT => write!(f, "{}", "%"),
+            TokenType::QUESTION => write!(f, "{}", "?"),
+            TokenType::COLON => write!(f, "{}", ":"),
 
             // One or two character tokens.
             TokenType::BANG => write!(f, "{}", "!"),
@@ -85,15 +116,24 @@ impl Display for TokenType {
 
             // Keywords.
             TokenType::AND => write!(f, "{}", "and"),
+            TokenType::BREAK => write!(f, "{}", "break"),
+            TokenType::CONTINUE => write!(f, "{}", "continue"),
             TokenType::CLASS => write!(f, "{}", "class"),
             TokenType::CONST => write!(f, "{}", "const"),
+            TokenType::CASE => write!(f, "{}", "case"),
+            TokenType::DEFAULT => write!(f, "{}", "default"),
+            TokenType::DO => write!(f, "{}", "do"),
+            TokenType::SWITCH => write!(f, "{}", "switch"),
             TokenType::ELSE => write!(f, "{}", "else"),
             TokenType::FALSE => write!(f, "{}", "false"),
             TokenType::FUN => write!(f, "{}", "fun"),
             TokenType::FOR => write!(f, "{}", "for"),
             TokenType::IF => write!(f, "{}", "if"),
+            TokenType::IS => write!(f, "{}", "is"),
+            TokenType::LAZY => write!(f, "{}", "lazy"),
             TokenType::NIL => write!(f, "{}", "nil"),
             TokenType::OR => write!(f, "{}", "or"),
+            TokenType::PASS => write!(f, "{}", "pass"),
             TokenType::PRINT => write!(f, "{}", "print"),
             TokenType::RETURN => write!(f, "{}", "return"),
             TokenType::SUPER => write!(f, "{}", "super"),
@@ -112,14 +152,17 @@ pub struct Token<'a> {
     pub(super) token_type: TokenType,
     pub literal: &'a [u8],
     pub line: u32,
+    // 1 based column of the token's first character
+    pub column: u32,
 }
 
 impl<'a> Token<'a> {
-    pub fn new(token_type: TokenType, literal: &'a [u8], line: u32) -> Self {
+    pub fn new(token_type: TokenType, literal: &'a [u8], line: u32, column: u32) -> Self {
         Token {
             token_type,
             literal,
             line,
+            column,
         }
     }
 }
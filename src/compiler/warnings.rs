@@ -0,0 +1,18 @@
+//! Warnings from compiling and running. They're printed as they're found and also kept
+//! per thread so an embedder (or a test) can collect them afterwards.
+
+use std::cell::RefCell;
+
+thread_local! {
+    static WARNINGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+pub fn warn(message: String) {
+    eprintln!("Warning:: {}", message);
+    WARNINGS.with(|warnings| warnings.borrow_mut().push(message));
+}
+
+/// Drains the warnings raised on this thread so far
+pub fn take_warnings() -> Vec<String> {
+    WARNINGS.with(|warnings| warnings.take())
+}
@@ -1,8 +1,131 @@
-use std::fmt::{Debug, Display};
+use std::{
+    fmt::{Debug, Display},
+    str::FromStr,
+};
+
+use crate::vm::config::with_config;
 
 pub trait ErrTraitBase {
     fn raise(&self);
+
+    /// `(line, column, message)` for errors that point at a spot in the
+    /// source, runtime errors only know the line
+    fn position(&self) -> Option<(usize, Option<usize>, String)> {
+        None
+    }
+
+    /// The errors collected into this one, for errors that group others
+    fn grouped(&self) -> Option<&[Box<dyn ErrTrait>]> {
+        None
+    }
+
+    /// Records the line of the instruction that was running when the error
+    /// was raised, errors that don't track a line ignore it
+    fn at_line(&mut self, _line: usize) {}
 }
 
 pub trait ErrTrait: ErrTraitBase + Debug + Display {}
 impl<T> ErrTrait for T where T: ErrTraitBase + Debug + Display {}
+
+/// How errors get written out, `pretty` is the caret display meant for a
+/// terminal, `plain` is one line per error and `json` one object per error
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ErrorFormat {
+    Plain,
+    #[default]
+    Pretty,
+    Json,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(ErrorFormat::Plain),
+            "pretty" => Ok(ErrorFormat::Pretty),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(format!(
+                "unknown error format `{}`, expected plain, pretty or json",
+                s
+            )),
+        }
+    }
+}
+
+impl dyn ErrTrait {
+    pub fn formatted(&self, format: ErrorFormat, file: &str) -> String {
+        // each error of a group goes on its own line
+        if let (Some(errs), false) = (self.grouped(), format == ErrorFormat::Pretty) {
+            return errs
+                .iter()
+                .map(|err| err.formatted(format, file))
+                .collect::<Vec<String>>()
+                .join("\n");
+        }
+        let (line, column, message) = match self.position() {
+            Some((line, column, message)) => (Some(line), column, message),
+            // no position to point at, squash the display onto one line
+            None => (
+                None,
+                None,
+                format!("{}", self)
+                    .split_whitespace()
+                    .collect::<Vec<&str>>()
+                    .join(" "),
+            ),
+        };
+        match format {
+            ErrorFormat::Pretty => format!("{}", self),
+            ErrorFormat::Plain => match (line, column) {
+                (Some(line), Some(column)) => {
+                    format!("{}:{}:{}: {}", file, line, column, message)
+                }
+                (Some(line), None) => format!("{}:{}: {}", file, line, message),
+                _ => format!("{}: {}", file, message),
+            },
+            ErrorFormat::Json => {
+                let number = |n: Option<usize>| match n {
+                    Some(n) => n.to_string(),
+                    None => "null".to_string(),
+                };
+                format!(
+                    "{{\"file\": {}, \"line\": {}, \"column\": {}, \"message\": {}}}",
+                    json_string(file),
+                    number(line),
+                    number(column),
+                    json_string(&message)
+                )
+            }
+        }
+    }
+
+    /// Writes the error out in the format picked with `--error-format`, the
+    /// plain and json formats go to stderr so they can be read apart from
+    /// the script's output
+    pub fn report(&self) {
+        let (format, file) = with_config(|config| (config.error_format, config.source_name()));
+        match format {
+            ErrorFormat::Pretty => self.raise(),
+            _ => eprintln!("{}", self.formatted(format, &file)),
+        }
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
@@ -5,7 +5,14 @@ use std::{
 };
 
 use crate::{
-    compiler::compiler::UpValue, errors::err::ErrTrait, values::values::Value, vm::table::Table,
+    compiler::{compiler::UpValue, warnings::warn},
+    errors::err::ErrTrait,
+    values::{
+        func::Frame,
+        intern::intern,
+        values::{unsafe_int, Value},
+    },
+    vm::{config::with_config, stack::Stack, table::Table},
 };
 
 use super::{
@@ -19,9 +26,14 @@ pub enum BinaryOp {
     SUBTRACT,
     MULTIPLY,
     DIVIDE,
+    // `~/`, division rounded down
+    QUOTIENT,
+    MODULO,
+    POW,
     EQUAL,
     GREATER,
     LESS,
+    IS,
 }
 
 pub struct Binary {
@@ -37,12 +49,45 @@ impl Binary {
         }
     }
 
+    /// The operator as it's written in lox
+    fn symbol(&self) -> &'static str {
+        match self.op {
+            BinaryOp::ADD => "+",
+            BinaryOp::DIVIDE => "/",
+            BinaryOp::QUOTIENT => "~/",
+            BinaryOp::MODULO => "%",
+            BinaryOp::POW => "**",
+            BinaryOp::MULTIPLY => "*",
+            BinaryOp::SUBTRACT => "-",
+            BinaryOp::EQUAL => "==",
+            BinaryOp::GREATER => ">",
+            BinaryOp::LESS => "<",
+            BinaryOp::IS => "is",
+        }
+    }
+
+    fn check_string_length(&self, res: String) -> Result<Value, Box<dyn ErrTrait>> {
+        match with_config(|config| config.max_string_length) {
+            Some(max) if res.len() > max => Err(Box::new(InstructionErr::new(
+                format!(
+                    "{:?} produced a string of length {} which exceeds the max string length of {}. \
+                     To build large strings collect the pieces in a list and join them instead",
+                    self.symbol(),
+                    res.len(),
+                    max
+                ),
+                format!("{:?}", self.symbol()),
+            ))),
+            _ => Ok(Value::String(intern(&res))),
+        }
+    }
+
     pub fn eval_add(&self, left: Value, right: Value) -> Result<Value, Box<dyn ErrTrait>> {
         let raise_type_err = || {
             Box::new(
                 InstructionErr::new(
-                    format!("{} can only be performed on 2 Numbers/Strings or used for string concatenation", self),
-                    format!("{}", self)
+                    format!("{:?} can only be performed on 2 Numbers/Strings or used for string concatenation", self.symbol()),
+                    format!("{:?}", self.symbol())
                 )
             )
         };
@@ -54,18 +99,18 @@ impl Binary {
                 }
                 Value::String(rval) => {
                     let res = format!("{}{}", lval, rval);
-                    return Ok(Value::String(res));
+                    return self.check_string_length(res);
                 }
                 _ => return Err(raise_type_err()),
             },
             Value::String(lval) => match right {
                 Value::Number(rval) => {
                     let res = format!("{}{}", lval, rval);
-                    return Ok(Value::String(res));
+                    return self.check_string_length(res);
                 }
                 Value::String(rval) => {
                     let res = format!("{}{}", lval, rval);
-                    return Ok(Value::String(res));
+                    return self.check_string_length(res);
                 }
                 _ => return Err(raise_type_err()),
             },
@@ -76,8 +121,8 @@ impl Binary {
     fn eval_subtract(&self, left: Value, right: Value) -> Result<Value, Box<dyn ErrTrait>> {
         let raise_type_err = || {
             Box::new(InstructionErr::new(
-                format!("{} can only be performed on 2 Numbers", self),
-                format!("{}", self),
+                format!("{:?} can only be performed on 2 Numbers", self.symbol()),
+                format!("{:?}", self.symbol()),
             ))
         };
         match left {
@@ -95,8 +140,8 @@ impl Binary {
     fn eval_multiply(&self, left: Value, right: Value) -> Result<Value, Box<dyn ErrTrait>> {
         let raise_type_err = || {
             Box::new(InstructionErr::new(
-                format!("{} can only be performed on 2 Numbers", self),
-                format!("{}", self),
+                format!("{:?} can only be performed on 2 Numbers", self.symbol()),
+                format!("{:?}", self.symbol()),
             ))
         };
         match left {
@@ -114,8 +159,8 @@ impl Binary {
     fn eval_divide(&self, left: Value, right: Value) -> Result<Value, Box<dyn ErrTrait>> {
         let raise_type_err = || {
             Box::new(InstructionErr::new(
-                format!("{} can only be performed on 2 Numbers", self),
-                format!("{}", self),
+                format!("{:?} can only be performed on 2 Numbers", self.symbol()),
+                format!("{:?}", self.symbol()),
             ))
         };
         match left {
@@ -130,11 +175,56 @@ impl Binary {
         }
     }
 
+    /// Division rounded down, so `-7 ~/ 2` is `-4`
+    fn eval_floor_divide(&self, left: Value, right: Value) -> Result<Value, Box<dyn ErrTrait>> {
+        match (left, right) {
+            (Value::Number(_), Value::Number(0.0)) => Err(Box::new(InstructionErr::new(
+                format!("{:?} by zero", self.symbol()),
+                format!("{:?}", self.symbol()),
+            ))),
+            (Value::Number(lval), Value::Number(rval)) => Ok(Value::Number((lval / rval).floor())),
+            _ => Err(Box::new(InstructionErr::new(
+                format!("{:?} can only be performed on 2 Numbers", self.symbol()),
+                format!("{:?}", self.symbol()),
+            ))),
+        }
+    }
+
+    /// Remainder with the sign of the dividend, the same as `%` on floats
+    fn eval_modulo(&self, left: Value, right: Value) -> Result<Value, Box<dyn ErrTrait>> {
+        match (left, right) {
+            (Value::Number(_), Value::Number(rval)) if rval == 0.0 => {
+                Err(Box::new(InstructionErr::new(
+                    format!("{:?} by zero", self.symbol()),
+                    format!("{:?}", self.symbol()),
+                )))
+            }
+            (Value::Number(lval), Value::Number(rval)) => Ok(Value::Number(lval % rval)),
+            _ => Err(Box::new(InstructionErr::new(
+                format!("{:?} can only be performed on 2 Numbers", self.symbol()),
+                format!("{:?}", self.symbol()),
+            ))),
+        }
+    }
+
+    fn eval_pow(&self, left: Value, right: Value) -> Result<Value, Box<dyn ErrTrait>> {
+        match (left, right) {
+            (Value::Number(lval), Value::Number(rval)) => Ok(Value::Number(lval.powf(rval))),
+            _ => Err(Box::new(InstructionErr::new(
+                format!("{:?} can only be performed on 2 Numbers", self.symbol()),
+                format!("{:?}", self.symbol()),
+            ))),
+        }
+    }
+
     fn eval_greater(&self, left: Value, right: Value) -> Result<Value, Box<dyn ErrTrait>> {
         let raise_type_err = || {
             Box::new(InstructionErr::new(
-                format!("{} can only be performed on 2 Numbers", self),
-                format!("{}", self),
+                format!(
+                    "{:?} can only be performed on 2 Numbers or 2 Strings",
+                    self.symbol()
+                ),
+                format!("{:?}", self.symbol()),
             ))
         };
         match left {
@@ -145,6 +235,11 @@ impl Binary {
                 }
                 _ => return Err(raise_type_err()),
             },
+            // strings compare lexicographically
+            Value::String(lval) => match right {
+                Value::String(rval) => Ok(Value::Bool(lval > rval)),
+                _ => Err(raise_type_err()),
+            },
             _ => return Err(raise_type_err()),
         }
     }
@@ -152,8 +247,11 @@ impl Binary {
     fn eval_less(&self, left: Value, right: Value) -> Result<Value, Box<dyn ErrTrait>> {
         let raise_type_err = || {
             Box::new(InstructionErr::new(
-                format!("{} can only be performed on 2 Numbers", self),
-                format!("{}", self),
+                format!(
+                    "{:?} can only be performed on 2 Numbers or 2 Strings",
+                    self.symbol()
+                ),
+                format!("{:?}", self.symbol()),
             ))
         };
         match left {
@@ -164,33 +262,74 @@ impl Binary {
                 }
                 _ => return Err(raise_type_err()),
             },
+            // strings compare lexicographically
+            Value::String(lval) => match right {
+                Value::String(rval) => Ok(Value::Bool(lval < rval)),
+                _ => Err(raise_type_err()),
+            },
             _ => return Err(raise_type_err()),
         }
     }
+
+    /// Instances match their own class and every class it inherits from,
+    /// anything that isn't an instance never matches
+    fn eval_is(&self, left: Value, right: Value) -> Result<Value, Box<dyn ErrTrait>> {
+        match (left, right) {
+            (Value::Instance(instance), Value::Class(class)) => {
+                Ok(Value::Bool(instance.class().is_a(&class)))
+            }
+            (_, Value::Class(_)) => Ok(Value::Bool(false)),
+            (_, right) => Err(Box::new(InstructionErr::new(
+                format!(
+                    "{:?} expects a class on the right, not {}",
+                    self.symbol(),
+                    right
+                ),
+                format!("{:?}", self.symbol()),
+            ))),
+        }
+    }
 }
 
 impl InstructionBase for Binary {
     fn eval(
         &self,
-        stack: Rc<RefCell<Vec<Value>>>,
-        _: Rc<RefCell<Table>>,
-        _: Rc<RefCell<Vec<String>>>,
+        stack: &Rc<RefCell<Stack>>,
+        _: &Rc<RefCell<Table>>,
+        _: &Rc<RefCell<Vec<Frame>>>,
         _: usize,
-        _: Rc<RefCell<Vec<UpValue>>>,
+        _: &Rc<RefCell<Vec<UpValue>>>,
         _: usize,
         _: usize,
-    ) -> Result<usize, Box<dyn ErrTrait>> {
+    ) -> Result<isize, Box<dyn ErrTrait>> {
         let right = stack.borrow_mut().pop().unwrap();
         let left = stack.borrow_mut().pop().unwrap();
+        // only the operation that first leaves the safe range warns
+        let operands_safe = ![&left, &right]
+            .iter()
+            .any(|val| matches!(val, Value::Number(n) if unsafe_int(*n)));
         let res = match self.op {
             BinaryOp::ADD => self.eval_add(left, right)?,
             BinaryOp::SUBTRACT => self.eval_subtract(left, right)?,
             BinaryOp::MULTIPLY => self.eval_multiply(left, right)?,
             BinaryOp::DIVIDE => self.eval_divide(left, right)?,
+            BinaryOp::QUOTIENT => self.eval_floor_divide(left, right)?,
+            BinaryOp::MODULO => self.eval_modulo(left, right)?,
+            BinaryOp::POW => self.eval_pow(left, right)?,
             BinaryOp::EQUAL => Value::Bool(left == right),
             BinaryOp::GREATER => self.eval_greater(left, right)?,
             BinaryOp::LESS => self.eval_less(left, right)?,
+            BinaryOp::IS => self.eval_is(left, right)?,
         };
+        if let Value::Number(n) = res {
+            if operands_safe && unsafe_int(n) && with_config(|config| config.warn_unsafe_int) {
+                warn(format!(
+                    "{:?} produced {} which is past 2^53, it may not be exact",
+                    self.symbol(),
+                    n
+                ));
+            }
+        }
         stack.borrow_mut().push(res.clone());
         Ok(0)
     }
@@ -202,16 +341,7 @@ impl InstructionBase for Binary {
 
 impl Display for Binary {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let op_str = match self.op {
-            BinaryOp::ADD => "+",
-            BinaryOp::DIVIDE => "/",
-            BinaryOp::MULTIPLY => "*",
-            BinaryOp::SUBTRACT => "-",
-            BinaryOp::EQUAL => "==",
-            BinaryOp::GREATER => ">",
-            BinaryOp::LESS => "<",
-        };
-        write!(f, "{:?}", op_str)
+        write!(f, "{:?}       {:?}", self.code, self.symbol())
     }
 }
 
@@ -220,3 +350,178 @@ impl Debug for Binary {
         write!(f, "{:?}", self.op)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::{config::set_config, config::Config, vm::VM};
+
+    use super::*;
+
+    #[test]
+    fn test_max_string_length() {
+        set_config(Config {
+            max_string_length: Some(5),
+            ..Config::default()
+        });
+        let add = Binary::new(BinaryOp::ADD);
+        assert!(add
+            .eval_add(Value::String("ab".into()), Value::String("cde".into()))
+            .is_ok());
+        assert!(add
+            .eval_add(Value::String("abc".into()), Value::Number(123.0))
+            .is_err());
+
+        let globals = Rc::new(RefCell::new(Table::new()));
+        let func = VM::compile(
+            b"var s = \"x\";\nwhile (true) { s = s + \"x\"; }\n".to_vec(),
+            globals.clone(),
+        )
+        .unwrap();
+        let err = func
+            .call(
+                Rc::new(RefCell::new(Stack::new())),
+                globals.clone(),
+                Rc::new(RefCell::new(Vec::new())),
+                0,
+            )
+            .unwrap_err();
+        assert!(format!("{}", err).contains("exceeds the max string length of 5"));
+        assert_eq!(
+            (*globals).borrow().resolve(&"s".into()),
+            Some(Value::String("xxxxx".into()))
+        );
+        set_config(Config::default());
+    }
+
+    #[test]
+    fn test_modulo() {
+        let modulo = Binary::new(BinaryOp::MODULO);
+        let eval =
+            |left: f64, right: f64| modulo.eval_modulo(Value::Number(left), Value::Number(right));
+        assert_eq!(eval(5.0, 3.0).unwrap(), Value::Number(2.0));
+        assert_eq!(eval(5.5, 2.0).unwrap(), Value::Number(1.5));
+        assert_eq!(eval(-5.0, 3.0).unwrap(), Value::Number(-2.0));
+        assert!(eval(5.0, 0.0).is_err());
+        assert!(modulo
+            .eval_modulo(Value::String("5".into()), Value::Number(3.0))
+            .is_err());
+
+        let globals = Rc::new(RefCell::new(Table::new()));
+        let func = VM::compile(b"var r = 1 + 7 % 4;\n".to_vec(), globals.clone()).unwrap();
+        VM::new(&func, globals.clone()).run().unwrap();
+        assert_eq!(
+            (*globals).borrow().resolve(&"r".into()),
+            Some(Value::Number(4.0))
+        );
+    }
+
+    #[test]
+    fn test_floor_divide() {
+        let globals = Rc::new(RefCell::new(Table::new()));
+        let src = b"var whole = 6 ~/ 2;\nvar half = 7 ~/ 2;\nvar negative = -7 ~/ 2;\nvar fraction = 7.5 ~/ 2.5;\nvar mixed = 1 + 9 ~/ 4 * 2; // 1 + ((9 ~/ 4) * 2)\n";
+        let func = VM::compile(src.to_vec(), globals.clone()).unwrap();
+        VM::new(&func, globals.clone()).run().unwrap();
+        for (name, expected) in [
+            ("whole", 3.0),
+            ("half", 3.0),
+            ("negative", -4.0),
+            ("fraction", 3.0),
+            ("mixed", 5.0),
+        ] {
+            assert_eq!(
+                (*globals).borrow().resolve(&name.into()),
+                Some(Value::Number(expected)),
+                "{}",
+                name
+            );
+        }
+
+        // a zero divisor errors the same way `%` does
+        let func = VM::compile(b"var q = 7 ~/ 0;\n".to_vec(), globals.clone()).unwrap();
+        let err = VM::new(&func, globals.clone()).run().unwrap_err();
+        assert!(format!("{}", err).contains("\"~/\" by zero"), "{}", err);
+
+        let floor_divide = Binary::new(BinaryOp::QUOTIENT);
+        assert!(floor_divide
+            .eval_floor_divide(Value::String("7".into()), Value::Number(2.0))
+            .is_err());
+    }
+
+    #[test]
+    fn test_pow() {
+        let globals = Rc::new(RefCell::new(Table::new()));
+        let src = b"var kilo = 2 ** 10;\nvar right = 2 ** 3 ** 2;\nvar negated = -2 ** 2;\nvar mixed = 2 * 3 ** 2;\n";
+        let func = VM::compile(src.to_vec(), globals.clone()).unwrap();
+        VM::new(&func, globals.clone()).run().unwrap();
+        for (name, expected) in [
+            ("kilo", 1024.0),
+            ("right", 512.0),
+            ("negated", -4.0),
+            ("mixed", 18.0),
+        ] {
+            assert_eq!(
+                (*globals).borrow().resolve(&name.into()),
+                Some(Value::Number(expected)),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_string_comparison() {
+        let globals = Rc::new(RefCell::new(Table::new()));
+        let src = b"var less = \"abc\" < \"abd\";\nvar greater = \"b\" > \"abc\";\nvar prefix = \"ab\" < \"abc\";\nvar equal_less = \"abc\" < \"abc\";\nvar equal_at_most = \"abc\" <= \"abc\";\nvar equal_at_least = \"abc\" >= \"abc\";\nvar not_greater = \"abc\" >= \"abd\";\n";
+        let func = VM::compile(src.to_vec(), globals.clone()).unwrap();
+        VM::new(&func, globals.clone()).run().unwrap();
+        for (name, expected) in [
+            ("less", true),
+            ("greater", true),
+            ("prefix", true),
+            ("equal_less", false),
+            ("equal_at_most", true),
+            ("equal_at_least", true),
+            ("not_greater", false),
+        ] {
+            assert_eq!(
+                (*globals).borrow().resolve(&name.into()),
+                Some(Value::Bool(expected)),
+                "{}",
+                name
+            );
+        }
+
+        let binary = Binary::new(BinaryOp::LESS);
+        assert!(binary
+            .eval_less(Value::String("1".into()), Value::Number(2.0))
+            .is_err());
+        assert!(binary.eval_less(Value::Nil, Value::Nil).is_err());
+    }
+
+    #[test]
+    fn test_is() {
+        let globals = Rc::new(RefCell::new(Table::new()));
+        let src = b"class Animal {}\nclass Dog < Animal {}\nclass Puppy < Dog {}\nclass Rock {}\nvar dog = Dog();\nvar direct = dog is Dog;\nvar parent = dog is Animal;\nvar grandparent = Puppy() is Animal;\nvar child = Animal() is Dog;\nvar other = dog is Rock;\nvar number = 1 is Animal;\nvar negated = !(dog is Rock);\n";
+        let func = VM::compile(src.to_vec(), globals.clone()).unwrap();
+        VM::new(&func, globals.clone()).run().unwrap();
+        for (name, expected) in [
+            ("direct", true),
+            ("parent", true),
+            ("grandparent", true),
+            ("child", false),
+            ("other", false),
+            ("number", false),
+            ("negated", true),
+        ] {
+            assert_eq!(
+                (*globals).borrow().resolve(&name.into()),
+                Some(Value::Bool(expected)),
+                "{}",
+                name
+            );
+        }
+
+        let binary = Binary::new(BinaryOp::IS);
+        assert!(binary.eval_is(Value::Nil, Value::Number(1.0)).is_err());
+    }
+}
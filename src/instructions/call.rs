@@ -7,8 +7,8 @@ use std::{
 use crate::{
     compiler::compiler::UpValue,
     instructions::err::InstructionErr,
-    values::{func::Method, obj::Instance, values::Value},
-    vm::table::Table,
+    values::{func::Frame, func::Method, obj::Instance, values::Value},
+    vm::{stack::Stack, table::Table},
 };
 
 use super::instructions::{InstructionBase, InstructionType};
@@ -31,6 +31,33 @@ impl Call {
     }
 }
 
+impl Call {
+    /// Calls a lox callable from native code with the given arguments,
+    /// laying out the stack the same way compiled code would for `OP_CALL`
+    pub fn invoke(
+        callee: Value,
+        args: Vec<Value>,
+        stack: Rc<RefCell<Stack>>,
+        env: Rc<RefCell<Table>>,
+        call_frame: Rc<RefCell<Vec<Frame>>>,
+    ) -> Result<Value, Box<dyn crate::errors::err::ErrTrait>> {
+        let args_len = args.len();
+        let line_contents = format!("{}(...)", callee);
+        (*stack).borrow_mut().push(callee);
+        (*stack).borrow_mut().extend(args);
+        Call::new(args_len, 0, line_contents).eval(
+            &stack,
+            &env,
+            &call_frame,
+            0,
+            &Rc::new(RefCell::new(Vec::new())),
+            0,
+            0,
+        )?;
+        Ok((*stack).borrow_mut().pop().unwrap_or(Value::Nil))
+    }
+}
+
 impl InstructionBase for Call {
     fn disassemble(&self) -> InstructionType {
         self.code.clone()
@@ -38,14 +65,14 @@ impl InstructionBase for Call {
 
     fn eval(
         &self,
-        stack: Rc<RefCell<Vec<Value>>>,
-        env: Rc<RefCell<Table>>,
-        call_frame: Rc<RefCell<Vec<String>>>,
+        stack: &Rc<RefCell<Stack>>,
+        env: &Rc<RefCell<Table>>,
+        call_frame: &Rc<RefCell<Vec<Frame>>>,
         _: usize,
-        _: Rc<RefCell<Vec<UpValue>>>,
+        _: &Rc<RefCell<Vec<UpValue>>>,
         _: usize,
         _: usize,
-    ) -> Result<usize, Box<dyn crate::errors::err::ErrTrait>> {
+    ) -> Result<isize, Box<dyn crate::errors::err::ErrTrait>> {
         let func_pos = (*stack)
             .borrow()
             .len()
@@ -69,11 +96,34 @@ Line {}: {}
                     )));
                 }
                 let offset = (*stack).borrow().len().saturating_sub(self.args_len);
-                let val = func.call(stack.clone(), env, call_frame, offset)?;
+                let val = func.call(stack.clone(), env.clone(), call_frame.clone(), offset)?;
                 (*stack).borrow_mut().push(val);
             }
             Value::Native(func) => {
-                let arity = (*func).arity();
+                if !func.accepts(self.args_len) {
+                    return Err(Box::new(InstructionErr::new(
+                        format!(
+                            "
+Line {}: {}
+         ^
+         -------- Expected {} argument for {} found {}
+",
+                            self.line,
+                            self.line_contents,
+                            func.arity_text(),
+                            func,
+                            self.args_len
+                        ),
+                        format!("{}(...)", func.name()),
+                    )));
+                }
+                let args = (*stack).borrow_mut().split_off(func_pos);
+                let val = func.call(&args, stack.clone(), env.clone(), call_frame.clone())?;
+                (*stack).borrow_mut().push(val);
+            }
+            Value::NativeMethod(func, receiver) => {
+                // the receiver is passed to the native as its first argument
+                let arity = (*func).arity() - 1;
                 if arity != self.args_len {
                     return Err(Box::new(InstructionErr::new(
                         format!(
@@ -87,7 +137,10 @@ Line {}: {}
                         format!("{}(...)", func.name()),
                     )));
                 }
-                func.call(stack.clone())?;
+                let mut args = vec![*receiver];
+                args.extend((*stack).borrow_mut().split_off(func_pos));
+                let val = func.call(&args, stack.clone(), env.clone(), call_frame.clone())?;
+                (*stack).borrow_mut().push(val);
             }
             Value::Class(class) => {
                 match class.get_method("__init__".to_string()) {
@@ -108,10 +161,23 @@ Line {}: {}
                         }
                         let instance = Rc::new(Instance::new(class.clone()));
                         let offset = (*stack).borrow().len().saturating_sub(self.args_len);
-                        Method::new(method.clone(), instance.clone()).call(stack.clone(), env, call_frame, offset)?;
+                        Method::new(method.clone(), instance.clone()).call(stack.clone(), env.clone(), call_frame.clone(), offset)?;
                         (*stack).borrow_mut().push(Value::Instance(instance.clone()));
                     }
                     None => {
+                        if self.args_len != 0 {
+                            return Err(Box::new(InstructionErr::new(
+                                format!(
+"
+Line {}: {}
+          ^
+          -------- Expected 0 argument(s) for {} found {}, it has no initializer
+",
+                                    self.line, self.line_contents, class, self.args_len
+                                ),
+                                format!("{}(...)", class),
+                            )));
+                        }
                         let instance = Instance::new(class.clone());
                         (*stack).borrow_mut().push(Value::Instance(Rc::new(instance)));
                     }
@@ -133,7 +199,7 @@ Line {}: {}
                     )));
                 }
                 let offset = (*stack).borrow().len().saturating_sub(self.args_len);
-                let val = method.call(stack.clone(), env, call_frame, offset)?;
+                let val = method.call(stack.clone(), env.clone(), call_frame.clone(), offset)?;
                 (*stack).borrow_mut().push(val);
             }
             Value::ClassMethod(func) => {
@@ -152,7 +218,7 @@ Line {}: {}
                     )));
                 }
                 let offset = (*stack).borrow().len().saturating_sub(self.args_len).saturating_sub(1);
-                let val = func.call(stack.clone(), env, call_frame, offset)?;
+                let val = func.call(stack.clone(), env.clone(), call_frame.clone(), offset)?;
                 (*stack).borrow_mut().push(val);
             }
             _ => {
@@ -1,12 +1,54 @@
-use std::fmt::{Debug, Display};
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    fmt::{Debug, Display},
+    mem,
+    rc::Rc,
+    str::FromStr,
+};
 
-use crate::errors::err::ErrTrait;
+use crate::{
+    errors::err::ErrTrait,
+    values::values::Value,
+    vm::{config::with_config, stack::Stack, table::Table},
+};
 
-use super::{err::ChunkErr, instructions::Instruction};
+use super::{
+    constant::Constant,
+    err::ChunkErr,
+    instructions::{InstructionBase, InstructionType, None},
+    op::Op,
+};
+
+/// Which passes run over a chunk once it's compiled. `basic` cleans up
+/// after the codegen, `full` also folds constant arithmetic
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OptLevel {
+    Off,
+    #[default]
+    Basic,
+    Full,
+}
+
+impl FromStr for OptLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(OptLevel::Off),
+            "basic" => Ok(OptLevel::Basic),
+            "full" => Ok(OptLevel::Full),
+            _ => Err(format!(
+                "unknown optimization level `{}`, expected off, basic or full",
+                s
+            )),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Chunk {
-    pub code: Vec<Box<dyn Instruction>>,
+    pub code: Vec<Op>,
     pub count: usize,
     pub capacity: usize,
     pub lines: Vec<usize>,
@@ -24,32 +66,206 @@ impl Chunk {
 
     pub fn write_to_chunk(
         &mut self,
-        instruction: Box<dyn Instruction>,
+        instruction: impl Into<Op>,
         line: usize,
     ) -> Result<(), Box<dyn ErrTrait>> {
-        self.code.push(instruction);
+        if let Some(limit) = with_config(|config| config.max_chunk_size) {
+            if self.code.len() >= limit {
+                return Err(Box::new(ChunkErr::new(
+                    format!(
+                        "function is too large, it compiles to more than {} instructions",
+                        limit
+                    ),
+                    line,
+                )));
+            }
+        }
+        self.code.push(instruction.into());
         self.count += 1;
         self.capacity = self.code.capacity();
         self.lines.push(line);
         Ok(())
     }
 
-    pub fn swap_instructions(
-        &mut self,
-        origin: usize,
-        dest: usize,
-    ) -> Result<(), Box<dyn ErrTrait>> {
-        let limit = self.code.len() - 1;
-        if origin > limit || dest > limit {
-            return Err(Box::new(ChunkErr::new(
-                format!(
-                    "instruction swap failed for the bounds: {} < --- > {}.\nChunk dump: {}",
-                    origin, dest, self
-                ),
-                self.lines.pop().unwrap_or(0),
-            )));
-        }
-        self.code.swap(origin, dest);
+    /// Drops the last instruction written
+    pub fn pop_instruction(&mut self) {
+        if self.code.pop().is_some() {
+            self.lines.pop();
+            self.count -= 1;
+        }
+    }
+
+    /// Points the jump at `at` to the next instruction written
+    pub fn patch_jump(&mut self, at: usize) -> Result<(), Box<dyn ErrTrait>> {
+        self.patch_jump_to(at, self.code.len())
+    }
+
+    /// Points the jump at `at` to the instruction at `target`
+    pub fn patch_jump_to(&mut self, at: usize, target: usize) -> Result<(), Box<dyn ErrTrait>> {
+        match self.code.get_mut(at) {
+            Some(inst) if inst.jump_offset().is_some() => {
+                inst.set_jump_offset(target as isize - at as isize);
+                Ok(())
+            }
+            _ => Err(Box::new(ChunkErr::new(
+                format!("there's no jump at {} to patch.\nChunk dump: {}", at, self),
+                self.lines.get(at).copied().unwrap_or(0),
+            ))),
+        }
+    }
+
+    /// Where the jump at `idx` lands, `None` for anything that isn't a jump
+    fn jump_target(&self, idx: usize) -> Option<usize> {
+        self.code[idx]
+            .jump_offset()
+            .map(|offset| idx.saturating_add_signed(offset))
+    }
+
+    fn jump_targets(&self) -> HashSet<usize> {
+        (0..self.code.len())
+            .filter_map(|idx| self.jump_target(idx))
+            .collect()
+    }
+
+    /// Runs the passes `level` asks for
+    pub fn optimize(&mut self, level: OptLevel) {
+        match level {
+            OptLevel::Off => {}
+            OptLevel::Basic => self.peephole(),
+            OptLevel::Full => {
+                // folding leaves placeholders the peephole clears, which can
+                // line up more constants for the next round
+                while self.fold_constants() {
+                    self.peephole();
+                }
+                self.peephole();
+            }
+        }
+    }
+
+    /// Replaces a binary operation on two constants with the constant it
+    /// evaluates to, the two instructions it frees become `None`s. An
+    /// operation that would fail is left for the runtime to report
+    pub fn fold_constants(&mut self) -> bool {
+        let targets = self.jump_targets();
+
+        let mut folded = false;
+        let mut idx = 0;
+        while idx + 2 < self.code.len() {
+            let operands = match (self.code[idx].constant(), self.code[idx + 1].constant()) {
+                (Some(left), Some(right)) => Some((left.clone(), right.clone())),
+                _ => Option::None,
+            };
+            let foldable = self.code[idx + 2].disassemble() == InstructionType::OP_BINARY
+                && !targets.contains(&(idx + 1))
+                && !targets.contains(&(idx + 2));
+            if let (Some((left, right)), true) = (operands, foldable) {
+                let stack = Rc::new(RefCell::new(Stack::from(vec![left, right])));
+                let evaluated = self.code[idx + 2].eval(
+                    &stack,
+                    &Rc::new(RefCell::new(Table::new())),
+                    &Rc::new(RefCell::new(Vec::new())),
+                    0,
+                    &Rc::new(RefCell::new(Vec::new())),
+                    0,
+                    0,
+                );
+                let result = stack.borrow_mut().pop();
+                if let (Ok(_), Some(result)) = (evaluated, result) {
+                    self.code[idx] = Constant::new(result).into();
+                    self.code[idx + 1] = None::new().into();
+                    self.code[idx + 2] = None::new().into();
+                    folded = true;
+                    idx += 3;
+                    continue;
+                }
+            }
+            idx += 1;
+        }
+        folded
+    }
+
+    /// Drops the `None` placeholders constant folding leaves behind, `PopN`s
+    /// with nothing to pop and `nil` constants that get popped straight away,
+    /// then points every jump at whatever instruction ends up in its
+    /// target's place
+    pub fn peephole(&mut self) {
+        let len = self.code.len();
+        let targets = self.jump_targets();
+
+        let mut removed = vec![false; len];
+        let mut idx = 0;
+        while idx < len {
+            if self.code[idx].disassemble() == InstructionType::OP_NONE
+                || self.code[idx].pop_count() == Some(0)
+            {
+                removed[idx] = true;
+            } else if idx + 1 < len
+                && matches!(self.code[idx].constant(), Some(Value::Nil))
+                && self.code[idx + 1].disassemble() == InstructionType::OP_POP
+                && !targets.contains(&(idx + 1))
+            {
+                removed[idx] = true;
+                removed[idx + 1] = true;
+                idx += 1;
+            }
+            idx += 1;
+        }
+        if !removed.contains(&true) {
+            return;
+        }
+
+        // where each instruction lands once the removed ones are gone, a
+        // removed instruction maps to the next one that's kept and the end
+        // of the chunk stays the end
+        let mut new_index = Vec::with_capacity(len + 1);
+        let mut kept = 0;
+        for is_removed in removed.iter() {
+            new_index.push(kept);
+            if !is_removed {
+                kept += 1;
+            }
+        }
+        new_index.push(kept);
+
+        let code = mem::take(&mut self.code);
+        let lines = mem::take(&mut self.lines);
+        for (idx, ((mut inst, line), is_removed)) in
+            code.into_iter().zip(lines).zip(removed).enumerate()
+        {
+            if is_removed {
+                continue;
+            }
+            if let Some(offset) = inst.jump_offset() {
+                let to = idx.saturating_add_signed(offset).min(len);
+                inst.set_jump_offset(new_index[to] as isize - new_index[idx] as isize);
+            }
+            self.code.push(inst);
+            self.lines.push(line);
+        }
+        self.count = self.code.len();
+    }
+
+    /// Catches jumps patched to land outside the chunk before they get to
+    /// run, landing just past the last instruction ends the function
+    pub fn validate_jumps(&self) -> Result<(), Box<dyn ErrTrait>> {
+        for (idx, inst) in self.code.iter().enumerate() {
+            if let Some(offset) = inst.jump_offset() {
+                let to = idx as isize + offset;
+                if to < 0 || to > self.code.len() as isize {
+                    return Err(Box::new(ChunkErr::new(
+                        format!(
+                            "jump at {} targets {} outside of the chunk's {} instructions.\nChunk dump: {}",
+                            idx,
+                            to,
+                            self.code.len(),
+                            self
+                        ),
+                        self.lines[idx],
+                    )));
+                }
+            }
+        }
         Ok(())
     }
 }
@@ -57,10 +273,10 @@ impl Chunk {
 impl Display for Chunk {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut str = String::new();
-        let line = 1;
         let mut index = 0;
         for inst in &self.code {
-            if index != 0 && line == self.lines[index] {
+            // instructions on the same line as the one before are marked `|`
+            if index != 0 && self.lines[index - 1] == self.lines[index] {
                 str = str + &format!("{}  {}", "|", inst) + "\n";
             } else {
                 str = str + &format!("{}  {}", self.lines[index], inst) + "\n";
@@ -73,21 +289,245 @@ impl Display for Chunk {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
     use crate::{
-        instructions::{constant::Constant, return_inst::Return},
-        values::values::Value,
+        instructions::{
+            binary::{Binary, BinaryOp},
+            instructions::{Pop, PopN},
+            jump::{ForceJump, Jump},
+            return_inst::Return,
+        },
+        values::{func::Func, values::Value},
+        vm::{
+            config::{set_config, Config},
+            table::Table,
+            vm::VM,
+        },
     };
 
-    use super::*;
-
     #[test]
     fn test_chunk_display() {
         let mut chunk = Chunk::new();
         chunk
-            .write_to_chunk(Box::new(Constant::new(Value::Number(1.0))), 1)
+            .write_to_chunk(Constant::new(Value::Number(1.0)), 1)
             .unwrap();
-        chunk.write_to_chunk(Box::new(Return::new()), 1).unwrap();
+        chunk.write_to_chunk(Return::new(), 1).unwrap();
         assert_eq!(format!("{}", chunk), "1  OP_CONST       1\n|  OP_RETURN\n");
         print!("{}", chunk);
     }
+
+    #[test]
+    fn test_max_chunk_size() {
+        set_config(Config {
+            max_chunk_size: Some(64),
+            ..Config::default()
+        });
+        let src = "var x = 0;\n".to_string() + &"x = x + 1;\n".repeat(100);
+        let err = VM::compile(src.into_bytes(), VM::globals()).unwrap_err();
+        assert!(format!("{}", err).contains("more than 64 instructions"));
+        assert!(VM::compile(b"var x = 0;\nx = x + 1;\n".to_vec(), VM::globals()).is_ok());
+        set_config(Config::default());
+    }
+
+    #[test]
+    fn test_validate_jumps() {
+        let mut chunk = Chunk::new();
+        chunk.write_to_chunk(ForceJump::new(1), 1).unwrap();
+        chunk.write_to_chunk(Return::new(), 1).unwrap();
+        assert!(chunk.validate_jumps().is_ok());
+
+        chunk.write_to_chunk(ForceJump::new(7), 2).unwrap();
+        assert!(chunk.validate_jumps().is_err());
+    }
+
+    /// Picks 1 or 2 depending on `cond`, with the placeholders folding
+    /// leaves behind and a dead `nil;`
+    fn if_else_chunk(cond: bool) -> Chunk {
+        let mut chunk = Chunk::new();
+        let code: Vec<Op> = vec![
+            Constant::new(Value::Bool(cond)).into(),
+            Jump::new(5, true, 1, String::new()).into(),
+            Constant::new(Value::Nil).into(),
+            Pop::new().into(),
+            Constant::new(Value::Number(1.0)).into(),
+            ForceJump::new(3).into(),
+            None::new().into(),
+            Constant::new(Value::Number(2.0)).into(),
+            None::new().into(),
+            Constant::new(Value::Number(3.0)).into(),
+        ];
+        for inst in code {
+            chunk.write_to_chunk(inst, 1).unwrap();
+        }
+        chunk
+    }
+
+    fn run(chunk: Chunk) -> Vec<Value> {
+        let stack = Rc::new(RefCell::new(Stack::new()));
+        Func::new(
+            "script".to_string(),
+            chunk,
+            0,
+            0,
+            Rc::new(RefCell::new(Vec::new())),
+        )
+        .call(
+            stack.clone(),
+            Rc::new(RefCell::new(Table::new())),
+            Rc::new(RefCell::new(Vec::new())),
+            0,
+        )
+        .unwrap();
+        let result = stack.borrow().clone();
+        result
+    }
+
+    #[test]
+    fn test_peephole() {
+        for cond in [true, false] {
+            let unoptimized = if_else_chunk(cond);
+            let mut optimized = if_else_chunk(cond);
+            optimized.peephole();
+
+            assert_eq!(unoptimized.code.len(), 10);
+            assert_eq!(optimized.code.len(), 6);
+            assert!(optimized.validate_jumps().is_ok());
+            assert_eq!(run(optimized), run(unoptimized));
+        }
+
+        // a jump to a placeholder at the very end lands on the end instead
+        let mut chunk = Chunk::new();
+        chunk.write_to_chunk(ForceJump::new(2), 1).unwrap();
+        chunk.write_to_chunk(None::new(), 1).unwrap();
+        chunk.write_to_chunk(None::new(), 1).unwrap();
+        chunk.peephole();
+        assert_eq!(chunk.code.len(), 1);
+        assert_eq!(chunk.code[0].jump_offset(), Some(1));
+        assert!(chunk.validate_jumps().is_ok());
+
+        // a `PopN` with nothing to pop goes too, one that pops stays
+        let mut chunk = Chunk::new();
+        chunk.write_to_chunk(ForceJump::new(2), 1).unwrap();
+        chunk.write_to_chunk(PopN::new(0), 1).unwrap();
+        chunk.write_to_chunk(PopN::new(1), 1).unwrap();
+        chunk.peephole();
+        assert_eq!(chunk.code.len(), 2);
+        assert_eq!(chunk.code[0].jump_offset(), Some(1));
+        assert_eq!(chunk.code[1].pop_count(), Some(1));
+        assert!(chunk.validate_jumps().is_ok());
+    }
+
+    /// The offsets of every jump in the compiled script
+    fn jump_offsets(src: &str) -> (Vec<isize>, String) {
+        let func = VM::compile(src.as_bytes().to_vec(), VM::globals()).unwrap();
+        let offsets = func
+            .chunk
+            .code
+            .iter()
+            .filter_map(|inst| inst.jump_offset())
+            .collect();
+        let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+        VM::interprate_to(src.as_bytes().to_vec(), VM::globals(), output.clone()).unwrap();
+        let printed = String::from_utf8(output.borrow().clone()).unwrap();
+        (offsets, printed)
+    }
+
+    #[test]
+    fn test_relative_jumps() {
+        // `if`/`else`, `and`/`or` and `?:` only ever jump forward
+        let (offsets, printed) = jump_offsets(
+            "var x = 2;
+if (x > 1) { print \"big\"; } else { print \"small\"; }
+if (x > 3) { print \"bigger\"; } else { print \"smaller\"; }
+if (x < 1) { print \"never\"; }
+print x > 1 and x < 3;
+print x < 1 or x > 3;
+print x == 2 ? \"two\" : \"other\";
+",
+        );
+        assert!(!offsets.is_empty());
+        assert!(offsets.iter().all(|offset| *offset > 0));
+        assert_eq!(printed, "\"big\"\n\"smaller\"\ntrue\nfalse\n\"two\"\n");
+
+        // loops jump back to their condition
+        let (offsets, printed) = jump_offsets(
+            "var i = 0;
+while (i < 3) { print i; i = i + 1; }
+",
+        );
+        assert_eq!(offsets.iter().filter(|offset| **offset < 0).count(), 1);
+        assert_eq!(printed, "0\n1\n2\n");
+    }
+
+    #[test]
+    fn test_patch_jump() {
+        let mut chunk = Chunk::new();
+        chunk
+            .write_to_chunk(Jump::new(0, true, 1, String::new()), 1)
+            .unwrap();
+        chunk.write_to_chunk(Pop::new(), 1).unwrap();
+        chunk.write_to_chunk(Pop::new(), 1).unwrap();
+        chunk.patch_jump(0).unwrap();
+        assert_eq!(chunk.code[0].jump_offset(), Some(3));
+        chunk.patch_jump_to(0, 1).unwrap();
+        assert_eq!(chunk.code[0].jump_offset(), Some(1));
+        assert!(chunk.patch_jump(1).is_err());
+        assert!(chunk.patch_jump(7).is_err());
+    }
+
+    #[test]
+    fn test_optimization_levels() {
+        let src = "var total = 0;
+for (var i = 0; i < 4; i = i + 1) {
+    total = total + i * (2 + 3) - 10 / 4;
+}
+if (1 + 1 == 2) { print \"folded\"; } else { print \"wrong\"; }
+print total;
+print \"con\" + \"cat\";
+print 2 ** 3 ** 2;
+print 1 + nil == nil;
+";
+        let mut outputs = Vec::new();
+        for level in [OptLevel::Off, OptLevel::Basic, OptLevel::Full] {
+            set_config(Config {
+                ir_optimize: level,
+                ..Config::default()
+            });
+            let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+            let _ = VM::interprate_to(src.as_bytes().to_vec(), VM::globals(), output.clone());
+            let len = VM::compile(src.as_bytes().to_vec(), VM::globals())
+                .unwrap()
+                .chunk
+                .code
+                .len();
+            outputs.push((String::from_utf8(output.borrow().clone()).unwrap(), len));
+        }
+        set_config(Config::default());
+
+        assert_eq!(outputs[0].0, outputs[2].0);
+        assert_eq!(outputs[1].0, outputs[2].0);
+        assert!(outputs[0].0.starts_with("\"folded\"\n"));
+        // jumps no longer leave placeholders, so basic only has dead nils to drop
+        assert!(outputs[2].1 < outputs[1].1 && outputs[1].1 <= outputs[0].1);
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let mut chunk = Chunk::new();
+        chunk
+            .write_to_chunk(Constant::new(Value::Number(2.0)), 1)
+            .unwrap();
+        chunk
+            .write_to_chunk(Constant::new(Value::Number(3.0)), 1)
+            .unwrap();
+        chunk
+            .write_to_chunk(Binary::new(BinaryOp::MULTIPLY), 1)
+            .unwrap();
+        chunk.write_to_chunk(Constant::new(Value::Nil), 1).unwrap();
+        chunk.write_to_chunk(Binary::new(BinaryOp::ADD), 1).unwrap();
+        chunk.optimize(OptLevel::Full);
+        // 2 * 3 folds, adding nil is an error left for the runtime
+        assert_eq!(chunk.code.len(), 3);
+        assert_eq!(chunk.code[0].constant(), Some(&Value::Number(6.0)));
+    }
 }
@@ -0,0 +1,263 @@
+use std::{
+    cell::RefCell,
+    fmt::{Debug, Display},
+    rc::Rc,
+};
+
+use crate::{
+    compiler::compiler::UpValue,
+    errors::err::ErrTrait,
+    values::{func::Frame, values::Value},
+    vm::{stack::Stack, table::Table},
+};
+
+use super::{
+    define::DefinitionScope,
+    err::InstructionErr,
+    instructions::{InstructionBase, InstructionType},
+};
+
+/// Turns the function on top of the stack into a closure with its own
+/// upvalues. `captures` maps the function's upvalue slots either to a
+/// local of the running function or to one of its upvalues
+pub struct Closure {
+    code: InstructionType,
+    captures: Vec<(usize, DefinitionScope)>,
+}
+
+impl Closure {
+    pub fn new(captures: Vec<(usize, DefinitionScope)>) -> Self {
+        Closure {
+            code: InstructionType::OP_CLOSURE,
+            captures,
+        }
+    }
+}
+
+impl InstructionBase for Closure {
+    fn disassemble(&self) -> InstructionType {
+        self.code.clone()
+    }
+
+    fn eval(
+        &self,
+        stack: &Rc<RefCell<Stack>>,
+        _: &Rc<RefCell<Table>>,
+        _: &Rc<RefCell<Vec<Frame>>>,
+        offset: usize,
+        upvalue_stack: &Rc<RefCell<Vec<UpValue>>>,
+        _: usize,
+        _: usize,
+    ) -> Result<isize, Box<dyn ErrTrait>> {
+        let func = match (*stack).borrow_mut().pop() {
+            Some(Value::Func(func)) => func,
+            val => {
+                return Err(Box::new(InstructionErr::new(
+                    format!("Can only close over functions, not {:?}", val),
+                    format!("{}", self.code),
+                )))
+            }
+        };
+
+        let mut upvalues: Vec<UpValue> = (*upvalue_stack).borrow().clone();
+        for (slot, scope) in self.captures.iter() {
+            let cell = match scope {
+                DefinitionScope::Local(idx) => {
+                    (*stack).borrow_mut().capture(idx.saturating_add(offset))
+                }
+                DefinitionScope::UpValue(idx) => (*upvalue_stack).borrow()[*idx].value.clone(),
+                DefinitionScope::Global => continue,
+            };
+            upvalues[*slot].value = cell;
+        }
+
+        (*stack)
+            .borrow_mut()
+            .push(Value::Func(Rc::new(func.closure(upvalues))));
+        Ok(0)
+    }
+}
+
+impl Debug for Closure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<Closure {:?}>", self.captures)
+    }
+}
+
+impl Display for Closure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}       {:?}", self.code, self.captures)
+    }
+}
+
+/// Closes the upvalues of every local from `local` up, so closures made
+/// in one loop iteration keep that iteration's values
+pub struct CloseUpValue {
+    code: InstructionType,
+    local: usize,
+}
+
+impl CloseUpValue {
+    pub fn new(local: usize) -> Self {
+        CloseUpValue {
+            code: InstructionType::OP_CLOSE_UPVALUE,
+            local,
+        }
+    }
+}
+
+impl InstructionBase for CloseUpValue {
+    fn disassemble(&self) -> InstructionType {
+        self.code.clone()
+    }
+
+    fn eval(
+        &self,
+        stack: &Rc<RefCell<Stack>>,
+        _: &Rc<RefCell<Table>>,
+        _: &Rc<RefCell<Vec<Frame>>>,
+        offset: usize,
+        _: &Rc<RefCell<Vec<UpValue>>>,
+        _: usize,
+        _: usize,
+    ) -> Result<isize, Box<dyn ErrTrait>> {
+        (*stack)
+            .borrow_mut()
+            .close_upvalues(self.local.saturating_add(offset));
+        Ok(0)
+    }
+}
+
+impl Debug for CloseUpValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}       {}", self.code, self.local)
+    }
+}
+
+impl Display for CloseUpValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}       {}", self.code, self.local)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{
+        compiler::compiler::Capture,
+        values::values::Value,
+        vm::{natives::load_natives, stack::Stack, table::Table, vm::VM},
+    };
+
+    fn run(src: &str) -> Rc<RefCell<Table>> {
+        let globals = Rc::new(RefCell::new(Table::new()));
+        load_natives(globals.clone());
+        let func = VM::compile(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        VM::new(&func, globals.clone()).run().unwrap();
+        globals
+    }
+
+    fn global(globals: &Rc<RefCell<Table>>, name: &str) -> Value {
+        (*globals).borrow().resolve(&name.into()).unwrap()
+    }
+
+    #[test]
+    fn test_loop_closures_capture_distinct_values() {
+        let globals = run("var a; var b; var c;
+var x; var y; var z;
+{
+    for (var i = 0; i < 3; i = i + 1) {
+        var j = i * 10;
+        fun loop_var() { return i; }
+        fun body_var() { return j; }
+        if (i == 0) { a = loop_var; x = body_var; }
+        if (i == 1) { b = loop_var; y = body_var; }
+        if (i == 2) { c = loop_var; z = body_var; }
+    }
+}
+var ra = a(); var rb = b(); var rc = c();
+var rx = x(); var ry = y(); var rz = z();
+");
+
+        for (name, expected) in [
+            ("ra", 0.0),
+            ("rb", 1.0),
+            ("rc", 2.0),
+            ("rx", 0.0),
+            ("ry", 10.0),
+            ("rz", 20.0),
+        ] {
+            assert_eq!(global(&globals, name), Value::Number(expected), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_top_level_loop_closures() {
+        let globals = run("var a; var b;
+for (var i = 0; i < 2; i = i + 1) {
+    fun loop_var() { return i; }
+    if (i == 0) { a = loop_var; }
+    if (i == 1) { b = loop_var; }
+}
+var ra = a(); var rb = b();
+var seen = 0;
+for (var k = 0; k < 5; k = k + 1) {
+    if (k == 1) continue;
+    if (k == 3) break;
+    seen = seen + k;
+}
+var after = \"slots still line up\";
+");
+        assert_eq!(global(&globals, "ra"), Value::Number(0.0));
+        assert_eq!(global(&globals, "rb"), Value::Number(1.0));
+        assert_eq!(global(&globals, "seen"), Value::Number(2.0));
+        assert_eq!(
+            global(&globals, "after"),
+            Value::String("slots still line up".into())
+        );
+        // the loop variable is scoped to the loop rather than a global
+        assert!((*globals).borrow().resolve(&"i".into()).is_none());
+    }
+
+    #[test]
+    fn test_closures_share_open_upvalues() {
+        let globals = run("fun counter() {
+    var n = 0;
+    fun inc() { n = n + 1; return n; }
+    return inc;
+}
+var first = counter();
+var second = counter();
+first();
+var from_first = first();
+var from_second = second();
+
+fun live() {
+    var y = 1;
+    fun get() { return y; }
+    y = 3;
+    return get();
+}
+var seen = live();
+");
+        assert_eq!(global(&globals, "from_first"), Value::Number(2.0));
+        assert_eq!(global(&globals, "from_second"), Value::Number(1.0));
+        assert_eq!(global(&globals, "seen"), Value::Number(3.0));
+    }
+
+    #[test]
+    fn test_stacks_keep_their_own_upvalues() {
+        let mut first = Stack::from(vec![Value::Number(1.0)]);
+        let mut second = Stack::from(vec![Value::Number(2.0)]);
+        let from_first = first.capture(0);
+        let from_second = second.capture(0);
+        assert!(!Rc::ptr_eq(&from_first, &from_second));
+        assert!(Rc::ptr_eq(&from_first, &first.capture(0)));
+
+        // closing a slot on one stack leaves the same slot on another open
+        first.close_upvalues(0);
+        assert!(matches!(*from_first.borrow(), Capture::Closed(Value::Number(n)) if n == 1.0));
+        assert!(matches!(*from_second.borrow(), Capture::Open(0)));
+    }
+}
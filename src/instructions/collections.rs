@@ -0,0 +1,493 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::{Debug, Display},
+    rc::Rc,
+};
+
+use crate::{
+    compiler::compiler::UpValue,
+    errors::err::ErrTrait,
+    values::{func::Frame, values::Value},
+    vm::{stack::Stack, table::Table},
+};
+
+use super::{
+    err::InstructionErr,
+    instructions::{InstructionBase, InstructionType},
+};
+
+/// Collects the top `len` values of the stack into a list, in the order
+/// they were pushed
+pub struct BuildArray {
+    code: InstructionType,
+    len: usize,
+}
+
+impl BuildArray {
+    pub fn new(len: usize) -> Self {
+        BuildArray {
+            code: InstructionType::OP_ARRAY,
+            len,
+        }
+    }
+}
+
+impl InstructionBase for BuildArray {
+    fn eval(
+        &self,
+        stack: &Rc<RefCell<Stack>>,
+        _: &Rc<RefCell<Table>>,
+        _: &Rc<RefCell<Vec<Frame>>>,
+        _: usize,
+        _: &Rc<RefCell<Vec<UpValue>>>,
+        _: usize,
+        _: usize,
+    ) -> Result<isize, Box<dyn ErrTrait>> {
+        let start = (*stack).borrow().len().saturating_sub(self.len);
+        let items = (*stack).borrow_mut().split_off(start);
+        (*stack)
+            .borrow_mut()
+            .push(Value::Array(Rc::new(RefCell::new(items))));
+        Ok(0)
+    }
+
+    fn disassemble(&self) -> InstructionType {
+        self.code.clone()
+    }
+}
+
+impl Debug for BuildArray {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}       <{} items>", self.code, self.len)
+    }
+}
+
+impl Display for BuildArray {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}       <{} items>", self.code, self.len)
+    }
+}
+
+/// Collects the top `len` key/value pairs of the stack into a map, a key
+/// repeated later in the literal wins
+pub struct BuildMap {
+    code: InstructionType,
+    len: usize,
+    line: usize,
+    line_contents: String,
+}
+
+impl BuildMap {
+    pub fn new(len: usize, line: usize, line_contents: String) -> Self {
+        BuildMap {
+            code: InstructionType::OP_MAP,
+            len,
+            line,
+            line_contents,
+        }
+    }
+}
+
+impl InstructionBase for BuildMap {
+    fn eval(
+        &self,
+        stack: &Rc<RefCell<Stack>>,
+        _: &Rc<RefCell<Table>>,
+        _: &Rc<RefCell<Vec<Frame>>>,
+        _: usize,
+        _: &Rc<RefCell<Vec<UpValue>>>,
+        _: usize,
+        _: usize,
+    ) -> Result<isize, Box<dyn ErrTrait>> {
+        let start = (*stack).borrow().len().saturating_sub(self.len * 2);
+        let pairs = (*stack).borrow_mut().split_off(start);
+        let mut entries = HashMap::with_capacity(self.len);
+        let mut pairs = pairs.into_iter();
+        while let (Some(key), Some(val)) = (pairs.next(), pairs.next()) {
+            match key {
+                Value::String(key) => {
+                    entries.insert(key.to_string(), val);
+                }
+                _ => {
+                    return Err(Box::new(InstructionErr::new(
+                        format!(
+                            "
+Line {}: {}
+          ^
+          -------- Map keys must be Strings, not `{}`
+",
+                            self.line, self.line_contents, key
+                        ),
+                        format!("{{{}: ...}}", key),
+                    )))
+                }
+            }
+        }
+        (*stack)
+            .borrow_mut()
+            .push(Value::Map(Rc::new(RefCell::new(entries))));
+        Ok(0)
+    }
+
+    fn disassemble(&self) -> InstructionType {
+        self.code.clone()
+    }
+}
+
+impl Debug for BuildMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}       <{} entries>", self.code, self.len)
+    }
+}
+
+impl Display for BuildMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}       <{} entries>", self.code, self.len)
+    }
+}
+
+/// The key `index` refers to in a map
+fn map_key(index: &Value) -> Result<String, String> {
+    match index {
+        Value::String(key) => Ok(key.to_string()),
+        _ => Err(format!("Map keys must be Strings, not {}", index)),
+    }
+}
+
+/// The slot `index` refers to in a list of `len` items
+fn list_slot(index: &Value, len: usize) -> Result<usize, String> {
+    match index {
+        Value::Number(n) if n.fract() != 0.0 => {
+            Err(format!("List indices must be whole numbers, not {}", n))
+        }
+        Value::Number(n) if *n < 0.0 || *n >= len as f64 => Err(format!(
+            "Index {} is out of bounds for a list of length {}",
+            n, len
+        )),
+        Value::Number(n) => Ok(*n as usize),
+        _ => Err(format!("List indices must be Numbers, not {}", index)),
+    }
+}
+
+pub struct IndexGet {
+    code: InstructionType,
+    line: usize,
+    line_contents: String,
+}
+
+impl IndexGet {
+    pub fn new(line: usize, line_contents: String) -> Self {
+        IndexGet {
+            code: InstructionType::OP_INDEX_GET,
+            line,
+            line_contents,
+        }
+    }
+
+    fn err(&self, message: String, target: &Value, index: &Value) -> Box<dyn ErrTrait> {
+        Box::new(InstructionErr::new(
+            format!(
+                "
+Line {}: {}
+          ^
+          -------- {}
+",
+                self.line, self.line_contents, message
+            ),
+            format!("{}[{}]", target, index),
+        ))
+    }
+}
+
+impl InstructionBase for IndexGet {
+    fn eval(
+        &self,
+        stack: &Rc<RefCell<Stack>>,
+        _: &Rc<RefCell<Table>>,
+        _: &Rc<RefCell<Vec<Frame>>>,
+        _: usize,
+        _: &Rc<RefCell<Vec<UpValue>>>,
+        _: usize,
+        _: usize,
+    ) -> Result<isize, Box<dyn ErrTrait>> {
+        let index = (*stack).borrow_mut().pop().unwrap();
+        let target = (*stack).borrow_mut().pop().unwrap();
+        let val = match &target {
+            Value::Array(items) => {
+                let len = (*items).borrow().len();
+                let slot =
+                    list_slot(&index, len).map_err(|message| self.err(message, &target, &index))?;
+                (*items).borrow()[slot].clone()
+            }
+            // a missing key reads as nil
+            Value::Map(entries) => {
+                let key = map_key(&index).map_err(|message| self.err(message, &target, &index))?;
+                let val = (*entries).borrow().get(&key).cloned();
+                val.unwrap_or(Value::Nil)
+            }
+            _ => {
+                return Err(self.err(
+                    format!("Only lists and maps can be indexed, not `{}`", target),
+                    &target,
+                    &index,
+                ))
+            }
+        };
+        (*stack).borrow_mut().push(val);
+        Ok(0)
+    }
+
+    fn disassemble(&self) -> InstructionType {
+        self.code.clone()
+    }
+}
+
+impl Debug for IndexGet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.code)
+    }
+}
+
+impl Display for IndexGet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.code)
+    }
+}
+
+pub struct IndexSet {
+    code: InstructionType,
+    line: usize,
+    line_contents: String,
+}
+
+impl IndexSet {
+    pub fn new(line: usize, line_contents: String) -> Self {
+        IndexSet {
+            code: InstructionType::OP_INDEX_SET,
+            line,
+            line_contents,
+        }
+    }
+
+    fn err(&self, message: String, target: &Value, index: &Value) -> Box<dyn ErrTrait> {
+        Box::new(InstructionErr::new(
+            format!(
+                "
+Line {}: {}
+          ^
+          -------- {}
+",
+                self.line, self.line_contents, message
+            ),
+            format!("{}[{}] = ...", target, index),
+        ))
+    }
+}
+
+impl InstructionBase for IndexSet {
+    fn eval(
+        &self,
+        stack: &Rc<RefCell<Stack>>,
+        _: &Rc<RefCell<Table>>,
+        _: &Rc<RefCell<Vec<Frame>>>,
+        _: usize,
+        _: &Rc<RefCell<Vec<UpValue>>>,
+        _: usize,
+        _: usize,
+    ) -> Result<isize, Box<dyn ErrTrait>> {
+        let val = (*stack).borrow_mut().pop().unwrap();
+        let index = (*stack).borrow_mut().pop().unwrap();
+        let target = (*stack).borrow_mut().pop().unwrap();
+        match &target {
+            Value::Array(items) => {
+                let len = (*items).borrow().len();
+                let slot =
+                    list_slot(&index, len).map_err(|message| self.err(message, &target, &index))?;
+                (*items).borrow_mut()[slot] = val.clone();
+            }
+            Value::Map(entries) => {
+                let key = map_key(&index).map_err(|message| self.err(message, &target, &index))?;
+                (*entries).borrow_mut().insert(key, val.clone());
+            }
+            _ => {
+                return Err(self.err(
+                    format!(
+                        "Only list elements and map entries can be assigned to, not `{}`",
+                        target
+                    ),
+                    &target,
+                    &index,
+                ))
+            }
+        }
+        // assignment is an expression, it leaves the value behind
+        (*stack).borrow_mut().push(val);
+        Ok(0)
+    }
+
+    fn disassemble(&self) -> InstructionType {
+        self.code.clone()
+    }
+}
+
+impl Debug for IndexSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.code)
+    }
+}
+
+impl Display for IndexSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{
+        values::values::Value,
+        vm::{stack::Stack, table::Table, vm::VM},
+    };
+
+    fn run(src: &str) -> Result<Rc<RefCell<Table>>, String> {
+        let globals = VM::globals();
+        let func = VM::compile(src.as_bytes().to_vec(), globals.clone())
+            .map_err(|err| format!("{}", err))?;
+        func.call(
+            Rc::new(RefCell::new(Stack::new())),
+            globals.clone(),
+            Rc::new(RefCell::new(Vec::new())),
+            0,
+        )
+        .map_err(|err| format!("{}", err))?;
+        Ok(globals)
+    }
+
+    fn global(globals: &Rc<RefCell<Table>>, name: &str) -> Value {
+        (*globals).borrow().resolve(&name.into()).unwrap()
+    }
+
+    fn list(items: Vec<Value>) -> Value {
+        Value::Array(Rc::new(RefCell::new(items)))
+    }
+
+    #[test]
+    fn test_array_literals_and_indexing() {
+        let globals = run("var empty = [];
+var xs = [1, \"two\", 1 + 2];
+var nested = [[1, 2], [3]];
+var second = xs[1];
+var inner = nested[0][1];
+xs[0] = 5;
+var assigned = (xs[2] = 7);
+var alias = xs;
+alias[1] = nil;
+{
+    var i = 1;
+    nested[i][0] = nested[0][0] + 10;
+}
+")
+        .unwrap();
+        assert_eq!(global(&globals, "empty"), list(vec![]));
+        assert_eq!(global(&globals, "second"), Value::String("two".into()));
+        assert_eq!(global(&globals, "inner"), Value::Number(2.0));
+        assert_eq!(global(&globals, "assigned"), Value::Number(7.0));
+        // lists are shared, writing through `alias` changes `xs`
+        assert_eq!(
+            global(&globals, "xs"),
+            list(vec![Value::Number(5.0), Value::Nil, Value::Number(7.0)])
+        );
+        assert_eq!(
+            global(&globals, "nested"),
+            list(vec![
+                list(vec![Value::Number(1.0), Value::Number(2.0)]),
+                list(vec![Value::Number(11.0)])
+            ])
+        );
+    }
+
+    #[test]
+    fn test_index_errors() {
+        for (src, message) in [
+            (
+                "var xs = [1, 2];\nxs[2];\n",
+                "Index 2 is out of bounds for a list of length 2",
+            ),
+            (
+                "var xs = [1, 2];\nxs[-1] = 0;\n",
+                "Index -1 is out of bounds",
+            ),
+            (
+                "var xs = [1, 2];\nxs[0.5];\n",
+                "List indices must be whole numbers",
+            ),
+            (
+                "var xs = [1, 2];\nxs[\"0\"];\n",
+                "List indices must be Numbers",
+            ),
+            ("var n = 1;\nn[0];\n", "Only lists and maps can be indexed"),
+        ] {
+            let err = run(src).err().unwrap();
+            assert!(err.contains(message), "{}: {}", src, err);
+        }
+    }
+
+    #[test]
+    fn test_maps() {
+        let globals = run("var empty = {};
+var ages = {\"ada\": 36, \"alan\": 41, \"ada\": 37};
+var ada = ages[\"ada\"];
+var missing = ages[\"grace\"];
+ages[\"grace\"] = 85;
+ages[\"alan\"] = ages[\"alan\"] + 1;
+var key = \"a\" + \"lan\";
+var alan = ages[key];
+var nested = {\"inner\": {\"xs\": [1, 2]}};
+nested[\"inner\"][\"xs\"][0] = 3;
+var printed = str(ages);
+")
+        .unwrap();
+        let map = |entries: Vec<(&str, Value)>| {
+            Value::Map(Rc::new(RefCell::new(
+                entries
+                    .into_iter()
+                    .map(|(key, val)| (key.to_string(), val))
+                    .collect(),
+            )))
+        };
+        assert_eq!(global(&globals, "empty"), map(vec![]));
+        assert_eq!(global(&globals, "ada"), Value::Number(37.0));
+        assert_eq!(global(&globals, "missing"), Value::Nil);
+        assert_eq!(global(&globals, "alan"), Value::Number(42.0));
+        assert_eq!(
+            global(&globals, "ages"),
+            map(vec![
+                ("ada", Value::Number(37.0)),
+                ("alan", Value::Number(42.0)),
+                ("grace", Value::Number(85.0))
+            ])
+        );
+        assert_eq!(
+            global(&globals, "nested"),
+            map(vec![(
+                "inner",
+                map(vec![(
+                    "xs",
+                    list(vec![Value::Number(3.0), Value::Number(2.0)])
+                )])
+            )])
+        );
+        assert_eq!(
+            global(&globals, "printed"),
+            Value::String("{\"ada\": 37, \"alan\": 42, \"grace\": 85}".into())
+        );
+
+        let err = run("var m = {1: 2};\n").err().unwrap();
+        assert!(err.contains("Map keys must be Strings"), "{}", err);
+        let err = run("var m = {};\nm[1] = 2;\n").err().unwrap();
+        assert!(err.contains("Map keys must be Strings"), "{}", err);
+    }
+}
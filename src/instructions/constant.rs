@@ -5,7 +5,10 @@ use std::{
 };
 
 use crate::{
-    compiler::compiler::UpValue, errors::err::ErrTrait, values::values::Value, vm::table::Table,
+    compiler::compiler::UpValue,
+    errors::err::ErrTrait,
+    values::{func::Frame, values::Value},
+    vm::{stack::Stack, table::Table},
 };
 
 use super::instructions::{InstructionBase, InstructionType};
@@ -28,14 +31,14 @@ impl Constant {
 impl InstructionBase for Constant {
     fn eval(
         &self,
-        stack: Rc<RefCell<Vec<Value>>>,
-        _: Rc<RefCell<Table>>,
-        _: Rc<RefCell<Vec<String>>>,
+        stack: &Rc<RefCell<Stack>>,
+        _: &Rc<RefCell<Table>>,
+        _: &Rc<RefCell<Vec<Frame>>>,
         _: usize,
-        _: Rc<RefCell<Vec<UpValue>>>,
+        _: &Rc<RefCell<Vec<UpValue>>>,
         _: usize,
         _: usize,
-    ) -> Result<usize, Box<dyn ErrTrait>> {
+    ) -> Result<isize, Box<dyn ErrTrait>> {
         stack.borrow_mut().push(self.operand.clone());
         Ok(0)
     }
@@ -43,6 +46,10 @@ impl InstructionBase for Constant {
     fn disassemble(&self) -> InstructionType {
         self.code.clone()
     }
+
+    fn constant(&self) -> Option<&Value> {
+        Some(&self.operand)
+    }
 }
 
 impl Display for Constant {
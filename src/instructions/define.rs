@@ -5,7 +5,10 @@ use std::{
 };
 
 use crate::{
-    compiler::compiler::UpValue, errors::err::ErrTrait, values::values::Value, vm::table::Table,
+    compiler::compiler::UpValue,
+    errors::err::ErrTrait,
+    values::{func::Frame, intern::Name, values::Value},
+    vm::{stack::Stack, table::Table},
 };
 
 use super::{
@@ -23,7 +26,7 @@ pub enum DefinitionScope {
 pub struct Define {
     code: InstructionType,
     scope: DefinitionScope,
-    operand: String,
+    operand: Name,
 }
 
 impl Define {
@@ -31,7 +34,7 @@ impl Define {
         Define {
             code: InstructionType::OP_DEFINE,
             scope,
-            operand,
+            operand: Name::new(&operand),
         }
     }
 }
@@ -39,14 +42,14 @@ impl Define {
 impl InstructionBase for Define {
     fn eval(
         &self,
-        stack: Rc<RefCell<Vec<Value>>>,
-        table: Rc<RefCell<Table>>,
-        _: Rc<RefCell<Vec<String>>>,
+        stack: &Rc<RefCell<Stack>>,
+        table: &Rc<RefCell<Table>>,
+        _: &Rc<RefCell<Vec<Frame>>>,
         _: usize,
-        _: Rc<RefCell<Vec<UpValue>>>,
+        _: &Rc<RefCell<Vec<UpValue>>>,
         _: usize,
         _: usize,
-    ) -> Result<usize, Box<dyn ErrTrait>> {
+    ) -> Result<isize, Box<dyn ErrTrait>> {
         match self.scope {
             DefinitionScope::Global => {
                 let current_stack_index = || {
@@ -84,7 +87,7 @@ impl Display for Define {
 
 pub struct Resolve {
     code: InstructionType,
-    identifier: String,
+    identifier: Name,
     scope: DefinitionScope,
 }
 
@@ -92,7 +95,7 @@ impl Resolve {
     pub fn new(identifier: String, scope: DefinitionScope) -> Self {
         Resolve {
             code: InstructionType::OP_RESOLVE,
-            identifier,
+            identifier: Name::new(&identifier),
             scope,
         }
     }
@@ -105,32 +108,48 @@ impl InstructionBase for Resolve {
 
     fn eval(
         &self,
-        stack: Rc<RefCell<Vec<Value>>>,
-        env: Rc<RefCell<Table>>,
-        _: Rc<RefCell<Vec<String>>>,
+        stack: &Rc<RefCell<Stack>>,
+        env: &Rc<RefCell<Table>>,
+        call_frame: &Rc<RefCell<Vec<Frame>>>,
         offset: usize,
-        upvalue_stack: Rc<RefCell<Vec<UpValue>>>,
+        upvalue_stack: &Rc<RefCell<Vec<UpValue>>>,
         _: usize,
         _: usize,
-    ) -> Result<usize, Box<dyn ErrTrait>> {
+    ) -> Result<isize, Box<dyn ErrTrait>> {
         match self.scope {
-            DefinitionScope::Global => match (*env).borrow().resolve(&self.identifier) {
-                Some(val) => {
-                    stack.borrow_mut().push(val);
-                }
-                None => {
-                    return Err(Box::new(InstructionErr::new(
-                        format!("undefined variable:: {} not found", self.identifier),
-                        format!("{}", self.code),
-                    )))
+            DefinitionScope::Global => {
+                // bound first so the table isn't borrowed while a thunk runs
+                let resolved = (*env).borrow().resolve(&self.identifier);
+                match resolved {
+                    Some(Value::Lazy(thunk)) => {
+                        // first read of a lazy global, evaluate and cache the result
+                        let thunk_offset = (*stack).borrow().len();
+                        let val = thunk.call(
+                            stack.clone(),
+                            env.clone(),
+                            call_frame.clone(),
+                            thunk_offset,
+                        )?;
+                        (*env).borrow_mut().override_(&self.identifier, val.clone());
+                        stack.borrow_mut().push(val);
+                    }
+                    Some(val) => {
+                        stack.borrow_mut().push(val);
+                    }
+                    None => {
+                        return Err(Box::new(InstructionErr::new(
+                            format!("undefined variable: `{}`", self.identifier),
+                            format!("{}", self.code),
+                        )))
+                    }
                 }
-            },
+            }
             DefinitionScope::Local(stack_idx) => {
                 let val = stack.borrow()[stack_idx.saturating_add(offset)].clone();
                 stack.borrow_mut().push(val);
             }
             DefinitionScope::UpValue(stack_idx) => {
-                let val = upvalue_stack.borrow()[stack_idx].value.clone();
+                let val = upvalue_stack.borrow()[stack_idx].get(stack);
                 stack.borrow_mut().push(val);
             }
         }
@@ -156,7 +175,7 @@ impl Display for Resolve {
 
 pub struct Override {
     code: InstructionType,
-    identifier: String,
+    identifier: Name,
     scope: DefinitionScope,
 }
 
@@ -164,7 +183,7 @@ impl Override {
     pub fn new(identifier: String, scope: DefinitionScope) -> Self {
         Override {
             code: InstructionType::OP_OVERRIDE,
-            identifier,
+            identifier: Name::new(&identifier),
             scope,
         }
     }
@@ -177,33 +196,31 @@ impl InstructionBase for Override {
 
     fn eval(
         &self,
-        stack: Rc<RefCell<Vec<Value>>>,
-        env: Rc<RefCell<Table>>,
-        _: Rc<RefCell<Vec<String>>>,
+        stack: &Rc<RefCell<Stack>>,
+        env: &Rc<RefCell<Table>>,
+        _: &Rc<RefCell<Vec<Frame>>>,
         offset: usize,
-        upvalue_stack: Rc<RefCell<Vec<UpValue>>>,
+        upvalue_stack: &Rc<RefCell<Vec<UpValue>>>,
         _: usize,
         _: usize,
-    ) -> Result<usize, Box<dyn ErrTrait>> {
+    ) -> Result<isize, Box<dyn ErrTrait>> {
         let top_of_stack = stack.borrow().len() - 1;
         let val = stack.borrow_mut()[top_of_stack].clone();
         match self.scope {
-            DefinitionScope::Global => {
-                match (*env).borrow_mut().override_(self.identifier.clone(), val) {
-                    Some(_) => {}
-                    None => {
-                        return Err(Box::new(InstructionErr::new(
-                            format!("undefined variable:: {} not found", self.identifier),
-                            format!("{}", self.code),
-                        )))
-                    }
+            DefinitionScope::Global => match (*env).borrow_mut().override_(&self.identifier, val) {
+                Some(_) => {}
+                None => {
+                    return Err(Box::new(InstructionErr::new(
+                        format!("undefined variable: `{}`", self.identifier),
+                        format!("{}", self.code),
+                    )))
                 }
-            }
+            },
             DefinitionScope::Local(stack_idx) => {
                 (*stack).borrow_mut()[stack_idx.saturating_add(offset)] = val;
             }
             DefinitionScope::UpValue(stack_idx) => {
-                (*upvalue_stack).borrow_mut()[stack_idx].value = val;
+                (*upvalue_stack).borrow()[stack_idx].set(stack, val);
             }
         }
         Ok(0)
@@ -225,3 +242,47 @@ impl Display for Override {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::{values::func::Native, vm::vm::VM};
+
+    use super::*;
+
+    static LAZY_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    fn run(src: &str, globals: Rc<RefCell<Table>>) {
+        let func = VM::compile(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        VM::new(&func, globals).run().unwrap();
+    }
+
+    #[test]
+    fn test_lazy_global_initializes_once() {
+        let globals = Rc::new(RefCell::new(Table::new()));
+        (*globals).borrow_mut().add(
+            "count".into(),
+            Value::Native(Rc::new(Native::new(
+                "count".to_string(),
+                0,
+                Box::new(|_, _| {
+                    let calls = LAZY_CALLS.fetch_add(1, Ordering::SeqCst) + 1;
+                    Ok(Value::Number(calls as f64))
+                }),
+            ))),
+        );
+
+        run("lazy var x = count() + 10;\n", globals.clone());
+        assert_eq!(LAZY_CALLS.load(Ordering::SeqCst), 0);
+
+        run("var a = x;\nvar b = x;\nvar c = x;\n", globals.clone());
+        assert_eq!(LAZY_CALLS.load(Ordering::SeqCst), 1);
+        for name in ["a", "b", "c", "x"] {
+            assert_eq!(
+                (*globals).borrow().resolve(&name.into()),
+                Some(Value::Number(11.0))
+            );
+        }
+    }
+}
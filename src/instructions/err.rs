@@ -6,11 +6,45 @@ use crate::errors::err::ErrTraitBase;
 pub struct InstructionErr {
     message: String,
     instruction_str: String,
+    line: Option<usize>,
 }
 
 impl ErrTraitBase for InstructionErr {
     fn raise(&self) {
-        println!("{}", self.message);
+        match self.line {
+            // the caret displays start on a new line and already show theirs
+            Some(line) if !self.message.starts_with('\n') => {
+                println!("Line {}: {}", line, self.message)
+            }
+            _ => println!("{}", self.message),
+        }
+    }
+
+    fn position(&self) -> Option<(usize, Option<usize>, String)> {
+        // the caret displays repeat the source line, keep just what's under
+        // the caret
+        let message = match self
+            .message
+            .lines()
+            .rev()
+            .map(str::trim)
+            .find(|text| text.starts_with("---"))
+        {
+            Some(text) => text.trim_start_matches('-').trim().to_string(),
+            None => self
+                .message
+                .split_whitespace()
+                .collect::<Vec<&str>>()
+                .join(" "),
+        };
+        self.line.map(|line| (line, None, message))
+    }
+
+    fn at_line(&mut self, line: usize) {
+        // the innermost call sees the error first, keep its line
+        if self.line.is_none() {
+            self.line = Some(line);
+        }
     }
 }
 
@@ -19,27 +53,35 @@ impl InstructionErr {
         InstructionErr {
             message,
             instruction_str,
+            line: None,
         }
     }
+
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
 }
 
 impl Display for InstructionErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "Instruction Err::: {}: {} ",
-            self.instruction_str, self.message
-        )
+        match self.line {
+            Some(line) => write!(
+                f,
+                "Instruction Err::: {} @ line {}: {} ",
+                self.instruction_str, line, self.message
+            ),
+            None => write!(
+                f,
+                "Instruction Err::: {}: {} ",
+                self.instruction_str, self.message
+            ),
+        }
     }
 }
 
 impl Debug for InstructionErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "Instruction Err::: {}: {} ",
-            self.instruction_str, self.message
-        )
+        Display::fmt(self, f)
     }
 }
 
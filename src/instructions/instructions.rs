@@ -4,12 +4,16 @@ use std::{
     rc::Rc,
 };
 
-use crate::{compiler::compiler::UpValue, errors::err::ErrTrait, vm::table::Table};
+use crate::{
+    compiler::compiler::UpValue,
+    errors::err::ErrTrait,
+    vm::{stack::Stack, table::Table},
+};
 
-use crate::values::values::Value;
+use crate::values::{func::Frame, values::Value};
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum InstructionType {
     OP_RETURN,
     OP_CONST,
@@ -27,6 +31,12 @@ pub enum InstructionType {
     OP_SET,
     OP_GET,
     OP_INHERIT,
+    OP_CLOSURE,
+    OP_CLOSE_UPVALUE,
+    OP_ARRAY,
+    OP_MAP,
+    OP_INDEX_GET,
+    OP_INDEX_SET,
 }
 
 impl Display for InstructionType {
@@ -37,20 +47,37 @@ impl Display for InstructionType {
 
 pub trait InstructionBase {
     fn disassemble(&self) -> InstructionType;
+    /// Runs the instruction, giving back how far to move the ip. 0 moves
+    /// on to the next instruction
     fn eval(
         &self,
-        stack: Rc<RefCell<Vec<Value>>>,
-        env: Rc<RefCell<Table>>,
-        call_frame: Rc<RefCell<Vec<String>>>,
+        stack: &Rc<RefCell<Stack>>,
+        env: &Rc<RefCell<Table>>,
+        call_frame: &Rc<RefCell<Vec<Frame>>>,
         offset: usize,
-        upvalue_stack: Rc<RefCell<Vec<UpValue>>>,
+        upvalue_stack: &Rc<RefCell<Vec<UpValue>>>,
         upvalue_offset: usize,
         local_upvalue_len: usize,
-    ) -> Result<usize, Box<dyn ErrTrait>>;
-}
+    ) -> Result<isize, Box<dyn ErrTrait>>;
+
+    /// How far a jump moves the ip from the jump itself
+    fn jump_offset(&self) -> Option<isize> {
+        Option::None
+    }
+
+    /// Points a jump somewhere else, a no-op for everything else
+    fn set_jump_offset(&mut self, _offset: isize) {}
 
-pub trait Instruction: InstructionBase + Display + Debug {}
-impl<T> Instruction for T where T: Display + Debug + InstructionBase {}
+    /// The value a constant instruction pushes
+    fn constant(&self) -> Option<&Value> {
+        Option::None
+    }
+
+    /// How many values a `PopN` pops
+    fn pop_count(&self) -> Option<usize> {
+        Option::None
+    }
+}
 
 pub struct Pop {
     code: InstructionType,
@@ -67,14 +94,14 @@ impl Pop {
 impl InstructionBase for Pop {
     fn eval(
         &self,
-        stack: Rc<RefCell<Vec<Value>>>,
-        _: Rc<RefCell<Table>>,
-        _: Rc<RefCell<Vec<String>>>,
+        stack: &Rc<RefCell<Stack>>,
+        _: &Rc<RefCell<Table>>,
+        _: &Rc<RefCell<Vec<Frame>>>,
         _: usize,
-        _: Rc<RefCell<Vec<UpValue>>>,
+        _: &Rc<RefCell<Vec<UpValue>>>,
         _: usize,
         _: usize,
-    ) -> Result<usize, Box<dyn ErrTrait>> {
+    ) -> Result<isize, Box<dyn ErrTrait>> {
         stack.borrow_mut().pop();
         Ok(0)
     }
@@ -115,15 +142,16 @@ impl InstructionBase for PopN {
     // pointer offset
     fn eval(
         &self,
-        stack: Rc<RefCell<Vec<Value>>>,
-        _: Rc<RefCell<Table>>,
-        _: Rc<RefCell<Vec<String>>>,
+        stack: &Rc<RefCell<Stack>>,
+        _: &Rc<RefCell<Table>>,
+        _: &Rc<RefCell<Vec<Frame>>>,
         _: usize,
-        _: Rc<RefCell<Vec<UpValue>>>,
+        _: &Rc<RefCell<Vec<UpValue>>>,
         _: usize,
         _: usize,
-    ) -> Result<usize, Box<dyn ErrTrait>> {
+    ) -> Result<isize, Box<dyn ErrTrait>> {
         let n_actual = (*stack).borrow().len().saturating_sub(self.n);
+        (*stack).borrow_mut().close_upvalues(n_actual);
         stack.borrow_mut().truncate(n_actual);
         Ok(0)
     }
@@ -131,6 +159,10 @@ impl InstructionBase for PopN {
     fn disassemble(&self) -> InstructionType {
         self.code.clone()
     }
+
+    fn pop_count(&self) -> Option<usize> {
+        Some(self.n)
+    }
 }
 
 impl Debug for PopN {
@@ -164,14 +196,14 @@ impl InstructionBase for None {
 
     fn eval(
         &self,
-        _: Rc<RefCell<Vec<Value>>>,
-        _: Rc<RefCell<Table>>,
-        _: Rc<RefCell<Vec<String>>>,
+        _: &Rc<RefCell<Stack>>,
+        _: &Rc<RefCell<Table>>,
+        _: &Rc<RefCell<Vec<Frame>>>,
         _: usize,
-        _: Rc<RefCell<Vec<UpValue>>>,
+        _: &Rc<RefCell<Vec<UpValue>>>,
         _: usize,
         _: usize,
-    ) -> Result<usize, Box<dyn ErrTrait>> {
+    ) -> Result<isize, Box<dyn ErrTrait>> {
         Ok(0)
     }
 }
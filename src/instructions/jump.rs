@@ -5,23 +5,38 @@ use std::{
 };
 
 use crate::{
-    compiler::compiler::UpValue, errors::err::ErrTrait, values::values::Value, vm::table::Table,
+    compiler::compiler::UpValue,
+    errors::err::ErrTrait,
+    values::func::Frame,
+    vm::{stack::Stack, table::Table},
 };
 
-use super::instructions::{InstructionBase, InstructionType};
+use super::{
+    err::InstructionErr,
+    instructions::{InstructionBase, InstructionType},
+};
 
 pub struct Jump {
     code: InstructionType,
-    to: usize,
+    offset: isize,
     continue_condition: bool,
+    line: usize,
+    line_contents: String,
 }
 
 impl Jump {
-    pub fn new(to: usize, continue_condition: bool) -> Self {
+    pub fn new(
+        offset: isize,
+        continue_condition: bool,
+        line: usize,
+        line_contents: String,
+    ) -> Self {
         Jump {
             code: InstructionType::OP_JUMP,
-            to,
+            offset,
             continue_condition,
+            line,
+            line_contents,
         }
     }
 }
@@ -33,20 +48,44 @@ impl InstructionBase for Jump {
 
     fn eval(
         &self,
-        stack: Rc<RefCell<Vec<Value>>>,
-        _: Rc<RefCell<Table>>,
-        _: Rc<RefCell<Vec<String>>>,
+        stack: &Rc<RefCell<Stack>>,
+        _: &Rc<RefCell<Table>>,
+        _: &Rc<RefCell<Vec<Frame>>>,
         _: usize,
-        _: Rc<RefCell<Vec<UpValue>>>,
+        _: &Rc<RefCell<Vec<UpValue>>>,
         _: usize,
         _: usize,
-    ) -> Result<usize, Box<dyn ErrTrait>> {
+    ) -> Result<isize, Box<dyn ErrTrait>> {
         let idx = stack.borrow().len() - 1;
-        let expr_res = stack.borrow_mut()[idx].truthy()?;
+        let condition = stack.borrow()[idx].clone();
+        let expr_res = match condition.truthy() {
+            Ok(truthy) => truthy,
+            Err(_) => {
+                return Err(Box::new(InstructionErr::new(
+                    format!(
+                        "
+Line {}: {}
+     ^
+     -------- condition must be boolean/number/string/nil, got {:?}
+",
+                        self.line, self.line_contents, condition
+                    ),
+                    format!("{}", self),
+                )));
+            }
+        };
         if expr_res == self.continue_condition {
             return Ok(0);
         }
-        Ok(self.to)
+        Ok(self.offset)
+    }
+
+    fn jump_offset(&self) -> Option<isize> {
+        Some(self.offset)
+    }
+
+    fn set_jump_offset(&mut self, offset: isize) {
+        self.offset = offset;
     }
 }
 
@@ -54,8 +93,8 @@ impl Debug for Jump {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{:?} to {} if {}",
-            self.code, self.to, !self.continue_condition
+            "{:?} by {} if {}",
+            self.code, self.offset, !self.continue_condition
         )
     }
 }
@@ -64,22 +103,22 @@ impl Display for Jump {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{:?}_IF_{}       {}",
-            self.code, !self.continue_condition, self.to
+            "{:?}_IF_{}       {:+}",
+            self.code, !self.continue_condition, self.offset
         )
     }
 }
 
 pub struct ForceJump {
     code: InstructionType,
-    to: usize,
+    offset: isize,
 }
 
 impl ForceJump {
-    pub fn new(to: usize) -> Self {
+    pub fn new(offset: isize) -> Self {
         ForceJump {
             code: InstructionType::OP_JUMP,
-            to,
+            offset,
         }
     }
 }
@@ -91,26 +130,52 @@ impl InstructionBase for ForceJump {
 
     fn eval(
         &self,
-        _: Rc<RefCell<Vec<Value>>>,
-        _: Rc<RefCell<Table>>,
-        _: Rc<RefCell<Vec<String>>>,
+        _: &Rc<RefCell<Stack>>,
+        _: &Rc<RefCell<Table>>,
+        _: &Rc<RefCell<Vec<Frame>>>,
         _: usize,
-        _: Rc<RefCell<Vec<UpValue>>>,
+        _: &Rc<RefCell<Vec<UpValue>>>,
         _: usize,
         _: usize,
-    ) -> Result<usize, Box<dyn ErrTrait>> {
-        Ok(self.to)
+    ) -> Result<isize, Box<dyn ErrTrait>> {
+        Ok(self.offset)
+    }
+
+    fn jump_offset(&self) -> Option<isize> {
+        Some(self.offset)
+    }
+
+    fn set_jump_offset(&mut self, offset: isize) {
+        self.offset = offset;
     }
 }
 
 impl Debug for ForceJump {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?} to {}", self.code, self.to)
+        write!(f, "{:?} by {}", self.code, self.offset)
     }
 }
 
 impl Display for ForceJump {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}       {}", self.code, self.to)
+        write!(f, "{:?}       {:+}", self.code, self.offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::vm::VM;
+
+    #[test]
+    fn test_condition_must_coerce() {
+        let src = "class Foo {}\nif (Foo) {\n    print 1;\n}\n";
+        let err = VM::interprate_with(src.as_bytes().to_vec(), VM::globals()).unwrap_err();
+        let message = format!("{}", err);
+        assert!(message.contains("Line 2: if (Foo) {"));
+        assert!(message.contains("condition must be boolean/number/string/nil, got <Class Foo>"));
+
+        let src = "fun f() {}\nwhile (f) {}\n";
+        let err = VM::interprate_with(src.as_bytes().to_vec(), VM::globals()).unwrap_err();
+        assert!(format!("{}", err).contains("got <Fun f>"));
     }
 }
@@ -1,11 +1,14 @@
 pub mod binary;
 pub mod call;
 pub mod chunk;
+pub mod closure;
+pub mod collections;
 pub mod constant;
 pub mod define;
-mod err;
+pub mod err;
 pub mod instructions;
 pub mod jump;
+pub mod op;
 pub mod print;
 pub mod properties;
 pub mod return_inst;
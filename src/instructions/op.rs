@@ -0,0 +1,226 @@
+use std::{
+    cell::RefCell,
+    fmt::{Debug, Display},
+    rc::Rc,
+};
+
+use crate::{
+    compiler::compiler::UpValue,
+    errors::err::ErrTrait,
+    values::{func::Frame, values::Value},
+    vm::{stack::Stack, table::Table},
+};
+
+use super::{
+    binary::Binary,
+    call::Call,
+    closure::{CloseUpValue, Closure},
+    collections::{BuildArray, BuildMap, IndexGet, IndexSet},
+    constant::Constant,
+    define::{Define, Override, Resolve},
+    instructions::{InstructionBase, InstructionType, None, Pop, PopN},
+    jump::{ForceJump, Jump},
+    print::Print,
+    properties::{Get, Inherit, Set},
+    return_inst::Return,
+    unary::Unary,
+};
+
+/// Declares `Op` with a variant per instruction, dispatching each call
+/// with a `match` on the variant
+macro_rules! ops {
+    ($($variant:ident),* $(,)?) => {
+        /// An instruction as it's stored in a chunk. Keeping every kind of
+        /// instruction in one enum lets a chunk hold them inline and lets
+        /// `Func::call` reach each `eval` without a vtable lookup
+        pub enum Op {
+            $($variant($variant),)*
+        }
+
+        $(
+            impl From<$variant> for Op {
+                fn from(inst: $variant) -> Self {
+                    Op::$variant(inst)
+                }
+            }
+        )*
+
+        impl InstructionBase for Op {
+            #[inline]
+            fn eval(
+                &self,
+                stack: &Rc<RefCell<Stack>>,
+                env: &Rc<RefCell<Table>>,
+                call_frame: &Rc<RefCell<Vec<Frame>>>,
+                offset: usize,
+                upvalue_stack: &Rc<RefCell<Vec<UpValue>>>,
+                upvalue_offset: usize,
+                local_upvalue_len: usize,
+            ) -> Result<isize, Box<dyn ErrTrait>> {
+                match self {
+                    $(Op::$variant(inst) => inst.eval(
+                        stack,
+                        env,
+                        call_frame,
+                        offset,
+                        upvalue_stack,
+                        upvalue_offset,
+                        local_upvalue_len,
+                    ),)*
+                }
+            }
+
+            fn disassemble(&self) -> InstructionType {
+                match self {
+                    $(Op::$variant(inst) => inst.disassemble(),)*
+                }
+            }
+
+            fn jump_offset(&self) -> Option<isize> {
+                match self {
+                    $(Op::$variant(inst) => inst.jump_offset(),)*
+                }
+            }
+
+            fn set_jump_offset(&mut self, offset: isize) {
+                match self {
+                    $(Op::$variant(inst) => inst.set_jump_offset(offset),)*
+                }
+            }
+
+            fn constant(&self) -> Option<&Value> {
+                match self {
+                    $(Op::$variant(inst) => inst.constant(),)*
+                }
+            }
+
+            fn pop_count(&self) -> Option<usize> {
+                match self {
+                    $(Op::$variant(inst) => inst.pop_count(),)*
+                }
+            }
+        }
+
+        impl Debug for Op {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    $(Op::$variant(inst) => Debug::fmt(inst, f),)*
+                }
+            }
+        }
+
+        impl Display for Op {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    $(Op::$variant(inst) => Display::fmt(inst, f),)*
+                }
+            }
+        }
+    };
+}
+
+ops!(
+    Return,
+    Constant,
+    Unary,
+    Binary,
+    Print,
+    Pop,
+    PopN,
+    Define,
+    Resolve,
+    Override,
+    Jump,
+    ForceJump,
+    None,
+    Call,
+    Set,
+    Get,
+    Inherit,
+    Closure,
+    CloseUpValue,
+    BuildArray,
+    BuildMap,
+    IndexGet,
+    IndexSet,
+);
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{instructions::binary::BinaryOp, vm::vm::VM};
+
+    #[test]
+    fn test_op_display_matches_instruction() {
+        let binary = Binary::new(BinaryOp::ADD);
+        let op: Op = Binary::new(BinaryOp::ADD).into();
+        assert_eq!(format!("{}", op), format!("{}", binary));
+        assert_eq!(format!("{:?}", op), format!("{:?}", binary));
+        assert_eq!(op.disassemble(), InstructionType::OP_BINARY);
+
+        let mut jump: Op = ForceJump::new(3).into();
+        jump.set_jump_offset(-5);
+        assert_eq!(jump.jump_offset(), Some(-5));
+        assert_eq!(
+            Op::from(Constant::new(Value::Number(1.0))).constant(),
+            Some(&Value::Number(1.0))
+        );
+    }
+
+    #[test]
+    fn test_dispatch_parity() {
+        let src = "class Counter {
+    __init__(start) { this.n = start; }
+    bump() { this.n = this.n + 1; return this; }
+}
+class Loud < Counter {
+    bump() { print \"bump\"; return super.bump(); }
+}
+fun make(step) {
+    var total = 0;
+    fun add() { total = total + step; return total; }
+    return add;
+}
+var add = make(2);
+add();
+var xs = [1, 2, 3];
+xs[0] = add();
+var m = {\"a\": -xs[0]};
+m[\"b\"] = !false;
+var c = Loud(0).bump().bump();
+for (var i = 0; i < 3; i = i + 1) {
+    if (i == 1) { continue; }
+    print i;
+}
+print xs;
+print m;
+print c.n;
+";
+        let globals = VM::globals();
+        let func = VM::compile(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        let compiled: Vec<InstructionType> =
+            func.chunk.code.iter().map(|op| op.disassemble()).collect();
+        for kind in [
+            InstructionType::OP_INHERIT,
+            InstructionType::OP_ARRAY,
+            InstructionType::OP_MAP,
+            InstructionType::OP_INDEX_SET,
+            InstructionType::OP_UNARY,
+            InstructionType::OP_JUMP,
+            InstructionType::OP_GET,
+            InstructionType::OP_PRINT,
+        ] {
+            assert!(compiled.contains(&kind), "{} wasn't compiled", kind);
+        }
+
+        let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+        VM::interprate_to(src.as_bytes().to_vec(), globals, output.clone()).unwrap();
+        let printed = String::from_utf8(output.borrow().clone()).unwrap();
+        assert_eq!(
+            printed,
+            "\"bump\"\n\"bump\"\n0\n2\n[4, 2, 3]\n{\"a\": -4, \"b\": true}\n2\n"
+        );
+    }
+}
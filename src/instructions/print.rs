@@ -5,10 +5,21 @@ use std::{
 };
 
 use crate::{
-    compiler::compiler::UpValue, errors::err::ErrTrait, values::values::Value, vm::table::Table,
+    compiler::compiler::UpValue,
+    errors::err::ErrTrait,
+    values::{
+        err::ValueErr,
+        func::{Frame, Method},
+        values::Value,
+    },
+    vm::{output::print_line, stack::Stack, table::Table},
 };
 
-use super::instructions::{InstructionBase, InstructionType};
+use super::{
+    call::Call,
+    err::InstructionErr,
+    instructions::{InstructionBase, InstructionType},
+};
 
 pub struct Print {
     code: InstructionType,
@@ -22,18 +33,59 @@ impl Print {
     }
 }
 
+/// How `print` shows `val`. An instance whose class defines `toString`
+/// is shown as the string that method returns, any other value it
+/// returns is an error
+pub fn printed(
+    val: &Value,
+    stack: &Rc<RefCell<Stack>>,
+    env: &Rc<RefCell<Table>>,
+    call_frame: &Rc<RefCell<Vec<Frame>>>,
+) -> Result<String, Box<dyn ErrTrait>> {
+    if let Value::Instance(instance) = val {
+        if let Some(method) = instance.class().get_method("toString".to_string()) {
+            let text = Call::invoke(
+                Value::Method(Method::new(method, instance.clone())),
+                Vec::new(),
+                stack.clone(),
+                env.clone(),
+                call_frame.clone(),
+            )?;
+            return match text {
+                Value::String(text) => Ok(text.to_string()),
+                text => Err(Box::new(ValueErr::new(
+                    format!(
+                        "`{}.toString` has to return a String, it returned {}",
+                        instance.name(),
+                        text
+                    ),
+                    format!("{}.toString()", instance.name()),
+                ))),
+            };
+        }
+    }
+    Ok(format!("{}", val))
+}
+
 impl InstructionBase for Print {
     fn eval(
         &self,
-        stack: Rc<RefCell<Vec<Value>>>,
-        _: Rc<RefCell<Table>>,
-        _: Rc<RefCell<Vec<String>>>,
+        stack: &Rc<RefCell<Stack>>,
+        env: &Rc<RefCell<Table>>,
+        call_frame: &Rc<RefCell<Vec<Frame>>>,
         _: usize,
-        _: Rc<RefCell<Vec<UpValue>>>,
+        _: &Rc<RefCell<Vec<UpValue>>>,
         _: usize,
         _: usize,
-    ) -> Result<usize, Box<dyn ErrTrait>> {
-        println!("{}", stack.borrow_mut().pop().unwrap());
+    ) -> Result<isize, Box<dyn ErrTrait>> {
+        let val = stack.borrow_mut().pop().unwrap();
+        let text = printed(&val, stack, env, call_frame)?;
+        print_line(&text).map_err(|err| {
+            Box::new(InstructionErr::new(
+                format!("Failed to write output: {}", err),
+                format!("print {}", val),
+            )) as Box<dyn ErrTrait>
+        })?;
         Ok(0)
     }
 
@@ -53,3 +105,48 @@ impl Display for Print {
         write!(f, "{:?}", self.code)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::vm::vm::VM;
+
+    #[test]
+    fn test_to_string_hook() {
+        let src = "class Point {
+    __init__(x, y) { this.x = x; this.y = y; }
+    toString() { return \"(\" + str(this.x) + \", \" + str(this.y) + \")\"; }
+}
+class Plain {}
+var p = Point(1, 2);
+print p;
+print Plain();
+print \"as str: \" + str(p);
+print \"called: \" + p.toString();
+";
+        let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+        VM::interprate_to(src.as_bytes().to_vec(), VM::globals(), output.clone()).unwrap();
+        let printed = String::from_utf8(output.borrow().clone()).unwrap();
+        assert_eq!(
+            printed,
+            "(1, 2)\n<Plain instance>\n\"as str: (1, 2)\"\n\"called: (1, 2)\"\n"
+        );
+    }
+
+    #[test]
+    fn test_to_string_must_return_string() {
+        let src = "class Point {
+    toString() { return 1; }
+}
+print Point();
+";
+        let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+        let err =
+            VM::interprate_to(src.as_bytes().to_vec(), VM::globals(), output.clone()).unwrap_err();
+        assert!(
+            format!("{}", err).contains("`Point.toString` has to return a String, it returned 1")
+        );
+        assert!(output.borrow().is_empty());
+    }
+}
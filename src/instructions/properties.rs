@@ -5,10 +5,14 @@ use std::{
 };
 
 use crate::{
-    compiler::compiler::UpValue, errors::err::ErrTrait, values::values::Value, vm::table::Table,
+    compiler::compiler::UpValue,
+    errors::err::ErrTrait,
+    values::{func::Frame, intern::Name, values::Value},
+    vm::{natives::builtin_method, stack::Stack, table::Table},
 };
 
 use super::{
+    call::Call,
     define::DefinitionScope,
     err::InstructionErr,
     instructions::{InstructionBase, InstructionType},
@@ -35,14 +39,14 @@ impl Set {
 impl InstructionBase for Set {
     fn eval(
         &self,
-        stack: Rc<RefCell<Vec<Value>>>,
-        _: Rc<RefCell<Table>>,
-        _: Rc<RefCell<Vec<String>>>,
+        stack: &Rc<RefCell<Stack>>,
+        _: &Rc<RefCell<Table>>,
+        _: &Rc<RefCell<Vec<Frame>>>,
         _: usize,
-        _: Rc<RefCell<Vec<UpValue>>>,
+        _: &Rc<RefCell<Vec<UpValue>>>,
         _: usize,
         _: usize,
-    ) -> Result<usize, Box<dyn ErrTrait>> {
+    ) -> Result<isize, Box<dyn ErrTrait>> {
         let val = (*stack).borrow_mut().pop().unwrap();
         let inst = (*stack).borrow_mut().pop().unwrap();
         match inst {
@@ -105,18 +109,29 @@ impl Get {
 impl InstructionBase for Get {
     fn eval(
         &self,
-        stack: Rc<RefCell<Vec<Value>>>,
-        _: Rc<RefCell<Table>>,
-        _: Rc<RefCell<Vec<String>>>,
+        stack: &Rc<RefCell<Stack>>,
+        env: &Rc<RefCell<Table>>,
+        call_frame: &Rc<RefCell<Vec<Frame>>>,
         _: usize,
-        _: Rc<RefCell<Vec<UpValue>>>,
+        _: &Rc<RefCell<Vec<UpValue>>>,
         _: usize,
         _: usize,
-    ) -> Result<usize, Box<dyn ErrTrait>> {
+    ) -> Result<isize, Box<dyn ErrTrait>> {
         let inst = (*stack).borrow_mut().pop().unwrap();
         match inst {
             Value::Instance(instance) => {
                 match instance.get_prop(self.property.clone(), instance.clone()) {
+                    // getters run as soon as they're read
+                    Some(Value::Method(method)) if method.func.is_getter() => {
+                        let val = Call::invoke(
+                            Value::Method(method),
+                            Vec::new(),
+                            stack.clone(),
+                            env.clone(),
+                            call_frame.clone(),
+                        )?;
+                        (*stack).borrow_mut().push(val);
+                    }
                     Some(val) => {
                         (*stack).borrow_mut().push(val);
                     }
@@ -155,19 +170,24 @@ Line {}: {}
                     )));
                 }
             },
-            _ => {
-                return Err(Box::new(InstructionErr::new(
-                    format!(
-                        "
+            _ => match builtin_method(&inst, &self.property) {
+                Some(method) => {
+                    (*stack).borrow_mut().push(method);
+                }
+                None => {
+                    return Err(Box::new(InstructionErr::new(
+                        format!(
+                            "
 Line {}: {}
           ^
-          -------- Property accesses only supported for classes & instances not `{}`
+          -------- Property accesses only supported for classes, instances & builtin methods not `{}`
 ",
-                        self.line, self.line_contents, inst
-                    ),
-                    format!("{}.{}", inst, self.property),
-                )));
-            }
+                            self.line, self.line_contents, inst
+                        ),
+                        format!("{}.{}", inst, self.property),
+                    )));
+                }
+            },
         }
         Ok(0)
     }
@@ -191,7 +211,7 @@ impl Display for Get {
 
 pub struct Inherit {
     code: InstructionType,
-    ident: String,
+    ident: Name,
     target: DefinitionScope,
     line: usize,
     line_contents: String,
@@ -201,7 +221,7 @@ impl Inherit {
     pub fn new(target: DefinitionScope, ident: String, line: usize, line_contents: String) -> Self {
         Inherit {
             code: InstructionType::OP_INHERIT,
-            ident,
+            ident: Name::new(&ident),
             target,
             line,
             line_contents,
@@ -212,19 +232,19 @@ impl Inherit {
 impl InstructionBase for Inherit {
     fn eval(
         &self,
-        stack: Rc<RefCell<Vec<Value>>>,
-        globals: Rc<RefCell<Table>>,
-        _: Rc<RefCell<Vec<String>>>,
+        stack: &Rc<RefCell<Stack>>,
+        globals: &Rc<RefCell<Table>>,
+        _: &Rc<RefCell<Vec<Frame>>>,
         offset: usize,
-        upvalue_stack: Rc<RefCell<Vec<UpValue>>>,
+        upvalue_stack: &Rc<RefCell<Vec<UpValue>>>,
         _: usize,
         _: usize,
-    ) -> Result<usize, Box<dyn ErrTrait>> {
+    ) -> Result<isize, Box<dyn ErrTrait>> {
         let parent = (*stack).borrow_mut().pop().unwrap();
         let child = match self.target {
             DefinitionScope::Global => (*globals).borrow_mut().resolve(&self.ident).unwrap(),
             DefinitionScope::Local(idx) => (*stack).borrow()[idx.saturating_add(offset)].clone(),
-            DefinitionScope::UpValue(idx) => (*upvalue_stack).borrow()[idx].value.clone(),
+            DefinitionScope::UpValue(idx) => (*upvalue_stack).borrow()[idx].get(stack),
         };
         match parent.clone() {
             Value::Class(parent_class) => match child.clone() {
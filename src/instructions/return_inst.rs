@@ -5,7 +5,10 @@ use std::{
 };
 
 use crate::{
-    compiler::compiler::UpValue, errors::err::ErrTrait, values::values::Value, vm::table::Table,
+    compiler::compiler::UpValue,
+    errors::err::ErrTrait,
+    values::func::Frame,
+    vm::{stack::Stack, table::Table},
 };
 
 use super::instructions::{InstructionBase, InstructionType};
@@ -26,14 +29,14 @@ impl Return {
 impl InstructionBase for Return {
     fn eval(
         &self,
-        _: Rc<RefCell<Vec<Value>>>,
-        _: Rc<RefCell<Table>>,
-        call_frame: Rc<RefCell<Vec<String>>>,
+        _: &Rc<RefCell<Stack>>,
+        _: &Rc<RefCell<Table>>,
+        call_frame: &Rc<RefCell<Vec<Frame>>>,
         _: usize,
-        _: Rc<RefCell<Vec<UpValue>>>,
+        _: &Rc<RefCell<Vec<UpValue>>>,
         _: usize,
         _: usize,
-    ) -> Result<usize, Box<dyn ErrTrait>> {
+    ) -> Result<isize, Box<dyn ErrTrait>> {
         (*call_frame).borrow_mut().pop();
         Ok(0)
     }
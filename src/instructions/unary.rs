@@ -5,7 +5,10 @@ use std::{
 };
 
 use crate::{
-    compiler::compiler::UpValue, errors::err::ErrTrait, values::values::Value, vm::table::Table,
+    compiler::compiler::UpValue,
+    errors::err::ErrTrait,
+    values::{func::Frame, values::Value},
+    vm::{stack::Stack, table::Table},
 };
 
 use super::{
@@ -46,14 +49,14 @@ impl Unary {
 impl InstructionBase for Unary {
     fn eval(
         &self,
-        stack: Rc<RefCell<Vec<Value>>>,
-        _: Rc<RefCell<Table>>,
-        _: Rc<RefCell<Vec<String>>>,
+        stack: &Rc<RefCell<Stack>>,
+        _: &Rc<RefCell<Table>>,
+        _: &Rc<RefCell<Vec<Frame>>>,
         _: usize,
-        _: Rc<RefCell<Vec<UpValue>>>,
+        _: &Rc<RefCell<Vec<UpValue>>>,
         _: usize,
         _: usize,
-    ) -> Result<usize, Box<dyn ErrTrait>> {
+    ) -> Result<isize, Box<dyn ErrTrait>> {
         let operand = stack.borrow_mut().pop().unwrap();
         match self.op {
             UnaryOp::Negate => match operand {
@@ -101,7 +104,7 @@ mod tests {
     fn test_negate_display() {
         let mut chunk = Chunk::new();
         chunk
-            .write_to_chunk(Box::new(Unary::new(UnaryOp::Negate)), 1)
+            .write_to_chunk(Unary::new(UnaryOp::Negate), 1)
             .unwrap();
         assert_eq!(format!("{}", chunk), "1  OP_UNARY Negate\n");
         print!("{}", chunk);
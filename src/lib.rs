@@ -0,0 +1,78 @@
+//! The lox interpreter, `Interpreter` is the entry point for running lox
+//! from another Rust program
+use std::{cell::RefCell, rc::Rc};
+
+pub mod args;
+pub mod compiler;
+pub mod errors;
+pub mod instructions;
+pub mod runners;
+pub mod values;
+pub mod vm;
+
+pub use errors::err::ErrTrait;
+pub use values::{func::NativeContext, values::Value};
+
+use vm::{
+    config::{set_config, with_config, Config, STACK_PER_CALL},
+    table::Table,
+    vm::VM,
+};
+
+// the stack std gives a spawned thread, the smallest one `eval` is
+// expected to be called on
+const EMBEDDED_STACK_SIZE: usize = 2 * 1024 * 1024;
+
+/// Runs lox source against globals that persist between calls, so
+/// later sources can use what earlier ones defined
+pub struct Interpreter {
+    globals: Rc<RefCell<Table>>,
+    // lox calls recurse on the caller's stack, so deep recursion has to
+    // error out before it can overflow it
+    max_call_depth: usize,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter {
+            globals: VM::globals(),
+            max_call_depth: EMBEDDED_STACK_SIZE / STACK_PER_CALL,
+        }
+    }
+
+    /// Lets lox calls nest `depth` deep. The default fits a 2MiB stack,
+    /// callers on a larger one can allow about one call per 32KiB of it
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    /// Makes a host closure callable from lox as `name`
+    pub fn register_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        native: impl Fn(&[Value], &NativeContext) -> Result<Value, Box<dyn ErrTrait>> + 'static,
+    ) {
+        VM::register_native(&self.globals, name, arity, native);
+    }
+
+    /// Compiles and runs `src`, giving back the value of its final
+    /// expression statement or nil when it ends with anything else
+    pub fn eval(&mut self, src: &str) -> Result<Value, Box<dyn ErrTrait>> {
+        let func = VM::compile(src.as_bytes().to_vec(), self.globals.clone())?;
+        let config = with_config(|config| config.clone());
+        set_config(Config {
+            max_call_depth: Some(self.max_call_depth),
+            ..config.clone()
+        });
+        let res = VM::new(&func, self.globals.clone()).eval();
+        set_config(config);
+        res
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,65 +1,333 @@
 use crate::errors::err::ErrTrait;
 use crate::errors::ioerr::{InpErr, SrcErr};
-use crate::vm::vm::VM;
-use std::io::stdin;
-use std::path::PathBuf;
+use crate::vm::{
+    config::with_config,
+    output::{self, Output},
+    profile,
+    table::Table,
+    vm::VM,
+};
+use std::cell::RefCell;
+use std::fs;
+use std::io::{stdin, BufRead, Read};
+use std::path::{Path, PathBuf};
 use std::process;
-use std::{fs, io};
+use std::rc::Rc;
+
+// exit codes for a script that failed to compile and one that failed while
+// running, the same ones clox uses
+const COMPILE_ERR_CODE: i32 = 65;
+const RUNTIME_ERR_CODE: i32 = 70;
 
 pub struct SrcRunner {
+    // `-` reads the script from stdin
     path: PathBuf,
+    // given with `--eval`, run instead of reading `path`
+    code: Option<String>,
+    // global function to call once the file has run
+    entry: Option<String>,
 }
 
 impl SrcRunner {
-    pub fn new(path: PathBuf) -> Self {
-        return SrcRunner { path };
+    pub fn new(path: PathBuf, entry: Option<String>) -> Self {
+        return SrcRunner {
+            path,
+            code: None,
+            entry,
+        };
+    }
+
+    /// Runs `code` as if it had been read from a file
+    pub fn from_code(code: String, entry: Option<String>) -> Self {
+        SrcRunner {
+            path: PathBuf::from("<eval>"),
+            code: Some(code),
+            entry,
+        }
     }
 
     pub fn execute(&self) {
-        let src_file = fs::read(self.path.clone()).unwrap_or_else(|_| {
-            (&SrcErr::new(
-                format!("Could not find src file: {}", self.path.to_str().unwrap()),
-                self.path.clone(),
-            ) as &dyn ErrTrait)
-                .raise();
+        let src_file = self.read(stdin().lock()).unwrap_or_else(|err| {
+            err.report();
+            process::exit(1);
+        });
+        let result = self.run(src_file, VM::globals());
+        // the counts are still worth seeing when the script failed part way
+        if with_config(|config| config.profile) {
+            eprint!("{}", profile::report(&profile::take_profile()));
+        }
+        if let Err((err, code)) = result {
+            err.report();
+            process::exit(code);
+        }
+    }
+
+    /// Prints the script's bytecode without running it
+    pub fn dump(&self) {
+        let src_file = self.read(stdin().lock()).unwrap_or_else(|err| {
+            err.report();
             process::exit(1);
         });
-        VM::interprate(src_file).unwrap_or_else(|err| err.raise());
+        match self.disassemble(src_file) {
+            Ok(dump) => print!("{}", dump),
+            Err(err) => {
+                err.report();
+                process::exit(COMPILE_ERR_CODE);
+            }
+        }
+    }
+
+    fn disassemble(&self, src: Vec<u8>) -> Result<String, Box<dyn ErrTrait>> {
+        Ok(VM::compile(src, VM::globals())?.disassembly())
+    }
+
+    fn read(&self, mut stdin: impl Read) -> Result<Vec<u8>, Box<dyn ErrTrait>> {
+        if let Some(code) = &self.code {
+            return Ok(code.clone().into_bytes());
+        }
+        if self.path == Path::new("-") {
+            let mut src = Vec::new();
+            stdin
+                .read_to_end(&mut src)
+                .map_err(|err| Box::new(InpErr::new(err.to_string())) as Box<dyn ErrTrait>)?;
+            return Ok(src);
+        }
+        fs::read(self.path.clone()).map_err(|_| {
+            Box::new(SrcErr::new(
+                format!("Could not find src file: {}", self.path.to_str().unwrap()),
+                self.path.clone(),
+            )) as Box<dyn ErrTrait>
+        })
+    }
+
+    /// Runs the script then its entry, a failure comes back with the code
+    /// the process should exit with
+    fn run(
+        &self,
+        src: Vec<u8>,
+        globals: Rc<RefCell<Table>>,
+    ) -> Result<(), (Box<dyn ErrTrait>, i32)> {
+        let __main__ = VM::compile(src, globals.clone()).map_err(|err| (err, COMPILE_ERR_CODE))?;
+        VM::new(&__main__, globals.clone())
+            .run()
+            .map_err(|err| (err, RUNTIME_ERR_CODE))?;
+        match &self.entry {
+            Some(entry) => VM::call_entry(entry, globals).map_err(|err| (err, RUNTIME_ERR_CODE)),
+            None => Ok(()),
+        }
     }
 }
 
-pub struct InteractiveRunner {}
+pub struct InteractiveRunner {
+    // kept across submissions so earlier definitions stay available
+    globals: Rc<RefCell<Table>>,
+    prompt: String,
+    // shown while a submission spans several lines
+    continuation_prompt: String,
+}
 
 impl InteractiveRunner {
-    pub fn new() -> Self {
-        InteractiveRunner {}
+    pub fn new(prompt: String, continuation_prompt: String) -> Self {
+        InteractiveRunner {
+            globals: VM::globals(),
+            prompt,
+            continuation_prompt,
+        }
     }
 
-    pub fn execute(&self) {
-        let mut line: String = String::new();
+    pub fn execute(&mut self) {
         print!("The Lox Interpreter\n");
+        self.run(stdin().lock(), output::stdout());
+    }
+
+    /// Drops everything defined so far. Each submission runs on its own
+    /// VM so there's no stack or frames to clear beyond the globals
+    fn reset(&mut self) {
+        self.globals = VM::globals();
+    }
+
+    /// Runs a submission, printing the value of a trailing bare expression
+    /// to `output` along with whatever it prints itself
+    fn submit(&self, src: Vec<u8>, output: Output) -> Result<(), Box<dyn ErrTrait>> {
+        let __main__ = VM::compile_submission(src, self.globals.clone())?;
+        VM::with_output(&__main__, self.globals.clone(), output).run()
+    }
+
+    fn run(&mut self, mut input: impl BufRead, output: Output) {
+        let mut line: String = String::new();
         let mut src = String::new();
         loop {
-            if (&src).len() > 0 {
-                print!("...  ");
-            } else {
-                print!(">>>  ");
+            {
+                let mut output = output.borrow_mut();
+                if (&src).len() > 0 {
+                    write!(output, "{}", self.continuation_prompt).expect("write failed!");
+                } else {
+                    write!(output, "{}", self.prompt).expect("write failed!");
+                }
+                output.flush().expect("flush failed!");
             }
-            io::Write::flush(&mut io::stdout()).expect("flush failed!");
-            match stdin().read_line(&mut line) {
+            match input.read_line(&mut line) {
+                Ok(0) => break,
                 Ok(_) => {
-                    if line == "\n" && (&src).len() > 0 {
-                        VM::interprate(Vec::<u8>::from(src.clone()))
-                            .unwrap_or_else(|err| err.raise());
+                    if line.trim() == ":reset" {
+                        self.reset();
                         src.clear();
-                    }
-                    if line != "\n" && line != "\r" {
-                        src = src + &line;
+                        writeln!(output.borrow_mut(), "globals cleared").expect("write failed!");
+                    } else {
+                        if line == "\n" && (&src).len() > 0 {
+                            self.submit(Vec::<u8>::from(src.clone()), output.clone())
+                                .unwrap_or_else(|err| err.report());
+                            src.clear();
+                        }
+                        if line != "\n" && line != "\r" {
+                            src = src + &line;
+                        }
                     }
                     line.clear();
                 }
-                Err(err) => (&InpErr::new(err.to_string()) as &dyn ErrTrait).raise(),
+                Err(err) => (&InpErr::new(err.to_string()) as &dyn ErrTrait).report(),
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::values::values::Value;
+
+    use super::*;
+
+    #[test]
+    fn test_entry_runs_after_loading() {
+        let src = b"var ran = false;\nfun main() { ran = true; }\n".to_vec();
+        let globals = VM::globals();
+        let runner = SrcRunner::new(PathBuf::from("prog.lox"), Some("main".to_string()));
+        runner.run(src.clone(), globals.clone()).unwrap();
+        assert_eq!(
+            (*globals).borrow().resolve(&"ran".into()),
+            Some(Value::Bool(true))
+        );
+
+        let runner = SrcRunner::new(PathBuf::from("prog.lox"), Some("start".to_string()));
+        assert!(runner.run(src, VM::globals()).is_err());
+    }
+
+    fn repl() -> InteractiveRunner {
+        InteractiveRunner::new(">>>  ".to_string(), "...  ".to_string())
+    }
+
+    fn captured(output: &Rc<RefCell<Vec<u8>>>) -> String {
+        String::from_utf8(output.borrow().clone()).unwrap()
+    }
+
+    #[test]
+    fn test_custom_prompts() {
+        let mut runner = InteractiveRunner::new("lox> ".to_string(), "   | ".to_string());
+        let output = Rc::new(RefCell::new(Vec::new()));
+        runner.run("var x = 1;\nvar y = 2;\n\n".as_bytes(), output.clone());
+        assert_eq!(captured(&output), "lox>    |    | lox> ");
+    }
+
+    #[test]
+    fn test_bare_expressions_print() {
+        let mut runner = InteractiveRunner::new("".to_string(), "".to_string());
+        let output = Rc::new(RefCell::new(Vec::new()));
+        runner.run("3 * 4\n\n".as_bytes(), output.clone());
+        assert_eq!(captured(&output), "12\n");
+
+        // statements and expressions ending in `;` stay quiet
+        let output = Rc::new(RefCell::new(Vec::new()));
+        runner.run(
+            "var x = 2;\n\nx + 1;\n\nfun f() { return x; }\n\nf()\n\n".as_bytes(),
+            output.clone(),
+        );
+        assert_eq!(captured(&output), "2\n");
+    }
+
+    #[test]
+    fn test_dump_bytecode() {
+        let src = b"fun add(a, b) {\n    return a + b;\n}\nprint add(1, 2);\n".to_vec();
+        let runner = SrcRunner::new(PathBuf::from("prog.lox"), None);
+        let dump = runner.disassemble(src).unwrap();
+        let opcodes: Vec<&str> = dump
+            .lines()
+            .filter(|line| !line.starts_with("=="))
+            .filter_map(|line| line.split_whitespace().nth(1))
+            .collect();
+        assert_eq!(
+            opcodes,
+            vec![
+                "OP_CONST",
+                "OP_DEFINE",
+                "OP_RESOLVE",
+                "OP_CONST",
+                "OP_CONST",
+                "OP_CALL",
+                "OP_PRINT",
+                "OP_DEFINE",
+                "OP_DEFINE",
+                "OP_RESOLVE",
+                "OP_RESOLVE",
+                "OP_BINARY",
+                "OP_RETURN",
+                "OP_POPN",
+            ]
+        );
+        assert!(dump.starts_with("== __main__ ==\n"));
+        assert!(dump.contains("\n== add ==\n"));
+    }
+
+    #[test]
+    fn test_dash_reads_stdin() {
+        let runner = SrcRunner::new(PathBuf::from("-"), None);
+        let src = runner.read("var piped = 1 + 2;\n".as_bytes()).unwrap();
+        let globals = VM::globals();
+        runner.run(src, globals.clone()).unwrap();
+        assert_eq!(
+            (*globals).borrow().resolve(&"piped".into()),
+            Some(Value::Number(3.0))
+        );
+
+        let runner = SrcRunner::new(PathBuf::from("missing.lox"), None);
+        assert!(runner.read("var piped = 1;\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_globals_persist_across_submissions() {
+        let mut runner = repl();
+        let output = Rc::new(RefCell::new(Vec::new()));
+        runner.run(
+            "var x = 1;\nfun double(n) { return n * 2; }\n\n".as_bytes(),
+            output.clone(),
+        );
+        // a failing submission keeps what was defined before it
+        runner.run("var y = x - \"one\";\n\n".as_bytes(), output.clone());
+        runner.run("var y = double(x) + 1;\n\n".as_bytes(), output.clone());
+        assert_eq!(
+            (*runner.globals).borrow().resolve(&"y".into()),
+            Some(Value::Number(3.0))
+        );
+    }
+
+    #[test]
+    fn test_reset_clears_globals() {
+        let mut runner = repl();
+        let output = Rc::new(RefCell::new(Vec::new()));
+        runner.run("var x = 1;\n\n".as_bytes(), output.clone());
+        assert!((*runner.globals).borrow().exists(&"x".into()));
+
+        runner.run(":reset\n".as_bytes(), output.clone());
+        assert!(!(*runner.globals).borrow().exists(&"x".into()));
+        assert!(captured(&output).contains("globals cleared"));
+
+        // natives are loaded again and new definitions still work
+        runner.run(
+            "var t = clock();\n\n".as_bytes(),
+            Rc::new(RefCell::new(Vec::new())),
+        );
+        assert!(matches!(
+            (*runner.globals).borrow().resolve(&"t".into()),
+            Some(Value::Number(_))
+        ));
+    }
+}
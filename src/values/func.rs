@@ -5,17 +5,37 @@ use std::{
 };
 
 use crate::{
-    compiler::compiler::UpValue, errors::err::ErrTrait, instructions::chunk::Chunk,
-    vm::table::Table,
+    compiler::compiler::UpValue,
+    errors::err::ErrTrait,
+    instructions::{call::Call, chunk::Chunk, instructions::InstructionBase},
+    vm::{config::with_config, profile, stack::Stack, table::Table},
 };
 
 use super::{err::ValueErr, obj::Instance, values::Value};
 
+/// How deep calls can nest when `max_call_depth` isn't set
+pub const MAX_CALL_DEPTH: usize = 255;
+
+/// A call that's in progress, `line` is where it was when an error
+/// unwound through it
+#[derive(Clone)]
+pub struct Frame {
+    pub name: String,
+    pub line: Option<usize>,
+}
+
+impl Frame {
+    pub fn new(name: String) -> Self {
+        Frame { name, line: None }
+    }
+}
+
 pub struct Func {
     arity: usize,
-    pub chunk: Chunk,
+    // a method declared with `get`, called when the property is read
+    getter: bool,
+    pub chunk: Rc<Chunk>,
     name: String,
-    ip: RefCell<usize>,
     upvalues: Rc<RefCell<Vec<UpValue>>>,
     upvalue_offset: usize,
     upvalue_count: usize,
@@ -31,9 +51,9 @@ impl Func {
     ) -> Self {
         Func {
             arity: 0,
-            chunk,
+            getter: false,
+            chunk: Rc::new(chunk),
             name,
-            ip: RefCell::new(0),
             upvalues,
             upvalue_offset,
             upvalue_count,
@@ -44,68 +64,97 @@ impl Func {
         self.name.clone()
     }
 
+    /// A copy of the function sharing its code but with its own upvalues
+    pub fn closure(&self, upvalues: Vec<UpValue>) -> Func {
+        Func {
+            arity: self.arity,
+            getter: self.getter,
+            chunk: self.chunk.clone(),
+            name: self.name.clone(),
+            upvalues: Rc::new(RefCell::new(upvalues)),
+            upvalue_offset: self.upvalue_offset,
+            upvalue_count: self.upvalue_count,
+        }
+    }
+
     pub fn call(
         &self,
-        stack: Rc<RefCell<Vec<Value>>>,
+        stack: Rc<RefCell<Stack>>,
         env: Rc<RefCell<Table>>,
-        call_frame: Rc<RefCell<Vec<String>>>,
+        call_frame: Rc<RefCell<Vec<Frame>>>,
         stack_offset: usize,
     ) -> Result<Value, Box<dyn ErrTrait>> {
-        if (*call_frame).borrow().len() >= 255 {
+        let max_depth = with_config(|config| config.max_call_depth).unwrap_or(MAX_CALL_DEPTH);
+        if (*call_frame).borrow().len() >= max_depth {
             return Err(Box::new(ValueErr::new(
-                "Call stack exceeded".to_string(),
+                format!(
+                    "Call stack exceeded, calling `{}` would nest more than {} calls deep",
+                    self.name, max_depth
+                ),
                 format!("{}(..)", self.name),
             )));
         }
 
-        let pre_exec_ip = *self.ip.borrow();
-        self.ip.replace(0);
-
-        (*call_frame).borrow_mut().push(self.name.clone());
+        // the ip lives on this invocation rather than the `Func`, so a recursive
+        // call can't move it out from under the caller
+        let mut ip = 0;
+        (*call_frame)
+            .borrow_mut()
+            .push(Frame::new(self.name.clone()));
         let call_frame_size = (*call_frame).borrow().len();
 
         let code_len = self.chunk.code.len();
+        // read once per call rather than per instruction
+        let profiling = with_config(|config| config.profile);
         if self.chunk.code.len() > 0 {
             loop {
-                if *self.ip.borrow() >= code_len {
+                if ip >= code_len {
                     break;
                 }
-                let instruction = &self.chunk.code[*self.ip.borrow()];
+                let instruction = &self.chunk.code[ip];
+                if profiling {
+                    profile::record(instruction.disassemble());
+                }
                 match instruction.eval(
-                    stack.clone(),
-                    env.clone(),
-                    call_frame.clone(),
+                    &stack,
+                    &env,
+                    &call_frame,
                     stack_offset,
-                    self.upvalues.clone(),
+                    &self.upvalues,
                     self.upvalue_offset,
                     self.upvalue_count,
                 ) {
-                    Ok(offset) => {
-                        if offset > 0 {
-                            self.ip.replace(offset);
+                    Ok(jump) => {
+                        if jump != 0 {
+                            ip = ip.saturating_add_signed(jump);
                         } else {
-                            self.ip.replace_with(|&mut old| old + 1);
+                            ip += 1;
                         }
                         if (*call_frame).borrow().len() < call_frame_size {
                             // since this is an early return, OP_POPN hasn't run yet, so we need
                             // to do the cleanup here
-                            self.sync_upvalues(stack.clone(), stack_offset);
+                            (*stack).borrow_mut().close_upvalues(stack_offset);
                             let val = Ok((*stack).borrow_mut().pop().unwrap());
                             (*stack).borrow_mut().truncate(stack_offset);
-                            self.ip.replace(pre_exec_ip);
                             return val;
                         }
                     }
-                    Err(err) => {
-                        err.raise();
+                    Err(mut err) => {
+                        // the frames are left as is so the VM can print the trace
+                        let line = self.chunk.lines[ip];
+                        if let Some(frame) = (*call_frame).borrow_mut().get_mut(call_frame_size - 1)
+                        {
+                            frame.line = Some(line);
+                        }
+                        err.at_line(line);
+                        return Err(err);
                     }
                 }
             }
         }
 
-        self.sync_upvalues(stack.clone(), stack_offset);
+        (*stack).borrow_mut().close_upvalues(stack_offset);
         (*call_frame).borrow_mut().pop();
-        self.ip.replace(pre_exec_ip);
         Ok(Value::Nil)
     }
 
@@ -113,23 +162,33 @@ impl Func {
         self.arity
     }
 
+    /// The function's bytecode followed by that of every function, method
+    /// and lazy global compiled inside it
+    pub fn disassembly(&self) -> String {
+        let mut dump = format!("== {} ==\n{}", self.name, self.chunk);
+        for op in &self.chunk.code {
+            let nested = match op.constant() {
+                Some(Value::Func(func)) | Some(Value::Lazy(func)) => vec![func.clone()],
+                Some(Value::Class(class)) => class.methods(),
+                _ => continue,
+            };
+            for func in nested {
+                dump = dump + "\n" + &func.disassembly();
+            }
+        }
+        dump
+    }
+
     pub fn set_arity(&mut self, arity: usize) {
         self.arity = arity
     }
 
-    fn sync_upvalues(&self, stack: Rc<RefCell<Vec<Value>>>, stack_offset: usize) {
-        if self.upvalue_count == 0 {
-            return;
-        }
-        if self.upvalue_count >= (*stack).borrow().len().saturating_sub(stack_offset) {
-            return;
-        }
-        for idx in self.upvalue_offset..self.upvalue_offset + self.upvalue_count {
-            let stack_idx = (*self.upvalues).borrow()[idx].index;
-            let val = (*stack).borrow()[stack_idx.saturating_add(stack_offset)].clone();
+    pub fn is_getter(&self) -> bool {
+        self.getter
+    }
 
-            (*self.upvalues).borrow_mut()[idx].value = val;
-        }
+    pub fn set_getter(&mut self) {
+        self.getter = true
     }
 }
 
@@ -167,25 +226,56 @@ impl PartialEq for Func {
     }
 }
 
+/// Natives get exactly `arity` arguments and hand back their result, the
+/// `Call` instruction takes care of the stack. Being a closure a native can
+/// carry state from the host that registered it
+pub type NativeFn = dyn Fn(&[Value], &NativeContext) -> Result<Value, Box<dyn ErrTrait>>;
+
+/// What a native needs to call back into lox functions passed to it as
+/// arguments
+pub struct NativeContext {
+    pub stack: Rc<RefCell<Stack>>,
+    pub env: Rc<RefCell<Table>>,
+    pub call_frame: Rc<RefCell<Vec<Frame>>>,
+}
+
+impl NativeContext {
+    pub fn call(&self, callee: Value, args: Vec<Value>) -> Result<Value, Box<dyn ErrTrait>> {
+        Call::invoke(
+            callee,
+            args,
+            self.stack.clone(),
+            self.env.clone(),
+            self.call_frame.clone(),
+        )
+    }
+}
+
 pub struct Native {
     name: String,
     arity: usize,
-    call_: Box<fn(Rc<RefCell<Vec<Value>>>) -> Result<(), Box<dyn ErrTrait>>>,
+    // trailing arguments that can be left out
+    optional: usize,
+    call_: Box<NativeFn>,
 }
 
 impl Native {
-    pub fn new(
-        name: String,
-        arity: usize,
-        call: Box<fn(Rc<RefCell<Vec<Value>>>) -> Result<(), Box<dyn ErrTrait>>>,
-    ) -> Self {
+    pub fn new(name: String, arity: usize, call: Box<NativeFn>) -> Self {
         Native {
             name,
             arity,
+            optional: 0,
             call_: call,
         }
     }
 
+    /// Lets callers leave off up to `optional` arguments after the
+    /// required ones
+    pub fn with_optional(mut self, optional: usize) -> Self {
+        self.optional = optional;
+        self
+    }
+
     pub fn name(&self) -> String {
         self.name.clone()
     }
@@ -194,8 +284,32 @@ impl Native {
         self.arity
     }
 
-    pub fn call(&self, stack: Rc<RefCell<Vec<Value>>>) -> Result<(), Box<dyn ErrTrait>> {
-        (*self.call_)(stack.clone())
+    pub fn accepts(&self, args_len: usize) -> bool {
+        args_len >= self.arity && args_len <= self.arity.saturating_add(self.optional)
+    }
+
+    /// The argument count(s) the native takes, for error messages
+    pub fn arity_text(&self) -> String {
+        match self.optional {
+            0 => format!("{}", self.arity),
+            usize::MAX => format!("{} or more", self.arity),
+            optional => format!("{} to {}", self.arity, self.arity + optional),
+        }
+    }
+
+    pub fn call(
+        &self,
+        args: &[Value],
+        stack: Rc<RefCell<Stack>>,
+        env: Rc<RefCell<Table>>,
+        call_frame: Rc<RefCell<Vec<Frame>>>,
+    ) -> Result<Value, Box<dyn ErrTrait>> {
+        let ctx = NativeContext {
+            stack,
+            env,
+            call_frame,
+        };
+        (*self.call_)(args, &ctx)
     }
 }
 
@@ -207,12 +321,11 @@ impl Debug for Native {
 {}
 <fn {}>
 {}
-{:?}
+<native code>
 ",
             "-".repeat(self.name.len() + 4),
             self.name,
             "-".repeat(self.name.len() + 4),
-            self.call_
         )
     }
 }
@@ -246,14 +359,15 @@ impl Method {
 
     pub fn call(
         &self,
-        stack: Rc<RefCell<Vec<Value>>>,
+        stack: Rc<RefCell<Stack>>,
         env: Rc<RefCell<Table>>,
-        call_frame: Rc<RefCell<Vec<String>>>,
+        call_frame: Rc<RefCell<Vec<Frame>>>,
         stack_offset: usize,
     ) -> Result<Value, Box<dyn ErrTrait>> {
+        // the instance becomes the method's first local, `this`
         (*stack)
             .borrow_mut()
-            .push(Value::Instance(self.instance.clone()));
-        return self.func.call(stack, env, call_frame, stack_offset - 1);
+            .insert(stack_offset, Value::Instance(self.instance.clone()));
+        return self.func.call(stack, env, call_frame, stack_offset);
     }
 }
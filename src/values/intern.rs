@@ -0,0 +1,181 @@
+//! Pool of string contents shared by every identifier, and by every
+//! `Value::String` made while interning is switched on. Equal strings end
+//! up pointing at the same allocation so comparing them is a pointer check.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashSet,
+    fmt::{Debug, Display},
+    hash::{Hash, Hasher},
+    ops::Deref,
+    rc::Rc,
+};
+
+use crate::vm::config::with_config;
+
+// the pool is swept once it holds this many strings
+const MIN_SWEEP_AT: usize = 1024;
+
+thread_local! {
+    static POOL: RefCell<HashSet<Rc<str>>> = RefCell::new(HashSet::new());
+    static SWEEP_AT: Cell<usize> = const { Cell::new(MIN_SWEEP_AT) };
+}
+
+/// Storage for `text`, shared with every other live string holding the
+/// same contents when interning is on
+pub fn intern(text: &str) -> Rc<str> {
+    if !with_config(|config| config.intern_strings) {
+        return Rc::from(text);
+    }
+    pooled(text)
+}
+
+fn pooled(text: &str) -> Rc<str> {
+    POOL.with(|pool| {
+        if let Some(shared) = pool.borrow().get(text) {
+            return shared.clone();
+        }
+        let mut pool = pool.borrow_mut();
+        if pool.len() >= SWEEP_AT.with(Cell::get) {
+            // strings only the pool still points at aren't used anywhere
+            pool.retain(|shared| Rc::strong_count(shared) > 1);
+            SWEEP_AT.with(|at| at.set(MIN_SWEEP_AT.max(pool.len() * 2)));
+        }
+        let shared: Rc<str> = Rc::from(text);
+        pool.insert(shared.clone());
+        shared
+    })
+}
+
+/// An interned identifier. Every `Name` for the same text shares one
+/// allocation, so globals are hashed and compared by address rather than
+/// by reading the characters
+#[derive(Clone)]
+pub struct Name(Rc<str>);
+
+impl Name {
+    pub fn new(text: &str) -> Self {
+        Name(pooled(text))
+    }
+}
+
+impl From<&str> for Name {
+    fn from(text: &str) -> Self {
+        Name::new(text)
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Name {}
+
+impl Hash for Name {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (Rc::as_ptr(&self.0) as *const u8).hash(state)
+    }
+}
+
+impl Deref for Name {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for Name {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&*self.0, f)
+    }
+}
+
+impl Debug for Name {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&*self.0, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::Name;
+    use crate::{
+        values::values::Value,
+        vm::{
+            config::{set_config, Config},
+            table::Table,
+            vm::VM,
+        },
+    };
+
+    fn strings(src: &str, intern_strings: bool) -> (Rc<str>, Rc<str>, Rc<str>) {
+        set_config(Config {
+            intern_strings,
+            ..Config::default()
+        });
+        let globals = Rc::new(RefCell::new(Table::new()));
+        let func = VM::compile(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        VM::new(&func, globals.clone()).run().unwrap();
+        set_config(Config::default());
+
+        let global = |name: &str| match (*globals).borrow().resolve(&name.into()) {
+            Some(Value::String(text)) => text,
+            val => panic!("expected a string for {}, found {:?}", name, val),
+        };
+        (global("a"), global("b"), global("c"))
+    }
+
+    #[test]
+    fn test_equal_strings_share_storage() {
+        let src = "var a = \"lox\";\nvar b = \"lox\";\nvar c = \"l\" + \"ox\";\n";
+
+        let (a, b, c) = strings(src, true);
+        assert!(Rc::ptr_eq(&a, &b));
+        assert!(Rc::ptr_eq(&a, &c));
+
+        let (a, b, c) = strings(src, false);
+        assert_eq!(a, b);
+        assert_eq!(a, c);
+        assert!(!Rc::ptr_eq(&a, &b));
+        assert!(!Rc::ptr_eq(&a, &c));
+    }
+
+    #[test]
+    fn test_names_resolve_the_same_globals() {
+        let built = ["pa", "ir"].concat();
+        let name = Name::new("pair");
+        assert!(name == Name::new(&built));
+        assert!(name != Name::new("pairs"));
+        assert_eq!(&*name, "pair");
+
+        // names are interned whether or not strings are
+        for intern_strings in [true, false] {
+            set_config(Config {
+                intern_strings,
+                ..Config::default()
+            });
+            let globals = Rc::new(RefCell::new(Table::new()));
+            let func = VM::compile(
+                b"var pair = 1;\nvar other = pair + 1;\npair = other * 10;\n".to_vec(),
+                globals.clone(),
+            )
+            .unwrap();
+            VM::new(&func, globals.clone()).run().unwrap();
+            set_config(Config::default());
+
+            let globals = globals.borrow();
+            assert_eq!(
+                globals.resolve(&Name::new(&built)),
+                Some(Value::Number(20.0))
+            );
+            assert_eq!(globals.resolve(&"pair".into()), Some(Value::Number(20.0)));
+            assert_eq!(globals.resolve(&"other".into()), Some(Value::Number(2.0)));
+            assert_eq!(globals.resolve(&"missing".into()), None);
+        }
+    }
+}
@@ -1,4 +1,5 @@
-mod err;
+pub mod err;
 pub mod func;
+pub mod intern;
 pub mod obj;
 pub mod values;
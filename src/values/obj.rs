@@ -13,6 +13,7 @@ use super::{
 pub struct Class {
     name: String,
     methods: Rc<RefCell<HashMap<String, Rc<Func>>>>,
+    parent: RefCell<Option<Rc<Class>>>,
 }
 
 impl Class {
@@ -20,6 +21,7 @@ impl Class {
         Class {
             name,
             methods: Rc::new(RefCell::new(HashMap::new())),
+            parent: RefCell::new(None),
         }
     }
 
@@ -36,6 +38,13 @@ impl Class {
         None
     }
 
+    /// The class's own methods sorted by name
+    pub fn methods(&self) -> Vec<Rc<Func>> {
+        let mut methods: Vec<Rc<Func>> = (*self.methods).borrow().values().cloned().collect();
+        methods.sort_by_key(|method| method.name());
+        methods
+    }
+
     pub fn inherit(&self, parent: Rc<Class>) {
         for method in (*(*parent).methods).borrow_mut().iter() {
             let contains_key = self.methods.borrow().contains_key(method.0);
@@ -45,6 +54,23 @@ impl Class {
                     .insert(method.0.clone(), method.1.clone());
             }
         }
+        *self.parent.borrow_mut() = Some(parent);
+    }
+
+    /// The class this one inherits from, set by `inherit`
+    pub fn superclass(&self) -> Option<Rc<Class>> {
+        self.parent.borrow().clone()
+    }
+
+    /// Whether this is `class` or inherits from it somewhere up the chain
+    pub fn is_a(&self, class: &Class) -> bool {
+        if std::ptr::eq(self, class) {
+            return true;
+        }
+        match self.superclass() {
+            Some(parent) => parent.is_a(class),
+            None => false,
+        }
     }
 
     pub fn name(&self) -> String {
@@ -114,6 +140,23 @@ impl Instance {
     pub fn name(&self) -> String {
         self.class.name.clone()
     }
+
+    /// The class the instance was made from
+    pub fn class(&self) -> Rc<Class> {
+        self.class.clone()
+    }
+
+    /// The fields set on the instance sorted by name, methods aren't included
+    pub fn fields(&self) -> Vec<(String, Value)> {
+        let mut fields: Vec<(String, Value)> = self
+            .fields
+            .borrow()
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        fields.sort_by(|a, b| a.0.cmp(&b.0));
+        fields
+    }
 }
 
 impl Debug for Instance {
@@ -124,7 +167,7 @@ impl Debug for Instance {
 
 impl Display for Instance {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "<Class {}>", self.class.name)
+        write!(f, "<{} instance>", self.class.name)
     }
 }
 
@@ -1,4 +1,6 @@
 use std::{
+    cell::RefCell,
+    collections::HashMap,
     fmt::{Debug, Display},
     rc::Rc,
 };
@@ -11,10 +13,19 @@ use super::{
     obj::{Class, Instance},
 };
 
+/// The largest integer an f64 holds exactly, every integer past it may
+/// be rounded to a neighbour
+pub const MAX_SAFE_INTEGER: f64 = 9007199254740991.0;
+
+/// Whether `number` is beyond the integers an f64 can hold exactly
+pub fn unsafe_int(number: f64) -> bool {
+    number.is_finite() && number.abs() > MAX_SAFE_INTEGER
+}
+
 #[derive(PartialEq, Clone)]
 pub enum Value {
     Number(f64),
-    String(String),
+    String(Rc<str>),
     Nil,
     Bool(bool),
     Func(Rc<Func>),
@@ -23,6 +34,13 @@ pub enum Value {
     Method(Method),
     Class(Rc<Class>),
     Instance(Rc<Instance>),
+    // global initializer that runs on first resolve
+    Lazy(Rc<Func>),
+    Array(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<HashMap<String, Value>>>),
+    StringBuilder(Rc<RefCell<String>>),
+    // native bound to the value it was looked up on, e.g. `builder.append`
+    NativeMethod(Rc<Native>, Box<Value>),
 }
 
 impl Value {
@@ -40,6 +58,13 @@ impl Value {
     }
 }
 
+/// A map's entries ordered by key, so printing one is deterministic
+fn sorted_entries(entries: &HashMap<String, Value>) -> Vec<(&String, &Value)> {
+    let mut sorted: Vec<(&String, &Value)> = entries.iter().collect();
+    sorted.sort_by(|a, b| a.0.cmp(b.0));
+    sorted
+}
+
 impl Debug for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let str = match self {
@@ -49,7 +74,7 @@ impl Debug for Value {
                 true => format!("<Boolean {}>", String::from("true")),
                 false => format!("<Boolean {}>", String::from("false")),
             },
-            Value::String(val) => format!("<String {}>", val.to_owned()),
+            Value::String(val) => format!("<String {}>", val),
             Value::Func(func) => format!("<Fun {}>", (*func).name()),
             Value::ClassMethod(func) => format!("<Fun {}>", (*func).name()),
             Value::Native(func) => format!("<Native Fun {}>", (*func).name()),
@@ -60,6 +85,28 @@ impl Debug for Value {
             ),
             Value::Class(class) => format!("<Class {}>", (*class).name()),
             Value::Instance(instance) => format!("<Instance {}>", (*instance).name()),
+            Value::Lazy(func) => format!("<Lazy {}>", (*func).name()),
+            Value::Array(items) => format!(
+                "<Array [{}]>",
+                (*items)
+                    .borrow()
+                    .iter()
+                    .map(|item| format!("{:?}", item))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Value::Map(entries) => format!(
+                "<Map {{{}}}>",
+                sorted_entries(&(*entries).borrow())
+                    .iter()
+                    .map(|(key, val)| format!("{:?}: {:?}", key, val))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Value::StringBuilder(buffer) => format!("<StringBuilder {:?}>", (*buffer).borrow()),
+            Value::NativeMethod(func, receiver) => {
+                format!("<Native Method {} @{:?}>", (*func).name(), receiver)
+            }
         };
 
         write!(f, "{}", str)
@@ -75,7 +122,7 @@ impl Display for Value {
                 true => String::from("true"),
                 false => String::from("false"),
             },
-            Value::String(val) => format!("\"{}\"", val.to_owned()),
+            Value::String(val) => format!("\"{}\"", val),
             Value::Func(func) => format!("<Fun {}>", (*func).name()),
             Value::ClassMethod(func) => format!("<Fun {}>", (*func).name()),
             Value::Native(func) => format!("<Native Fun {}>", (*func).name()),
@@ -85,9 +132,73 @@ impl Display for Value {
                 method.instance.name()
             ),
             Value::Class(class) => format!("<Class {}>", (*class).name()),
-            Value::Instance(instance) => format!("<Instance {}>", (*instance).name()),
+            Value::Instance(instance) => format!("{}", instance),
+            Value::Lazy(func) => format!("<Lazy {}>", (*func).name()),
+            Value::Array(items) => format!(
+                "[{}]",
+                (*items)
+                    .borrow()
+                    .iter()
+                    .map(|item| format!("{}", item))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Value::Map(entries) => format!(
+                "{{{}}}",
+                sorted_entries(&(*entries).borrow())
+                    .iter()
+                    .map(|(key, val)| format!("\"{}\": {}", key, val))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Value::StringBuilder(buffer) => {
+                format!(
+                    "<StringBuilder {} chars>",
+                    (*buffer).borrow().chars().count()
+                )
+            }
+            Value::NativeMethod(func, receiver) => {
+                format!("<Native Method {} @{}>", (*func).name(), receiver)
+            }
         };
 
         write!(f, "{}", str)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_class_and_instance_display() {
+        let class = Rc::new(Class::new("Point".to_string()));
+        let instance = Value::Instance(Rc::new(Instance::new(class.clone())));
+        assert_eq!(format!("{}", Value::Class(class)), "<Class Point>");
+        assert_eq!(format!("{}", instance), "<Point instance>");
+    }
+
+    #[test]
+    fn test_number_display() {
+        for (number, expected) in [
+            (4.0, "4"),
+            (-12.0, "-12"),
+            (3.5, "3.5"),
+            (0.1, "0.1"),
+            (1e21, "1000000000000000000000"),
+        ] {
+            assert_eq!(format!("{}", Value::Number(number)), expected);
+        }
+    }
+
+    #[test]
+    fn test_superclass_link() {
+        let parent = Rc::new(Class::new("Animal".to_string()));
+        let child = Class::new("Dog".to_string());
+        assert!(child.superclass().is_none());
+
+        child.inherit(parent.clone());
+        assert!(Rc::ptr_eq(&child.superclass().unwrap(), &parent));
+        assert!(parent.superclass().is_none());
+    }
+}
@@ -0,0 +1,59 @@
+//! Runtime settings picked by the CLI/embedder. Instructions only get
+//! handed the stack, env and frames, so the settings are kept per thread
+//! rather than threaded through every `eval`.
+
+use std::cell::RefCell;
+
+use crate::{errors::err::ErrorFormat, instructions::chunk::OptLevel};
+
+/// Host stack reserved for each nested lox call, with room to spare for
+/// unoptimized builds
+pub const STACK_PER_CALL: usize = 32 * 1024;
+
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// strings built by concatenation can't grow past this many bytes
+    pub max_string_length: Option<usize>,
+    /// scripts may read environment variables through `env`
+    pub allow_env: bool,
+    /// arguments after the script path, handed out by `args`
+    pub script_args: Vec<String>,
+    /// warn when a local hides a variable from an outer scope
+    pub warn_shadowing: bool,
+    /// how errors are written out
+    pub error_format: ErrorFormat,
+    /// the file being run, errors point back at it
+    pub source: Option<String>,
+    /// compiling fails once a chunk would hold more instructions than this
+    pub max_chunk_size: Option<usize>,
+    /// how deep calls can nest before a script errors, 255 when unset
+    pub max_call_depth: Option<usize>,
+    /// warn when a number is too large for an f64 to hold exactly
+    pub warn_unsafe_int: bool,
+    /// warn when a local variable is never read
+    pub warn_unused: bool,
+    /// the passes run over compiled chunks
+    pub ir_optimize: OptLevel,
+    /// equal strings share one allocation
+    pub intern_strings: bool,
+    /// count the instructions executed for a histogram at the end of the run
+    pub profile: bool,
+}
+
+impl Config {
+    pub fn source_name(&self) -> String {
+        self.source.clone().unwrap_or_else(|| "<stdin>".to_string())
+    }
+}
+
+thread_local! {
+    static CONFIG: RefCell<Config> = RefCell::new(Config::default());
+}
+
+pub fn set_config(config: Config) {
+    CONFIG.with(|current| current.replace(config));
+}
+
+pub fn with_config<R>(f: impl FnOnce(&Config) -> R) -> R {
+    CONFIG.with(|current| f(&current.borrow()))
+}
@@ -1,7 +1,97 @@
-use crate::errors::err::ErrTraitBase;
+use std::fmt::{Debug, Display};
 
-pub struct RuntimeErr {}
+use crate::{
+    errors::err::{ErrTrait, ErrTraitBase},
+    values::func::Frame,
+};
+
+pub struct RuntimeErr {
+    message: String,
+}
+
+impl RuntimeErr {
+    pub fn new(message: String) -> Self {
+        RuntimeErr { message }
+    }
+}
 
 impl ErrTraitBase for RuntimeErr {
-    fn raise(&self) {}
+    fn raise(&self) {
+        println!("Runtime Err::: {}", self.message);
+    }
+}
+
+impl Display for RuntimeErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Runtime Err::: {}", self.message)
+    }
+}
+
+impl Debug for RuntimeErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Runtime Err::: {}", self.message)
+    }
+}
+
+/// An error that stopped a script, along with the calls that were running
+/// when it was raised, outermost first
+pub struct TracedErr {
+    err: Box<dyn ErrTrait>,
+    frames: Vec<Frame>,
+}
+
+impl TracedErr {
+    pub fn new(err: Box<dyn ErrTrait>, frames: Vec<Frame>) -> Self {
+        TracedErr { err, frames }
+    }
+}
+
+impl ErrTraitBase for TracedErr {
+    fn raise(&self) {
+        self.err.raise();
+        println!("\nStack Trace: ");
+        println!("-----------------");
+        let mut frames = self.frames.iter().rev().peekable();
+        while let Some(frame) = frames.next() {
+            match frame.line {
+                Some(line) => println!("<Fun {}> at line {}", frame.name, line),
+                None => println!("<Fun {}>", frame.name),
+            }
+            // deep recursion shows up once rather than once per call
+            let mut repeats = 0;
+            while frames
+                .next_if(|next| next.name == frame.name && next.line == frame.line)
+                .is_some()
+            {
+                repeats += 1;
+            }
+            if repeats > 0 {
+                println!("... repeated {} more time(s)", repeats);
+            }
+        }
+    }
+
+    fn position(&self) -> Option<(usize, Option<usize>, String)> {
+        self.err.position()
+    }
+
+    fn grouped(&self) -> Option<&[Box<dyn ErrTrait>]> {
+        self.err.grouped()
+    }
+
+    fn at_line(&mut self, line: usize) {
+        self.err.at_line(line)
+    }
+}
+
+impl Display for TracedErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.err, f)
+    }
+}
+
+impl Debug for TracedErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&self.err, f)
+    }
 }
@@ -0,0 +1,40 @@
+//! Where `read_line` reads from, stdin unless a host swaps in another
+//! reader. Kept per thread like the output
+
+use std::{
+    cell::RefCell,
+    io::{self, BufRead, BufReader},
+    rc::Rc,
+};
+
+pub type Input = Rc<RefCell<dyn BufRead>>;
+
+pub fn stdin() -> Input {
+    Rc::new(RefCell::new(BufReader::new(io::stdin())))
+}
+
+thread_local! {
+    static INPUT: RefCell<Input> = RefCell::new(stdin());
+}
+
+/// Makes `input` the source, handing back the one it replaced
+pub fn set_input(input: Input) -> Input {
+    INPUT.with(|current| current.replace(input))
+}
+
+/// The next line without its line ending, `None` once the input is
+/// exhausted or closed
+pub fn read_line() -> io::Result<Option<String>> {
+    let input = INPUT.with(|current| current.borrow().clone());
+    let mut line = String::new();
+    if input.borrow_mut().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(Some(line))
+}
@@ -1,4 +1,9 @@
+pub mod config;
 mod err;
-mod natives;
+pub mod input;
+pub mod natives;
+pub mod output;
+pub mod profile;
+pub mod stack;
 pub mod table;
 pub mod vm;
@@ -1,30 +1,1402 @@
 use std::{
     cell::RefCell,
     rc::Rc,
-    time::{SystemTime, UNIX_EPOCH},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use crate::values::{func::Native, values::Value};
+use crate::{
+    errors::err::ErrTrait,
+    instructions::{err::InstructionErr, print::printed},
+    values::{
+        err::ValueErr,
+        func::{Native, NativeContext, NativeFn},
+        intern::intern,
+        values::Value,
+    },
+};
 
-use super::table::Table;
+use super::{
+    config::with_config,
+    input::read_line,
+    output::{print_line, print_text},
+    table::Table,
+};
 
 pub fn load_natives(global: Rc<RefCell<Table>>) {
     // add `clock`
-    (*global).borrow_mut().add(
-        "clock".to_string(),
-        Value::Native(Rc::new(Native::new(
-            "clock".to_string(),
-            0,
-            Box::new(|stack| {
-                let start = SystemTime::now();
-                let since_the_epoch = start
-                    .duration_since(UNIX_EPOCH)
-                    .expect("Time went backwards");
-                (*stack)
-                    .borrow_mut()
-                    .push(Value::Number(since_the_epoch.as_millis() as f64));
-                Ok(())
-            }),
+    define_native(&global, "clock", 0, |_, _| {
+        let start = SystemTime::now();
+        let since_the_epoch = start
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards");
+        Ok(Value::Number(since_the_epoch.as_millis() as f64))
+    });
+
+    // add `now`, fractional seconds since the epoch for timing below a millisecond
+    define_native(&global, "now", 0, |_, _| {
+        let since_the_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards");
+        Ok(Value::Number(since_the_epoch.as_secs_f64()))
+    });
+
+    // add `sleep`, blocks for the given number of milliseconds
+    define_native(&global, "sleep", 1, |args, _| {
+        let duration = match &args[0] {
+            Value::Number(ms) if *ms >= 0.0 => Duration::try_from_secs_f64(ms / 1000.0)
+                .map_err(|_| format!("sleep: {} milliseconds is too long to sleep for", ms)),
+            arg => Err(format!(
+                "sleep: expected a non-negative number of milliseconds, not {}",
+                arg
+            )),
+        };
+        match duration {
+            Ok(duration) => {
+                thread::sleep(duration);
+                Ok(Value::Nil)
+            }
+            Err(message) => Err(Box::new(ValueErr::new(
+                message,
+                format!("sleep({})", args[0]),
+            ))),
+        }
+    });
+
+    // list queries that call back into lox
+    define_native(&global, "find", 2, find);
+    define_native(&global, "min_by", 2, |args, ctx| {
+        extreme_by(args, ctx, "min_by", |key, best| key < best)
+    });
+    define_native(&global, "max_by", 2, |args, ctx| {
+        extreme_by(args, ctx, "max_by", |key, best| key > best)
+    });
+
+    // list accessors, `first`/`last` give nil on an empty list like `find`
+    // does when nothing matches, and `rest` of an empty list is empty
+    define_native(&global, "first", 1, |args, _| {
+        let items = list_arg(&args[0], "first")?;
+        Ok(items.first().cloned().unwrap_or(Value::Nil))
+    });
+    define_native(&global, "last", 1, |args, _| {
+        let items = list_arg(&args[0], "last")?;
+        Ok(items.last().cloned().unwrap_or(Value::Nil))
+    });
+    define_native(&global, "rest", 1, |args, _| {
+        let items = list_arg(&args[0], "rest")?;
+        let rest = items.into_iter().skip(1).collect();
+        Ok(Value::Array(Rc::new(RefCell::new(rest))))
+    });
+
+    // add `zip`, pairs items by index and stops at the shorter list
+    define_native(&global, "zip", 2, |args, _| {
+        let left = list_arg(&args[0], "zip")?;
+        let right = list_arg(&args[1], "zip")?;
+        let pairs = left
+            .into_iter()
+            .zip(right)
+            .map(|(a, b)| Value::Array(Rc::new(RefCell::new(vec![a, b]))))
+            .collect();
+        Ok(Value::Array(Rc::new(RefCell::new(pairs))))
+    });
+
+    // add `print_or`
+    define_native(&global, "print_or", 2, |args, _| {
+        let text = print_or_text(&args[0], &args[1]);
+        print_line(&text).map_err(|err| {
+            Box::new(ValueErr::new(
+                format!("print_or: failed to write output: {}", err),
+                format!("print_or({})", text),
+            )) as Box<dyn ErrTrait>
+        })?;
+        Ok(Value::Nil)
+    });
+
+    // add `write`, prints like the `print` statement minus the newline
+    define_native(&global, "write", 1, |args, ctx| {
+        let text = printed(&args[0], &ctx.stack, &ctx.env, &ctx.call_frame)?;
+        print_text(&text).map_err(|err| {
+            Box::new(ValueErr::new(
+                format!("write: failed to write output: {}", err),
+                format!("write({})", text),
+            )) as Box<dyn ErrTrait>
+        })?;
+        Ok(Value::Nil)
+    });
+
+    // add `read_line`, nil once stdin is exhausted
+    define_native(&global, "read_line", 0, |_, _| match read_line() {
+        Ok(Some(line)) => Ok(Value::String(intern(&line))),
+        Ok(None) => Ok(Value::Nil),
+        Err(err) => Err(Box::new(ValueErr::new(
+            format!("read_line: failed to read input: {}", err),
+            "read_line()".to_string(),
+        ))),
+    });
+
+    // add `str`, the printed form of a value. Strings come back as they
+    // are rather than quoted
+    define_native(&global, "str", 1, |args, ctx| {
+        Ok(match &args[0] {
+            Value::String(text) => Value::String(text.clone()),
+            arg => Value::String(intern(&printed(
+                arg,
+                &ctx.stack,
+                &ctx.env,
+                &ctx.call_frame,
+            )?)),
+        })
+    });
+
+    // add `format`, fills each `{}` in the template with the next argument
+    define_native_variadic(&global, "format", 1, |args, ctx| {
+        let template = match &args[0] {
+            Value::String(template) => template.clone(),
+            arg => {
+                return Err(Box::new(ValueErr::new(
+                    format!("format: expected a String template, not {}", arg),
+                    format!("format({})", arg),
+                )))
+            }
+        };
+        let values = &args[1..];
+        let pieces: Vec<&str> = template.split("{}").collect();
+        if pieces.len() - 1 != values.len() {
+            return Err(Box::new(ValueErr::new(
+                format!(
+                    "format: the template has {} placeholder(s) but {} argument(s) were given",
+                    pieces.len() - 1,
+                    values.len()
+                ),
+                format!("format({})", args[0]),
+            )));
+        }
+        let mut text = pieces[0].to_string();
+        for (value, piece) in values.iter().zip(&pieces[1..]) {
+            match value {
+                Value::String(value) => text.push_str(value),
+                value => text.push_str(&printed(value, &ctx.stack, &ctx.env, &ctx.call_frame)?),
+            }
+            text.push_str(piece);
+        }
+        Ok(Value::String(intern(&text)))
+    });
+
+    // add `substr`, `length` characters from `start`
+    define_native(&global, "substr", 3, substr);
+
+    // add `split` and `join`, `join(split(s, sep), sep)` gives back `s`
+    define_native(&global, "split", 2, split);
+    define_native(&global, "join", 2, join);
+
+    // add `type`, the name of a value's type
+    define_native(&global, "type", 1, |args, _| {
+        let name = match &args[0] {
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Bool(_) => "bool",
+            Value::Nil => "nil",
+            Value::Func(_)
+            | Value::ClassMethod(_)
+            | Value::Native(_)
+            | Value::Method(_)
+            | Value::NativeMethod(_, _)
+            | Value::Lazy(_) => "function",
+            Value::Class(_) => "class",
+            Value::Instance(_) => "instance",
+            Value::Array(_) => "list",
+            Value::Map(_) => "map",
+            Value::StringBuilder(_) => "string_builder",
+        };
+        Ok(Value::String(intern(name)))
+    });
+
+    // add `assert`, fails with the optional message when the condition
+    // is falsy
+    define_native_with_optional(&global, "assert", 1, 1, |args, _| {
+        if args[0].truthy()? {
+            return Ok(Value::Nil);
+        }
+        let message = match args.get(1) {
+            Some(Value::String(text)) => format!("assertion failed: {}", text),
+            Some(message) => format!("assertion failed: {}", message),
+            None => "assertion failed".to_string(),
+        };
+        Err(Box::new(ValueErr::new(
+            message,
+            format!("assert({})", args[0]),
+        )))
+    });
+
+    // add `num`, nil when the string isn't a number so callers can check
+    define_native(&global, "num", 1, |args, _| match &args[0] {
+        Value::String(text) => Ok(text.trim().parse::<f64>().map_or(Value::Nil, Value::Number)),
+        arg => Err(Box::new(ValueErr::new(
+            format!("num: expected a String, not {}", arg),
+            format!("num({})", arg),
         ))),
+    });
+
+    // add `disassemble`, the compiled instructions of a function
+    define_native(&global, "disassemble", 1, |args, _| {
+        let func = match &args[0] {
+            Value::Func(func) | Value::ClassMethod(func) => func.clone(),
+            Value::Method(method) => method.func.clone(),
+            arg => {
+                return Err(Box::new(ValueErr::new(
+                    format!("disassemble: expected a function, not {}", arg),
+                    format!("disassemble({})", arg),
+                )))
+            }
+        };
+        Ok(Value::String(intern(&format!("{}", func.chunk))))
+    });
+
+    // add `auto_parse`
+    define_native(&global, "auto_parse", 1, |args, _| {
+        let parsed = match &args[0] {
+            Value::String(text) => parse_prefixed(text),
+            arg => Err(format!("expected a String, not {}", arg)),
+        };
+        parsed.map(Value::Number).map_err(|message| {
+            Box::new(ValueErr::new(
+                format!("auto_parse: {}", message),
+                format!("auto_parse({})", args[0]),
+            )) as Box<dyn ErrTrait>
+        })
+    });
+
+    // add `fields`, pairs of `[name, value]` for each field an instance has set
+    define_native(&global, "fields", 1, |args, _| {
+        let instance = match &args[0] {
+            Value::Instance(instance) => instance.clone(),
+            arg => {
+                return Err(Box::new(ValueErr::new(
+                    format!("fields: expected an instance, not {}", arg),
+                    format!("fields({})", arg),
+                )))
+            }
+        };
+        let pairs = instance
+            .fields()
+            .into_iter()
+            .map(|(name, value)| {
+                Value::Array(Rc::new(RefCell::new(vec![
+                    Value::String(intern(&name)),
+                    value,
+                ])))
+            })
+            .collect();
+        Ok(Value::Array(Rc::new(RefCell::new(pairs))))
+    });
+
+    // add `class_of`, the class an instance was made from
+    define_native(&global, "class_of", 1, |args, _| match &args[0] {
+        Value::Instance(instance) => Ok(Value::Class(instance.class())),
+        arg => Err(Box::new(ValueErr::new(
+            format!("class_of: expected an instance, not {}", arg),
+            format!("class_of({})", arg),
+        ))),
+    });
+
+    // add `env`, only with `--allow-env` so scripts are sandboxed by default
+    define_native(&global, "env", 1, |args, _| {
+        if !with_config(|config| config.allow_env) {
+            return Err(Box::new(ValueErr::new(
+                "env: reading environment variables is disabled, run with --allow-env".to_string(),
+                format!("env({})", args[0]),
+            )));
+        }
+        let name = match &args[0] {
+            Value::String(name) => name,
+            arg => {
+                return Err(Box::new(ValueErr::new(
+                    format!("env: expected a String, not {}", arg),
+                    format!("env({})", arg),
+                )))
+            }
+        };
+        Ok(match std::env::var(&**name) {
+            Ok(value) => Value::String(intern(&value)),
+            Err(_) => Value::Nil,
+        })
+    });
+
+    // add `args`, the arguments given after the script path
+    define_native(&global, "args", 0, |_, _| {
+        let args = with_config(|config| {
+            config
+                .script_args
+                .iter()
+                .map(|arg| Value::String(intern(arg)))
+                .collect()
+        });
+        Ok(Value::Array(Rc::new(RefCell::new(args))))
+    });
+
+    // add `mod`, the Euclidean remainder, never negative unlike `%` which
+    // keeps the sign of the dividend
+    define_native(&global, "mod", 2, |args, _| {
+        let (dividend, divisor) = (&args[0], &args[1]);
+        match (dividend, divisor) {
+            (Value::Number(_), Value::Number(b)) if *b == 0.0 => Err(Box::new(ValueErr::new(
+                "mod: division by zero".to_string(),
+                format!("mod({}, {})", dividend, divisor),
+            ))),
+            (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a.rem_euclid(*b))),
+            _ => Err(Box::new(ValueErr::new(
+                format!("mod: expected 2 Numbers, not {} and {}", dividend, divisor),
+                format!("mod({}, {})", dividend, divisor),
+            ))),
+        }
+    });
+
+    // math helpers, `sqrt` of a negative is NaN like `f64::sqrt`
+    for (name, op) in [
+        ("sqrt", f64::sqrt as fn(f64) -> f64),
+        ("floor", f64::floor),
+        ("ceil", f64::ceil),
+        ("abs", f64::abs),
+    ] {
+        define_native(&global, name, 1, move |args, _| {
+            Ok(Value::Number(op(number_arg(&args[0], name)?)))
+        });
+    }
+    define_native(&global, "pow", 2, |args, _| {
+        let base = number_arg(&args[0], "pow")?;
+        let exponent = number_arg(&args[1], "pow")?;
+        Ok(Value::Number(base.powf(exponent)))
+    });
+
+    // add `bool`, the same truthiness conditions use: 0, nil and false are
+    // false, every string is true. Values conditions reject (instances,
+    // functions, lists even when empty) are true instead of an error
+    define_native(&global, "bool", 1, |args, _| {
+        Ok(Value::Bool(args[0].truthy().unwrap_or(true)))
+    });
+
+    // add `string_builder`
+    define_native(&global, "string_builder", 0, |_, _| {
+        Ok(Value::StringBuilder(Rc::new(RefCell::new(String::new()))))
+    });
+}
+
+/// Methods on builtin values, looked up by `OP_GET`. The arity counts
+/// the receiver which the native gets as its first argument
+pub fn builtin_method(receiver: &Value, name: &str) -> Option<Value> {
+    let (arity, call): (usize, Box<NativeFn>) = match (receiver, name) {
+        (Value::StringBuilder(_), "append") => (2, Box::new(builder_append)),
+        (Value::StringBuilder(_), "build") => (1, Box::new(builder_build)),
+        (Value::String(_), "chars") => (1, Box::new(string_chars)),
+        (Value::String(_), "bytes") => (1, Box::new(string_bytes)),
+        _ => return None,
+    };
+    Some(Value::NativeMethod(
+        Rc::new(Native::new(name.to_string(), arity, call)),
+        Box::new(receiver.clone()),
+    ))
+}
+
+pub fn define_native(
+    global: &Rc<RefCell<Table>>,
+    name: &str,
+    arity: usize,
+    call: impl Fn(&[Value], &NativeContext) -> Result<Value, Box<dyn ErrTrait>> + 'static,
+) {
+    define_native_with_optional(global, name, arity, 0, call)
+}
+
+/// Like `define_native`, but up to `optional` arguments after the first
+/// `arity` can be left out
+pub fn define_native_with_optional(
+    global: &Rc<RefCell<Table>>,
+    name: &str,
+    arity: usize,
+    optional: usize,
+    call: impl Fn(&[Value], &NativeContext) -> Result<Value, Box<dyn ErrTrait>> + 'static,
+) {
+    (*global).borrow_mut().add(
+        name.into(),
+        Value::Native(Rc::new(
+            Native::new(name.to_string(), arity, Box::new(call)).with_optional(optional),
+        )),
     );
 }
+
+/// Like `define_native`, but any number of arguments can follow the first
+/// `arity`
+pub fn define_native_variadic(
+    global: &Rc<RefCell<Table>>,
+    name: &str,
+    arity: usize,
+    call: impl Fn(&[Value], &NativeContext) -> Result<Value, Box<dyn ErrTrait>> + 'static,
+) {
+    define_native_with_optional(global, name, arity, usize::MAX, call)
+}
+
+/// Reads `0x`/`0b`/`0o` prefixed integers and plain decimals, with an
+/// optional leading `-`. Zero padded decimals like `012` are rejected since
+/// they read as octal in some languages and decimal in others
+fn parse_prefixed(text: &str) -> Result<f64, String> {
+    let trimmed = text.trim();
+    let (sign, digits) = match trimmed.strip_prefix('-') {
+        Some(rest) => (-1.0, rest),
+        None => (1.0, trimmed),
+    };
+    let radix = match digits.get(..2) {
+        Some("0x") | Some("0X") => 16,
+        Some("0b") | Some("0B") => 2,
+        Some("0o") | Some("0O") => 8,
+        _ => 10,
+    };
+
+    if radix != 10 {
+        let body = &digits[2..];
+        if body.is_empty() || !body.chars().all(|c| c.is_digit(radix)) {
+            return Err(format!("`{}` isn't a valid base {} number", text, radix));
+        }
+        return u64::from_str_radix(body, radix)
+            .map(|number| sign * number as f64)
+            .map_err(|err| format!("`{}` {}", text, err));
+    }
+
+    let mut chars = digits.chars();
+    if let (Some('0'), Some(next)) = (chars.next(), chars.next()) {
+        if next.is_ascii_digit() {
+            return Err(format!(
+                "`{}` is ambiguous, leading zeros aren't allowed on decimals",
+                text
+            ));
+        }
+    }
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        return Err(format!("`{}` isn't a valid number", text));
+    }
+    digits
+        .parse::<f64>()
+        .map(|number| sign * number)
+        .map_err(|_| format!("`{}` isn't a valid number", text))
+}
+
+/// Strings as is rather than quoted like the `print` statement shows them
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.to_string(),
+        _ => format!("{}", value),
+    }
+}
+
+/// `print_or(x, default)` prints `default` when `x` is nil
+fn print_or_text(value: &Value, default: &Value) -> String {
+    match value {
+        Value::Nil => text(default),
+        _ => text(value),
+    }
+}
+
+/// `builder.append(x)`: appends in place so building a string in a loop
+/// doesn't copy everything built so far like `+` does
+fn builder_append(args: &[Value], _: &NativeContext) -> Result<Value, Box<dyn ErrTrait>> {
+    let (builder, value) = (&args[0], &args[1]);
+    if let Value::StringBuilder(buffer) = builder {
+        (*buffer).borrow_mut().push_str(&text(value));
+        let len = (*buffer).borrow().len();
+        if let Some(max) = with_config(|config| config.max_string_length) {
+            if len > max {
+                return Err(Box::new(InstructionErr::new(
+                    format!(
+                        "string builder grew to length {} which exceeds the max string length of {}",
+                        len, max
+                    ),
+                    "append(...)".to_string(),
+                )));
+            }
+        }
+    }
+    // handing the builder back allows chaining appends
+    Ok(builder.clone())
+}
+
+fn builder_build(args: &[Value], _: &NativeContext) -> Result<Value, Box<dyn ErrTrait>> {
+    Ok(match &args[0] {
+        Value::StringBuilder(buffer) => Value::String(intern(&(*buffer).borrow())),
+        _ => Value::Nil,
+    })
+}
+
+/// `text.chars()`: a list of one character strings, a multi-byte
+/// character stays whole
+fn string_chars(args: &[Value], _: &NativeContext) -> Result<Value, Box<dyn ErrTrait>> {
+    let chars = match &args[0] {
+        Value::String(text) => text
+            .chars()
+            .map(|c| Value::String(intern(&c.to_string())))
+            .collect(),
+        _ => Vec::new(),
+    };
+    Ok(Value::Array(Rc::new(RefCell::new(chars))))
+}
+
+/// `text.bytes()`: the UTF-8 bytes of the string as numbers
+fn string_bytes(args: &[Value], _: &NativeContext) -> Result<Value, Box<dyn ErrTrait>> {
+    let bytes = match &args[0] {
+        Value::String(text) => text
+            .bytes()
+            .map(|byte| Value::Number(byte as f64))
+            .collect(),
+        _ => Vec::new(),
+    };
+    Ok(Value::Array(Rc::new(RefCell::new(bytes))))
+}
+
+/// `substr(text, start, length)`: counts characters rather than bytes so a
+/// multi-byte character is never split. A length running past the end
+/// stops at the end, a start past the end is an error
+fn substr(args: &[Value], _: &NativeContext) -> Result<Value, Box<dyn ErrTrait>> {
+    let call = || format!("substr({}, {}, {})", args[0], args[1], args[2]);
+    let text = match &args[0] {
+        Value::String(text) => text,
+        arg => {
+            return Err(Box::new(ValueErr::new(
+                format!("substr expects a String, not {}", arg),
+                call(),
+            )))
+        }
+    };
+    let mut bounds = [0; 2];
+    for (bound, (arg, name)) in bounds
+        .iter_mut()
+        .zip([(&args[1], "start"), (&args[2], "length")])
+    {
+        let n = number_arg(arg, "substr")?;
+        if n < 0.0 || n.fract() != 0.0 {
+            return Err(Box::new(ValueErr::new(
+                format!(
+                    "substr: {} must be a non-negative whole number, not {}",
+                    name, n
+                ),
+                call(),
+            )));
+        }
+        *bound = n as usize;
+    }
+    let [start, length] = bounds;
+    let chars = text.chars().count();
+    if start > chars {
+        return Err(Box::new(ValueErr::new(
+            format!(
+                "substr: start {} is past the end of a string of length {}",
+                start, chars
+            ),
+            call(),
+        )));
+    }
+    let slice: String = text.chars().skip(start).take(length).collect();
+    Ok(Value::String(intern(&slice)))
+}
+
+/// `split(text, separator)`: the pieces of `text` between each separator,
+/// an empty separator is rejected since `chars()` already covers that
+fn split(args: &[Value], _: &NativeContext) -> Result<Value, Box<dyn ErrTrait>> {
+    match (&args[0], &args[1]) {
+        (Value::String(_), Value::String(separator)) if separator.is_empty() => {
+            Err(Box::new(ValueErr::new(
+                "split: the separator can't be empty, use `chars()` instead".to_string(),
+                format!("split({}, {})", args[0], args[1]),
+            )))
+        }
+        (Value::String(text), Value::String(separator)) => {
+            let pieces = text
+                .split(&**separator)
+                .map(|piece| Value::String(intern(piece)))
+                .collect();
+            Ok(Value::Array(Rc::new(RefCell::new(pieces))))
+        }
+        (text, separator) => Err(Box::new(ValueErr::new(
+            format!("split: expected 2 Strings, not {} and {}", text, separator),
+            format!("split({}, {})", text, separator),
+        ))),
+    }
+}
+
+/// `join(list, separator)`: the strings in `list` with `separator` between
+/// each pair
+fn join(args: &[Value], _: &NativeContext) -> Result<Value, Box<dyn ErrTrait>> {
+    let items = list_arg(&args[0], "join")?;
+    let separator = match &args[1] {
+        Value::String(separator) => separator,
+        separator => {
+            return Err(Box::new(ValueErr::new(
+                format!("join: expected a String separator, not {}", separator),
+                format!("join({}, {})", args[0], separator),
+            )))
+        }
+    };
+    let mut pieces = Vec::with_capacity(items.len());
+    for item in &items {
+        match item {
+            Value::String(piece) => pieces.push(piece.to_string()),
+            item => {
+                return Err(Box::new(ValueErr::new(
+                    format!("join: the list can only hold Strings, found {}", item),
+                    format!("join({}, {})", args[0], separator),
+                )))
+            }
+        }
+    }
+    Ok(Value::String(intern(&pieces.join(separator))))
+}
+
+/// The items of a list argument
+fn list_arg(list: &Value, name: &str) -> Result<Vec<Value>, Box<dyn ErrTrait>> {
+    match list {
+        Value::Array(items) => Ok((*items).borrow().clone()),
+        _ => Err(Box::new(ValueErr::new(
+            format!("{} expects a list, not {}", name, list),
+            format!("{}({})", name, list),
+        ))),
+    }
+}
+
+/// The value of a number argument
+fn number_arg(number: &Value, name: &str) -> Result<f64, Box<dyn ErrTrait>> {
+    match number {
+        Value::Number(n) => Ok(*n),
+        _ => Err(Box::new(ValueErr::new(
+            format!("{} expects a Number, not {}", name, number),
+            format!("{}({})", name, number),
+        ))),
+    }
+}
+
+/// The `(list, callback)` pair the list query natives take
+fn list_and_callback(args: &[Value], name: &str) -> Result<(Vec<Value>, Value), Box<dyn ErrTrait>> {
+    match &args[0] {
+        Value::Array(items) => Ok(((*items).borrow().clone(), args[1].clone())),
+        list => Err(Box::new(ValueErr::new(
+            format!(
+                "{} expects a list as its first argument, not {}",
+                name, list
+            ),
+            format!("{}(...)", name),
+        ))),
+    }
+}
+
+/// `find(list, pred)`: first element `pred` is truthy for, nil if none is
+fn find(args: &[Value], ctx: &NativeContext) -> Result<Value, Box<dyn ErrTrait>> {
+    let (items, pred) = list_and_callback(args, "find")?;
+    for item in items {
+        if ctx.call(pred.clone(), vec![item.clone()])?.truthy()? {
+            return Ok(item);
+        }
+    }
+    Ok(Value::Nil)
+}
+
+/// Shared body of `min_by`/`max_by`, `replaces` decides if a key beats the
+/// current best. Ties keep the earlier element and an empty list gives nil
+fn extreme_by(
+    args: &[Value],
+    ctx: &NativeContext,
+    name: &str,
+    replaces: fn(f64, f64) -> bool,
+) -> Result<Value, Box<dyn ErrTrait>> {
+    let (items, key_fn) = list_and_callback(args, name)?;
+    let mut best: Option<(f64, Value)> = None;
+    for item in items {
+        let key = match ctx.call(key_fn.clone(), vec![item.clone()])? {
+            Value::Number(key) => key,
+            other => {
+                return Err(Box::new(ValueErr::new(
+                    format!("{} key function must return a Number, not {}", name, other),
+                    format!("{}(...)", name),
+                )))
+            }
+        };
+        best = match best {
+            Some((best_key, _)) if !replaces(key, best_key) => best,
+            _ => Some((key, item)),
+        };
+    }
+    Ok(best.map(|(_, item)| item).unwrap_or(Value::Nil))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::vm::{
+        config::{set_config, Config},
+        input::set_input,
+        stack::Stack,
+        vm::VM,
+    };
+
+    use super::*;
+
+    fn run(src: &str, xs: Vec<Value>) -> Rc<RefCell<Table>> {
+        let globals = Rc::new(RefCell::new(Table::new()));
+        load_natives(globals.clone());
+        (*globals)
+            .borrow_mut()
+            .add("xs".into(), Value::Array(Rc::new(RefCell::new(xs))));
+        let func = VM::compile(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        VM::new(&func, globals.clone()).run().unwrap();
+        globals
+    }
+
+    fn global(globals: &Rc<RefCell<Table>>, name: &str) -> Value {
+        (*globals).borrow().resolve(&name.into()).unwrap()
+    }
+
+    /// Runs `src` against `globals`, returning the error it has to raise
+    fn run_err_in(globals: &Rc<RefCell<Table>>, src: &str) -> String {
+        let func = VM::compile(src.as_bytes().to_vec(), globals.clone()).unwrap();
+        let err = func
+            .call(
+                Rc::new(RefCell::new(Stack::new())),
+                globals.clone(),
+                Rc::new(RefCell::new(Vec::new())),
+                0,
+            )
+            .unwrap_err();
+        format!("{}", err)
+    }
+
+    fn run_err(src: &str) -> String {
+        let globals = Rc::new(RefCell::new(Table::new()));
+        load_natives(globals.clone());
+        run_err_in(&globals, src)
+    }
+
+    #[test]
+    fn test_print_or() {
+        let dash = Value::String("-".into());
+        assert_eq!(print_or_text(&Value::Nil, &dash), "-");
+        assert_eq!(print_or_text(&Value::Number(5.0), &dash), "5");
+        assert_eq!(
+            print_or_text(&Value::String("x".into()), &dash),
+            "x".to_string()
+        );
+
+        let globals = run("var res = print_or(nil, \"-\");\n", vec![]);
+        assert_eq!(global(&globals, "res"), Value::Nil);
+    }
+
+    #[test]
+    fn test_write() {
+        let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+        VM::interprate_to(
+            b"write(\"[\");\nwrite(1 + 1);\nprint \"]\";\n".to_vec(),
+            VM::globals(),
+            output.clone(),
+        )
+        .unwrap();
+        let printed = String::from_utf8(output.borrow().clone()).unwrap();
+        assert_eq!(printed, "\"[\"2\"]\"\n");
+    }
+
+    #[test]
+    fn test_auto_parse() {
+        assert_eq!(parse_prefixed("0xFF"), Ok(255.0));
+        assert_eq!(parse_prefixed("0o17"), Ok(15.0));
+        assert_eq!(parse_prefixed("0b101"), Ok(5.0));
+        assert_eq!(parse_prefixed("42"), Ok(42.0));
+        assert_eq!(parse_prefixed("-0x10"), Ok(-16.0));
+        assert_eq!(parse_prefixed("0.5"), Ok(0.5));
+        for invalid in ["0x", "0xZZ", "0b102", "012", "abc", "", "1e5"] {
+            assert!(parse_prefixed(invalid).is_err(), "{}", invalid);
+        }
+
+        let globals = run("var hex = auto_parse(\"0xFF\");\n", vec![]);
+        assert_eq!(global(&globals, "hex"), Value::Number(255.0));
+    }
+
+    #[test]
+    fn test_fields() {
+        let globals = run(
+            "class Point {
+    area() { return 0; }
+}
+var p = Point();
+p.y = 2;
+p.x = 1;
+var fs = fields(p);
+",
+            vec![],
+        );
+        let pair = |name: &str, value: f64| {
+            Value::Array(Rc::new(RefCell::new(vec![
+                Value::String(name.into()),
+                Value::Number(value),
+            ])))
+        };
+        assert_eq!(
+            global(&globals, "fs"),
+            Value::Array(Rc::new(RefCell::new(vec![pair("x", 1.0), pair("y", 2.0)])))
+        );
+    }
+
+    #[test]
+    fn test_class_of() {
+        let globals = run(
+            "class Point {
+    __init__(x) { this.x = x; }
+}
+var p = Point(1);
+var klass = class_of(p);
+var copy = class_of(p)(2);
+var same = class_of(copy) == Point;
+",
+            vec![],
+        );
+        match (global(&globals, "klass"), global(&globals, "copy")) {
+            (Value::Class(class), Value::Instance(copy)) => {
+                assert_eq!(class.name(), "Point");
+                assert_eq!(copy.name(), "Point");
+                assert_eq!(
+                    copy.get_prop("x".to_string(), copy.clone()),
+                    Some(Value::Number(2.0))
+                );
+            }
+            vals => panic!("expected a class and an instance, found {:?}", vals),
+        }
+        assert_eq!(global(&globals, "same"), Value::Bool(true));
+
+        assert!(run_err("class_of(1);\n").contains("class_of: expected an instance"));
+    }
+
+    #[test]
+    fn test_env() {
+        // reads a variable the process already has, setting one races the
+        // other tests
+        let src = "var set = env(\"PATH\");\nvar unset = env(\"UNSET_XYZ\");\n";
+        let path = std::env::var("PATH").map_or(Value::Nil, |p| Value::String(p.into()));
+
+        assert!(run_err(src).contains("--allow-env"));
+
+        set_config(Config {
+            allow_env: true,
+            ..Config::default()
+        });
+        let globals = run(src, vec![]);
+        assert_eq!(global(&globals, "set"), path);
+        assert_eq!(global(&globals, "unset"), Value::Nil);
+        set_config(Config::default());
+    }
+
+    #[test]
+    fn test_mod() {
+        let globals = run(
+            "var truncated = -7 % 3;\nvar euclidean = mod(-7, 3);\nvar both = mod(7, -3);\n",
+            vec![],
+        );
+        assert_eq!(global(&globals, "truncated"), Value::Number(-1.0));
+        assert_eq!(global(&globals, "euclidean"), Value::Number(2.0));
+        assert_eq!(global(&globals, "both"), Value::Number(1.0));
+    }
+
+    #[test]
+    fn test_read_line() {
+        let previous = set_input(Rc::new(RefCell::new(Cursor::new(
+            b"first line\r\nsecond\nlast".to_vec(),
+        ))));
+        let globals = run(
+            "var a = read_line();\nvar b = read_line();\nvar c = read_line();\nvar d = read_line();\n",
+            vec![],
+        );
+        set_input(previous);
+        assert_eq!(global(&globals, "a"), Value::String("first line".into()));
+        assert_eq!(global(&globals, "b"), Value::String("second".into()));
+        assert_eq!(global(&globals, "c"), Value::String("last".into()));
+        assert_eq!(global(&globals, "d"), Value::Nil);
+    }
+
+    #[test]
+    fn test_str() {
+        let globals = run(
+            "class Point {}\nvar whole = str(3);\nvar fraction = str(2.5);\nvar yes = str(true);\nvar no = str(false);\nvar nothing = str(nil);\nvar text = str(\"hi\");\nvar point = str(Point());\nvar message = \"total: \" + str(nil);\n",
+            vec![],
+        );
+        for (name, expected) in [
+            ("whole", "3"),
+            ("fraction", "2.5"),
+            ("yes", "true"),
+            ("no", "false"),
+            ("nothing", "nil"),
+            ("text", "hi"),
+            ("point", "<Point instance>"),
+            ("message", "total: nil"),
+        ] {
+            assert_eq!(
+                global(&globals, name),
+                Value::String(expected.into()),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_type() {
+        let globals = run(
+            "class A { method() {} }\nfun f() {}\nvar a = A();\nvar number = type(1);\nvar string = type(\"s\");\nvar boolean = type(false);\nvar nothing = type(nil);\nvar function = type(f);\nvar native = type(clock);\nvar method = type(a.method);\nvar klass = type(A);\nvar instance = type(a);\nvar list = type(xs);\nvar builder = type(string_builder());\n",
+            vec![],
+        );
+        for (name, expected) in [
+            ("number", "number"),
+            ("string", "string"),
+            ("boolean", "bool"),
+            ("nothing", "nil"),
+            ("function", "function"),
+            ("native", "function"),
+            ("method", "function"),
+            ("klass", "class"),
+            ("instance", "instance"),
+            ("list", "list"),
+            ("builder", "string_builder"),
+        ] {
+            assert_eq!(
+                global(&globals, name),
+                Value::String(expected.into()),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_num() {
+        let globals = run(
+            "var whole = num(\"42\");\nvar fraction = num(\" -2.5\\n\");\nvar bad = num(\"4 2\");\nvar empty = num(\"\");\n",
+            vec![],
+        );
+        assert_eq!(global(&globals, "whole"), Value::Number(42.0));
+        assert_eq!(global(&globals, "fraction"), Value::Number(-2.5));
+        assert_eq!(global(&globals, "bad"), Value::Nil);
+        assert_eq!(global(&globals, "empty"), Value::Nil);
+
+        assert!(run_err("num(1);\n").contains("num: expected a String"));
+    }
+
+    #[test]
+    fn test_sleep() {
+        let globals = run(
+            "var start = clock();\nvar slept = sleep(20);\nvar elapsed = clock() - start;\n",
+            vec![],
+        );
+        assert_eq!(global(&globals, "slept"), Value::Nil);
+        match global(&globals, "elapsed") {
+            Value::Number(elapsed) => assert!(elapsed >= 20.0, "only slept {}ms", elapsed),
+            val => panic!("expected a number, found {:?}", val),
+        }
+
+        for src in ["sleep(-1);\n", "sleep(\"1\");\n"] {
+            assert!(run_err(src).contains("sleep: expected a non-negative number of milliseconds"));
+        }
+        // too long for a `Duration` is an error rather than a panic
+        for src in ["sleep(1e300);\n", "sleep(1 / 0);\n"] {
+            let err = run_err(src);
+            assert!(
+                err.contains("milliseconds is too long to sleep for"),
+                "{}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_now() {
+        let globals = run(
+            "var first = now();\nvar second = now();\nvar millis = clock();\n",
+            vec![],
+        );
+        let number = |name| match global(&globals, name) {
+            Value::Number(n) => n,
+            val => panic!("expected a number, found {:?}", val),
+        };
+        assert!(number("second") >= number("first"));
+        // seconds rather than `clock`'s milliseconds
+        assert!((number("millis") / 1000.0 - number("first")).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_assert() {
+        let globals = run(
+            "assert(true);\nassert(1, \"numbers are truthy\");\nvar passed = assert(\"x\");\n",
+            vec![],
+        );
+        assert_eq!(global(&globals, "passed"), Value::Nil);
+
+        assert!(run_err("assert(1 > 2);\n").contains("assertion failed"));
+        assert!(run_err("assert(nil, \"needs a value\");\n")
+            .contains("assertion failed: needs a value"));
+        assert!(run_err("assert();\n").contains("Expected 1 to 2 argument"));
+        assert!(run_err("assert(true, 1, 2);\n").contains("Expected 1 to 2 argument"));
+    }
+
+    #[test]
+    fn test_format() {
+        let globals = run(
+            "var filled = format(\"{} + {} = {}\", 1, 2.5, 3.5);\nvar mixed = format(\"[{}] {} {}\", \"text\", nil, [1]);\nvar plain = format(\"no placeholders\");\n",
+            vec![],
+        );
+        assert_eq!(
+            global(&globals, "filled"),
+            Value::String("1 + 2.5 = 3.5".into())
+        );
+        assert_eq!(
+            global(&globals, "mixed"),
+            Value::String("[text] nil [1]".into())
+        );
+        assert_eq!(
+            global(&globals, "plain"),
+            Value::String("no placeholders".into())
+        );
+
+        assert!(run_err("format(\"{} {}\", 1);\n")
+            .contains("the template has 2 placeholder(s) but 1 argument(s) were given"));
+        assert!(run_err("format(\"{}\", 1, 2);\n")
+            .contains("the template has 1 placeholder(s) but 2 argument(s) were given"));
+        assert!(run_err("format(1);\n").contains("format: expected a String template"));
+        assert!(run_err("format();\n").contains("Expected 1 or more argument"));
+    }
+
+    #[test]
+    fn test_substr() {
+        let globals = run(
+            "var word = \"hello world\";\nvar middle = substr(word, 6, 5);\nvar empty = substr(word, 3, 0);\nvar at_end = substr(word, 11, 2);\nvar clamped = substr(word, 9, 10);\nvar multi = substr(\"naïve café\", 2, 6);\n",
+            vec![],
+        );
+        for (name, expected) in [
+            ("middle", "world"),
+            ("empty", ""),
+            ("at_end", ""),
+            ("clamped", "ld"),
+            ("multi", "ïve ca"),
+        ] {
+            assert_eq!(
+                global(&globals, name),
+                Value::String(expected.into()),
+                "{}",
+                name
+            );
+        }
+
+        assert!(run_err("substr(\"abc\", 4, 1);\n")
+            .contains("substr: start 4 is past the end of a string of length 3"));
+        assert!(run_err("substr(\"abc\", -1, 1);\n")
+            .contains("substr: start must be a non-negative whole number"));
+        assert!(run_err("substr(\"abc\", 0, 1.5);\n")
+            .contains("substr: length must be a non-negative whole number"));
+        assert!(run_err("substr(1, 0, 1);\n").contains("substr expects a String"));
+    }
+
+    #[test]
+    fn test_split_join() {
+        let globals = run(
+            "var line = \"a::b::::c\";\nvar pieces = split(line, \"::\");\nvar third = pieces[2];\nvar rejoined = join(pieces, \"::\");\nvar unsplit = split(\"abc\", \",\");\nvar empty = join([], \", \");\n",
+            vec![],
+        );
+        match global(&globals, "pieces") {
+            Value::Array(items) => assert_eq!((*items).borrow().len(), 4),
+            val => panic!("expected a list, found {:?}", val),
+        }
+        assert_eq!(global(&globals, "third"), Value::String("".into()));
+        assert_eq!(
+            global(&globals, "rejoined"),
+            Value::String("a::b::::c".into())
+        );
+        match global(&globals, "unsplit") {
+            Value::Array(items) => {
+                assert_eq!(*(*items).borrow(), vec![Value::String("abc".into())])
+            }
+            val => panic!("expected a list, found {:?}", val),
+        }
+        assert_eq!(global(&globals, "empty"), Value::String("".into()));
+
+        assert!(run_err("join([\"a\", 1], \",\");\n")
+            .contains("join: the list can only hold Strings, found 1"));
+        assert!(run_err("split(\"abc\", \"\");\n").contains("the separator can't be empty"));
+        assert!(run_err("split(1, \",\");\n").contains("split: expected 2 Strings"));
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let globals = run(
+            "fun add(a, b) { return a + b; }\nvar code = disassemble(add);\n",
+            vec![],
+        );
+        match global(&globals, "code") {
+            Value::String(code) => {
+                assert!(code.contains("OP_RESOLVE"), "{}", code);
+                assert!(code.contains("OP_RETURN"), "{}", code);
+            }
+            val => panic!("expected a String, found {}", val),
+        }
+
+        assert!(run_err("disassemble(1);\n").contains("disassemble: expected a function"));
+    }
+
+    #[test]
+    fn test_math() {
+        let globals = run(
+            "var root = sqrt(16);\nvar nan = sqrt(-1);\nvar down = floor(-2.5);\nvar up = ceil(2.1);\nvar absolute = abs(-3);\nvar power = pow(2, 10);\n",
+            vec![],
+        );
+        assert_eq!(global(&globals, "root"), Value::Number(4.0));
+        match global(&globals, "nan") {
+            Value::Number(n) => assert!(n.is_nan()),
+            val => panic!("expected NaN, found {}", val),
+        }
+        assert_eq!(global(&globals, "down"), Value::Number(-3.0));
+        assert_eq!(global(&globals, "up"), Value::Number(3.0));
+        assert_eq!(global(&globals, "absolute"), Value::Number(3.0));
+        assert_eq!(global(&globals, "power"), Value::Number(1024.0));
+
+        for src in [
+            "sqrt(\"x\");\n",
+            "floor(nil);\n",
+            "ceil(true);\n",
+            "abs(\"1\");\n",
+            "pow(2, \"x\");\n",
+        ] {
+            assert!(run_err(src).contains("expects a Number"), "{}", src);
+        }
+    }
+
+    #[test]
+    fn test_string_builder() {
+        let globals = run(
+            "var sb = string_builder();\nfor (var i = 0; i < 1000; i = i + 1) { sb.append(\"x\"); }\nvar built = sb.build();\n",
+            vec![],
+        );
+        assert_eq!(
+            global(&globals, "built"),
+            Value::String("x".repeat(1000).into())
+        );
+
+        let globals = run(
+            "var built = string_builder().append(\"a\").append(1).append(nil).build();\n",
+            vec![],
+        );
+        assert_eq!(global(&globals, "built"), Value::String("a1nil".into()));
+    }
+
+    #[test]
+    fn test_string_builder_appends_in_place() {
+        // `+` allocates a fresh string per step, the builder should only
+        // reallocate when its buffer's capacity runs out
+        let builder = Rc::new(RefCell::new(String::new()));
+        let ctx = NativeContext {
+            stack: Rc::new(RefCell::new(Stack::new())),
+            env: Rc::new(RefCell::new(Table::new())),
+            call_frame: Rc::new(RefCell::new(Vec::new())),
+        };
+        let mut reallocations = 0;
+        let mut buffer_ptr = (*builder).borrow().as_ptr();
+        for _ in 0..1000 {
+            let args = [
+                Value::StringBuilder(builder.clone()),
+                Value::String("x".into()),
+            ];
+            builder_append(&args, &ctx).unwrap();
+            if (*builder).borrow().as_ptr() != buffer_ptr {
+                reallocations += 1;
+                buffer_ptr = (*builder).borrow().as_ptr();
+            }
+        }
+        assert_eq!((*builder).borrow().len(), 1000);
+        assert!(reallocations < 20, "{} reallocations", reallocations);
+    }
+
+    #[test]
+    fn test_bool() {
+        let globals = run(
+            "class Point {}
+var zero = bool(0);
+var number = bool(-2.5);
+var empty_string = bool(\"\");
+var none = bool(nil);
+var empty_list = bool(xs);
+var instance = bool(Point());
+var function = bool(clock);
+",
+            vec![],
+        );
+        for (name, expected) in [
+            ("zero", false),
+            ("number", true),
+            ("empty_string", true),
+            ("none", false),
+            ("empty_list", true),
+            ("instance", true),
+            ("function", true),
+        ] {
+            assert_eq!(global(&globals, name), Value::Bool(expected), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_chars_and_bytes() {
+        let list = |items: Vec<Value>| Value::Array(Rc::new(RefCell::new(items)));
+        let strings = |items: &[&str]| {
+            list(
+                items
+                    .iter()
+                    .map(|item| Value::String((*item).into()))
+                    .collect(),
+            )
+        };
+        let numbers = |items: &[u8]| list(items.iter().map(|n| Value::Number(*n as f64)).collect());
+
+        let globals = run(
+            "var ascii_chars = \"abc\".chars();
+var ascii_bytes = \"abc\".bytes();
+var word = \"héllo\";
+var multi_chars = word.chars();
+var multi_bytes = word.bytes();
+var empty = \"\".chars();
+",
+            vec![],
+        );
+        assert_eq!(global(&globals, "ascii_chars"), strings(&["a", "b", "c"]));
+        assert_eq!(global(&globals, "ascii_bytes"), numbers(&[97, 98, 99]));
+        assert_eq!(
+            global(&globals, "multi_chars"),
+            strings(&["h", "é", "l", "l", "o"])
+        );
+        assert_eq!(
+            global(&globals, "multi_bytes"),
+            numbers(&[104, 195, 169, 108, 108, 111])
+        );
+        assert_eq!(global(&globals, "empty"), list(vec![]));
+    }
+
+    #[test]
+    fn test_native_args_and_errors() {
+        let globals = Rc::new(RefCell::new(Table::new()));
+        define_native(&globals, "double", 1, |args, _| match &args[0] {
+            Value::Number(n) => Ok(Value::Number(n * 2.0)),
+            arg => Err(Box::new(ValueErr::new(
+                format!("double: expected a Number, not {}", arg),
+                format!("double({})", arg),
+            ))),
+        });
+        let func = VM::compile(
+            b"var before = 1;\nvar doubled = double(21);\nvar after = 2;\n".to_vec(),
+            globals.clone(),
+        )
+        .unwrap();
+        let stack = Rc::new(RefCell::new(Stack::new()));
+        func.call(
+            stack.clone(),
+            globals.clone(),
+            Rc::new(RefCell::new(Vec::new())),
+            0,
+        )
+        .unwrap();
+        assert_eq!(global(&globals, "doubled"), Value::Number(42.0));
+        // the native's argument is gone, only the globals are left behind
+        assert_eq!(stack.borrow().len(), 3);
+
+        assert!(run_err_in(&globals, "double(\"x\");\n").contains("double: expected a Number"));
+    }
+
+    #[test]
+    fn test_find() {
+        let numbers = vec![Value::Number(1.0), Value::Number(5.0), Value::Number(7.0)];
+        let globals = run(
+            "fun big(n) { return n > 4; }\nvar hit = find(xs, big);\n",
+            numbers.clone(),
+        );
+        assert_eq!(global(&globals, "hit"), Value::Number(5.0));
+
+        let globals = run(
+            "fun huge(n) { return n > 100; }\nvar miss = find(xs, huge);\n",
+            numbers,
+        );
+        assert_eq!(global(&globals, "miss"), Value::Nil);
+    }
+
+    #[test]
+    fn test_first_last_rest() {
+        let numbers = vec![Value::Number(1.0), Value::Number(5.0), Value::Number(7.0)];
+        let globals = run(
+            "var head = first(xs);\nvar tail = last(xs);\nvar others = rest(xs);\n",
+            numbers,
+        );
+        assert_eq!(global(&globals, "head"), Value::Number(1.0));
+        assert_eq!(global(&globals, "tail"), Value::Number(7.0));
+        assert_eq!(
+            global(&globals, "others"),
+            Value::Array(Rc::new(RefCell::new(vec![
+                Value::Number(5.0),
+                Value::Number(7.0)
+            ])))
+        );
+        // `rest` copies, the original list is left alone
+        match global(&globals, "xs") {
+            Value::Array(items) => assert_eq!(items.borrow().len(), 3),
+            xs => panic!("xs changed to {}", xs),
+        }
+
+        let globals = run(
+            "var head = first(xs);\nvar tail = last(xs);\nvar others = rest(xs);\n",
+            vec![],
+        );
+        assert_eq!(global(&globals, "head"), Value::Nil);
+        assert_eq!(global(&globals, "tail"), Value::Nil);
+        assert_eq!(
+            global(&globals, "others"),
+            Value::Array(Rc::new(RefCell::new(vec![])))
+        );
+    }
+
+    #[test]
+    fn test_zip() {
+        let globals = run(
+            "var same = zip(xs, rest(xs));\nvar pairs = zip(xs, xs);\n",
+            vec![Value::Number(1.0), Value::Number(2.0), Value::Number(3.0)],
+        );
+        let pair = |a: f64, b: f64| {
+            Value::Array(Rc::new(RefCell::new(vec![
+                Value::Number(a),
+                Value::Number(b),
+            ])))
+        };
+        assert_eq!(
+            global(&globals, "pairs"),
+            Value::Array(Rc::new(RefCell::new(vec![
+                pair(1.0, 1.0),
+                pair(2.0, 2.0),
+                pair(3.0, 3.0)
+            ])))
+        );
+        // truncated to the shorter list
+        assert_eq!(
+            global(&globals, "same"),
+            Value::Array(Rc::new(RefCell::new(vec![pair(1.0, 2.0), pair(2.0, 3.0)])))
+        );
+
+        assert!(run_err("zip(1, 2);\n").contains("zip expects a list"));
+    }
+
+    #[test]
+    fn test_max_by_and_min_by() {
+        let numbers = vec![Value::Number(3.0), Value::Number(-8.0), Value::Number(5.0)];
+        let globals = run(
+            "fun square(n) { return n * n; }\nvar hi = max_by(xs, square);\nvar lo = min_by(xs, square);\n",
+            numbers,
+        );
+        assert_eq!(global(&globals, "hi"), Value::Number(-8.0));
+        assert_eq!(global(&globals, "lo"), Value::Number(3.0));
+
+        let globals = run(
+            "fun square(n) { return n * n; }\nvar none = max_by(xs, square);\n",
+            vec![],
+        );
+        assert_eq!(global(&globals, "none"), Value::Nil);
+    }
+}
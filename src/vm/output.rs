@@ -0,0 +1,38 @@
+//! Where `print` writes to. Like the config it's kept per thread, the VM
+//! running a script swaps its sink in for the run
+
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    rc::Rc,
+};
+
+pub type Output = Rc<RefCell<dyn Write>>;
+
+pub fn stdout() -> Output {
+    Rc::new(RefCell::new(io::stdout()))
+}
+
+thread_local! {
+    static OUTPUT: RefCell<Output> = RefCell::new(stdout());
+}
+
+/// Makes `output` the sink, handing back the one it replaced
+pub fn set_output(output: Output) -> Output {
+    OUTPUT.with(|current| current.replace(output))
+}
+
+pub fn print_line(text: &str) -> io::Result<()> {
+    let output = OUTPUT.with(|current| current.borrow().clone());
+    let mut output = output.borrow_mut();
+    writeln!(output, "{}", text)?;
+    output.flush()
+}
+
+/// Writes `text` as is, without a newline after it
+pub fn print_text(text: &str) -> io::Result<()> {
+    let output = OUTPUT.with(|current| current.borrow().clone());
+    let mut output = output.borrow_mut();
+    write!(output, "{}", text)?;
+    output.flush()
+}
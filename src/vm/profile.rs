@@ -0,0 +1,95 @@
+//! Counts of the instructions a run executes, kept while `--profile` is
+//! on. Like the config they're kept per thread so `Func::call` can count
+//! without handing a counter to every call
+
+use std::{cell::RefCell, collections::HashMap};
+
+use crate::instructions::instructions::InstructionType;
+
+// the widest bar in the histogram
+const BAR_WIDTH: usize = 40;
+
+thread_local! {
+    static COUNTS: RefCell<HashMap<InstructionType, usize>> = RefCell::new(HashMap::new());
+}
+
+pub fn record(code: InstructionType) {
+    COUNTS.with(|counts| *counts.borrow_mut().entry(code).or_insert(0) += 1);
+}
+
+/// The counts so far, most executed first, clearing them for the next run
+pub fn take_profile() -> Vec<(InstructionType, usize)> {
+    let mut counts: Vec<(InstructionType, usize)> =
+        COUNTS.with(|counts| counts.borrow_mut().drain().collect());
+    counts.sort_by(|a, b| {
+        b.1.cmp(&a.1)
+            .then_with(|| format!("{:?}", a.0).cmp(&format!("{:?}", b.0)))
+    });
+    counts
+}
+
+/// A histogram of `counts` with bars scaled to the most executed instruction
+pub fn report(counts: &[(InstructionType, usize)]) -> String {
+    let total: usize = counts.iter().map(|(_, count)| count).sum();
+    let most = counts.first().map_or(1, |(_, count)| (*count).max(1));
+    let mut report = format!("== profile: {} instructions ==\n", total);
+    for (code, count) in counts {
+        let bar = (count * BAR_WIDTH).div_ceil(most);
+        report += &format!(
+            "{:<20} {:>10} {}\n",
+            format!("{:?}", code),
+            count,
+            "#".repeat(bar)
+        );
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::{
+        config::{set_config, Config},
+        vm::VM,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_profile_counts() {
+        set_config(Config {
+            profile: true,
+            ..Config::default()
+        });
+        take_profile();
+        VM::interprate_with(
+            b"var i = 0;\nwhile (i < 3) {\n    i = i + 1;\n}\n".to_vec(),
+            VM::globals(),
+        )
+        .unwrap();
+        let counts = take_profile();
+        set_config(Config::default());
+
+        let count = |code: InstructionType| {
+            counts
+                .iter()
+                .find(|(counted, _)| *counted == code)
+                .map_or(0, |(_, count)| *count)
+        };
+        // `i < 3` runs once per iteration and once more to leave the loop
+        assert_eq!(count(InstructionType::OP_BINARY), 4 + 3);
+        assert_eq!(count(InstructionType::OP_OVERRIDE), 3);
+        assert_eq!(count(InstructionType::OP_DEFINE), 1);
+        assert!(counts.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+
+        let text = report(&counts);
+        assert!(text.starts_with(&format!(
+            "== profile: {} instructions ==",
+            counts.iter().map(|(_, count)| count).sum::<usize>()
+        )));
+        assert!(text.contains("OP_OVERRIDE"));
+
+        // nothing is counted with the flag off
+        VM::interprate_with(b"var j = 1 + 2;\n".to_vec(), VM::globals()).unwrap();
+        assert!(take_profile().is_empty());
+    }
+}
@@ -0,0 +1,73 @@
+//! The stack values live on while a script runs, along with the upvalues
+//! still pointing into it. Each VM has its own, so closures only ever see
+//! the slots of the stack they were created on
+
+use std::{
+    cell::RefCell,
+    ops::{Deref, DerefMut},
+    rc::Rc,
+};
+
+use crate::{compiler::compiler::Capture, values::values::Value};
+
+#[derive(Default)]
+pub struct Stack {
+    values: Vec<Value>,
+    // upvalues still pointing at a live slot, shared so closures capturing
+    // the same variable see each other's writes
+    open_upvalues: Vec<(usize, Rc<RefCell<Capture>>)>,
+}
+
+impl Stack {
+    pub fn new() -> Self {
+        Stack::default()
+    }
+
+    /// The open upvalue for the slot at `idx`, created if no closure has
+    /// captured the slot yet
+    pub fn capture(&mut self, idx: usize) -> Rc<RefCell<Capture>> {
+        if let Some((_, cell)) = self.open_upvalues.iter().find(|(open, _)| *open == idx) {
+            return cell.clone();
+        }
+        let cell = Rc::new(RefCell::new(Capture::Open(idx)));
+        self.open_upvalues.push((idx, cell.clone()));
+        cell
+    }
+
+    /// Moves every open upvalue at or above `from` off the stack, this has
+    /// to run before those slots are popped
+    pub fn close_upvalues(&mut self, from: usize) {
+        let values = &self.values;
+        self.open_upvalues.retain(|(idx, cell)| {
+            if *idx < from {
+                return true;
+            }
+            let val = values.get(*idx).cloned().unwrap_or(Value::Nil);
+            *cell.borrow_mut() = Capture::Closed(val);
+            false
+        });
+    }
+}
+
+impl From<Vec<Value>> for Stack {
+    fn from(values: Vec<Value>) -> Self {
+        Stack {
+            values,
+            open_upvalues: Vec::new(),
+        }
+    }
+}
+
+impl Deref for Stack {
+    type Target = Vec<Value>;
+
+    fn deref(&self) -> &Vec<Value> {
+        &self.values
+    }
+}
+
+impl DerefMut for Stack {
+    fn deref_mut(&mut self) -> &mut Vec<Value> {
+        &mut self.values
+    }
+}
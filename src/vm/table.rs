@@ -1,42 +1,76 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    mem,
+};
 
-use crate::values::values::Value;
+use crate::values::{intern::Name, values::Value};
 
 pub struct Table {
-    vars: HashMap<String, Value>,
+    vars: HashMap<Name, Value>,
+    // globals the compiler has seen declared whose definitions haven't run yet
+    declared: HashSet<Name>,
 }
 
 impl Table {
     pub fn new() -> Self {
         Table {
             vars: HashMap::new(),
+            declared: HashSet::new(),
         }
     }
 
-    pub fn add(&mut self, identifier: String, value: Value) {
+    pub fn add(&mut self, identifier: Name, value: Value) {
+        self.declared.remove(&identifier);
         self.vars.insert(identifier, value);
     }
 
-    pub fn resolve(&self, identifier: &String) -> Option<Value> {
-        if self.vars.contains_key(identifier) {
-            return Some(self.vars.get(identifier).unwrap().clone());
+    /// Marks a global as declared so later code compiles against it. It
+    /// has no value, and reading it stays an error, until it's defined
+    pub fn declare(&mut self, identifier: Name) {
+        if !self.vars.contains_key(&identifier) {
+            self.declared.insert(identifier);
         }
-        None
     }
 
-    pub fn override_(&mut self, identifier: String, value: Value) -> Option<Value> {
-        if self.vars.contains_key(&identifier) {
-            return Some(self.vars.insert(identifier, value).unwrap().clone());
-        }
-        None
+    /// A copy of the global's value. Strings, functions and the like sit
+    /// behind an `Rc` so the copy is a pointer clone, not a deep one
+    pub fn resolve(&self, identifier: &Name) -> Option<Value> {
+        self.get(identifier).cloned()
+    }
+
+    /// The global's value without copying it out of the table
+    pub fn get(&self, identifier: &Name) -> Option<&Value> {
+        self.vars.get(identifier)
+    }
+
+    /// Replaces an existing global's value, handing back the old one. Globals
+    /// that haven't been defined are left alone
+    pub fn override_(&mut self, identifier: &Name, value: Value) -> Option<Value> {
+        self.vars
+            .get_mut(identifier)
+            .map(|slot| mem::replace(slot, value))
     }
 
     pub fn keys(&self) -> usize {
-        self.vars.keys().len()
+        self.vars.len() + self.declared.len()
+    }
+
+    pub fn exists(&self, identifier: &Name) -> bool {
+        self.vars.contains_key(identifier) || self.declared.contains(identifier)
+    }
+
+    /// Copy of the current globals that can later be handed to `restore`
+    pub fn snapshot(&self) -> Table {
+        Table {
+            vars: self.vars.clone(),
+            declared: self.declared.clone(),
+        }
     }
 
-    pub fn exists(&self, identifier: &String) -> bool {
-        self.vars.contains_key(identifier)
+    pub fn restore(&mut self, snapshot: Table) {
+        self.vars = snapshot.vars;
+        self.declared = snapshot.declared;
     }
 }
 
@@ -51,3 +85,80 @@ impl Display for Table {
         write!(f, "{}", str)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::Table;
+    use crate::{values::values::Value, vm::vm::VM};
+
+    #[test]
+    fn test_reads_see_latest_override() {
+        let mut table = Table::new();
+        let name = "greeting".into();
+        assert_eq!(table.override_(&name, Value::Nil), None);
+        assert!(!table.exists(&name));
+
+        table.add("greeting".into(), Value::String("hello".into()));
+        let first = table.resolve(&name);
+        assert_eq!(
+            table.override_(&name, Value::String("bye".into())),
+            Some(Value::String("hello".into()))
+        );
+        assert_eq!(table.get(&name), Some(&Value::String("bye".into())));
+        assert_eq!(table.resolve(&name), Some(Value::String("bye".into())));
+        // a value read earlier keeps what it saw
+        assert_eq!(first, Some(Value::String("hello".into())));
+
+        // and the same through compiled code
+        let globals = VM::globals();
+        VM::interprate_with(
+            b"var s = \"a\";\nvar seen = s;\ns = s + \"b\";\nvar after = s;\n".to_vec(),
+            globals.clone(),
+        )
+        .unwrap();
+        let globals = globals.borrow();
+        assert_eq!(
+            globals.get(&"seen".into()),
+            Some(&Value::String("a".into()))
+        );
+        assert_eq!(
+            globals.get(&"after".into()),
+            Some(&Value::String("ab".into()))
+        );
+    }
+
+    #[test]
+    fn test_functions_round_trip_through_globals() {
+        let globals = VM::globals();
+        let func = Rc::new(VM::compile(b"1 + 2;\n".to_vec(), globals.clone()).unwrap());
+
+        let mut table = Table::new();
+        table.add("script".into(), Value::Func(func.clone()));
+        match table.resolve(&"script".into()) {
+            Some(Value::Func(resolved)) => assert!(Rc::ptr_eq(&resolved, &func)),
+            val => panic!("expected the function back, found {:?}", val),
+        }
+
+        // functions declared by a script resolve as callable globals too
+        VM::interprate_with(
+            b"fun add(a, b) { return a + b; }\n".to_vec(),
+            globals.clone(),
+        )
+        .unwrap();
+        let add = match (*globals).borrow().resolve(&"add".into()) {
+            Some(Value::Func(add)) => add,
+            val => panic!("expected `add` to be a function, found {:?}", val),
+        };
+        assert_eq!(add.arity(), 2);
+        (*globals)
+            .borrow_mut()
+            .add("plus".into(), Value::Func(add.clone()));
+        VM::interprate_with(b"var sum = plus(2, 3);\n".to_vec(), globals.clone()).unwrap();
+        assert_eq!(
+            (*globals).borrow().resolve(&"sum".into()),
+            Some(Value::Number(5.0))
+        );
+    }
+}
@@ -1,49 +1,74 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, mem, rc::Rc};
 
 use crate::{
     compiler::compiler::{Compiler, FunctionType},
     errors::err::ErrTrait,
-    values::{func::Func, values::Value},
+    values::{
+        func::{Frame, Func, NativeContext},
+        values::Value,
+    },
 };
 
-use super::{natives::load_natives, table::Table};
+use super::{
+    err::{RuntimeErr, TracedErr},
+    natives::{define_native, load_natives},
+    output::{self, set_output, Output},
+    stack::Stack,
+    table::Table,
+};
 
 pub struct VM<'a> {
     // implicit main
     func: &'a Func,
-    frames: Rc<RefCell<Vec<String>>>,
-    stack: Rc<RefCell<Vec<Value>>>,
+    frames: Rc<RefCell<Vec<Frame>>>,
+    stack: Rc<RefCell<Stack>>,
     globals: Rc<RefCell<Table>>,
+    // where `print` writes to
+    output: Output,
 }
 
 impl<'a> VM<'a> {
     pub fn new(func: &'a Func, globals: Rc<RefCell<Table>>) -> Self {
+        VM::with_output(func, globals, output::stdout())
+    }
+
+    pub fn with_output(func: &'a Func, globals: Rc<RefCell<Table>>, output: Output) -> Self {
         VM {
             func,
             frames: Rc::new(RefCell::new(Vec::new())),
-            stack: Rc::new(RefCell::new(Vec::new())),
+            stack: Rc::new(RefCell::new(Stack::new())),
             globals,
+            output,
         }
     }
 
-    pub fn run(&mut self) -> Result<(), Box<dyn ErrTrait>> {
-        match self.func.call(
+    /// Runs the script and hands back the value of its final expression
+    /// statement, nil if it ends with anything else. Errors are left to the
+    /// caller to report
+    pub fn eval(&mut self) -> Result<Value, Box<dyn ErrTrait>> {
+        let previous = set_output(self.output.clone());
+        let res = self.func.call(
             self.stack.clone(),
             self.globals.clone(),
             self.frames.clone(),
             0,
-        ) {
-            Ok(_) => {}
+        );
+        set_output(previous);
+        // an error can leave upvalues pointing into this stack
+        (*self.stack).borrow_mut().close_upvalues(0);
+        res
+    }
+
+    /// Runs the script, a runtime error comes back with the stack trace
+    /// of where it was raised for the caller to report
+    pub fn run(&mut self) -> Result<(), Box<dyn ErrTrait>> {
+        match self.eval() {
+            Ok(_) => Ok(()),
             Err(err) => {
-                err.raise();
-                println!("\nStack Trace: ");
-                println!("-----------------");
-                for func in (*self.frames).borrow().iter().rev() {
-                    println!("<Fun {}>", func);
-                }
+                let frames = mem::take(&mut *(*self.frames).borrow_mut());
+                Err(Box::new(TracedErr::new(err, frames)))
             }
         }
-        Ok(())
     }
 
     pub fn compile<'b>(
@@ -55,11 +80,270 @@ impl<'a> VM<'a> {
         Ok(func)
     }
 
-    pub fn interprate(src: Vec<u8>) -> Result<(), Box<dyn ErrTrait>> {
+    /// Compiles a submission typed into the REPL, where a trailing
+    /// expression without its `;` prints its value
+    pub fn compile_submission(
+        src: Vec<u8>,
+        globals: Rc<RefCell<Table>>,
+    ) -> Result<Func, Box<dyn ErrTrait>> {
+        let upvalues = Rc::new(RefCell::new(Vec::new()));
+        Compiler::compile(src, FunctionType::Repl, globals, None, upvalues, None)
+    }
+
+    /// Runs `src` against an existing set of globals (e.g. a shared prelude)
+    /// and rolls back whatever the script defined or reassigned once it's done
+    pub fn with_globals(
+        src: Vec<u8>,
+        globals: Rc<RefCell<Table>>,
+    ) -> Result<(), Box<dyn ErrTrait>> {
+        let snapshot = (*globals).borrow().snapshot();
+        let res = VM::interprate_with(src, globals.clone());
+        (*globals).borrow_mut().restore(snapshot);
+        res
+    }
+
+    /// Fresh globals with the natives loaded
+    pub fn globals() -> Rc<RefCell<Table>> {
         let globals = Rc::new(RefCell::new(Table::new()));
         load_natives(globals.clone());
+        globals
+    }
+
+    /// Adds a native backed by a host closure to `globals`, it's called with
+    /// `arity` arguments
+    pub fn register_native(
+        globals: &Rc<RefCell<Table>>,
+        name: &str,
+        arity: usize,
+        native: impl Fn(&[Value], &NativeContext) -> Result<Value, Box<dyn ErrTrait>> + 'static,
+    ) {
+        define_native(globals, name, arity, native);
+    }
+
+    /// Compiles and runs `src` with a fresh set of globals
+    pub fn interprate(src: Vec<u8>) -> Result<(), Box<dyn ErrTrait>> {
+        VM::interprate_with(src, VM::globals())
+    }
+
+    /// Compiles and runs `src` against `globals`, keeping whatever it defines
+    pub fn interprate_with(
+        src: Vec<u8>,
+        globals: Rc<RefCell<Table>>,
+    ) -> Result<(), Box<dyn ErrTrait>> {
         let __main__ = VM::compile(src, globals.clone())?;
         VM::new(&__main__, globals).run()?;
         Ok(())
     }
+
+    /// Like `interprate_with` but `print` writes to `output`
+    pub fn interprate_to(
+        src: Vec<u8>,
+        globals: Rc<RefCell<Table>>,
+        output: Output,
+    ) -> Result<(), Box<dyn ErrTrait>> {
+        let __main__ = VM::compile(src, globals.clone())?;
+        VM::with_output(&__main__, globals, output).run()?;
+        Ok(())
+    }
+
+    /// Calls the global function `name` without arguments, for scripts that
+    /// define their globals and leave the work to something like `main`
+    pub fn call_entry(name: &str, globals: Rc<RefCell<Table>>) -> Result<(), Box<dyn ErrTrait>> {
+        let resolved = (*globals).borrow().resolve(&name.into());
+        let func = match resolved {
+            Some(Value::Func(func)) if func.arity() == 0 => func,
+            Some(Value::Func(func)) => {
+                return Err(Box::new(RuntimeErr::new(format!(
+                    "entry function `{}` expects {} argument(s), it's called with none",
+                    name,
+                    func.arity()
+                ))))
+            }
+            Some(val) => {
+                return Err(Box::new(RuntimeErr::new(format!(
+                    "entry `{}` is {}, not a function",
+                    name, val
+                ))))
+            }
+            None => {
+                return Err(Box::new(RuntimeErr::new(format!(
+                    "entry function `{}` is not defined",
+                    name
+                ))))
+            }
+        };
+        VM::new(&func, globals).run()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::vm::config::{set_config, Config};
+
+    #[test]
+    fn test_interprate() {
+        assert!(VM::interprate(b"var x = 1 + 2;\n".to_vec()).is_ok());
+        assert!(VM::interprate(b"var x = 1 + ;\n".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_with_globals_restores_globals() {
+        let globals = Rc::new(RefCell::new(Table::new()));
+        load_natives(globals.clone());
+        let prelude = VM::compile(b"var shared = 1;\n".to_vec(), globals.clone()).unwrap();
+        VM::new(&prelude, globals.clone()).run().unwrap();
+
+        VM::with_globals(b"var leaked = 2;\nshared = 5;\n".to_vec(), globals.clone()).unwrap();
+        assert!(!(*globals).borrow().exists(&"leaked".into()));
+        assert_eq!(
+            (*globals).borrow().resolve(&"shared".into()),
+            Some(Value::Number(1.0))
+        );
+
+        // the second script still sees the prelude but not the first script
+        assert!(VM::with_globals(b"var seen = shared;\n".to_vec(), globals.clone()).is_ok());
+        assert!(VM::with_globals(b"var seen = leaked;\n".to_vec(), globals.clone()).is_err());
+    }
+
+    #[test]
+    fn test_print_to_writer() {
+        let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+        VM::interprate_to(
+            b"print \"hi\";\nprint 1 + 2;\nprint_or(nil, \"default\");\n".to_vec(),
+            VM::globals(),
+            output.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output.borrow().clone()).unwrap(),
+            "\"hi\"\n3\ndefault\n"
+        );
+    }
+
+    #[test]
+    fn test_empty_programs() {
+        for src in ["", "   \n\t\n", "// nothing to see\n/* here */"] {
+            let output = Rc::new(RefCell::new(Vec::<u8>::new()));
+            VM::interprate_to(src.as_bytes().to_vec(), VM::globals(), output.clone()).unwrap();
+            assert!(output.borrow().is_empty(), "{:?}", src);
+
+            let func = VM::compile(src.as_bytes().to_vec(), VM::globals()).unwrap();
+            assert!(func.chunk.code.is_empty(), "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn test_register_native() {
+        let globals = VM::globals();
+        let count = Rc::new(Cell::new(0));
+        let counter = count.clone();
+        VM::register_native(&globals, "tick", 1, move |args, _| {
+            let step = match args[0] {
+                Value::Number(step) => step as i32,
+                _ => 1,
+            };
+            counter.set(counter.get() + step);
+            Ok(Value::Number(counter.get() as f64))
+        });
+
+        VM::interprate_with(
+            b"tick(1);\ntick(2);\nvar seen = tick(3);\n".to_vec(),
+            globals.clone(),
+        )
+        .unwrap();
+        assert_eq!(count.get(), 6);
+        assert_eq!(
+            (*globals).borrow().resolve(&"seen".into()),
+            Some(Value::Number(6.0))
+        );
+    }
+
+    #[test]
+    fn test_recursive_fib() {
+        let globals = VM::globals();
+        let src = b"fun fib(n) {
+    if (n < 2) { return n; }
+    return fib(n - 1) + fib(n - 2);
+}
+var res = fib(20);
+"
+        .to_vec();
+        VM::interprate_with(src, globals.clone()).unwrap();
+        assert_eq!(
+            (*globals).borrow().resolve(&"res".into()),
+            Some(Value::Number(6765.0))
+        );
+    }
+
+    #[test]
+    fn test_runtime_error_lines() {
+        let globals = VM::globals();
+        let src = b"var name = \"lox\";
+
+fun shout(s) {
+    var loud = s;
+    return loud - 1;
+}
+
+fun greet() {
+    return shout(name);
+}
+greet();
+"
+        .to_vec();
+        let func = VM::compile(src, globals.clone()).unwrap();
+        let mut vm = VM::new(&func, globals);
+        let err = vm.eval().unwrap_err();
+        assert!(format!("{}", err).contains("@ line 5: "), "{}", err);
+
+        // each frame keeps the line it was at when the error went through it
+        let trace: Vec<(String, Option<usize>)> = (*vm.frames)
+            .borrow()
+            .iter()
+            .map(|frame| (frame.name.clone(), frame.line))
+            .collect();
+        assert_eq!(
+            trace,
+            vec![
+                ("__main__".to_string(), Some(11)),
+                ("greet".to_string(), Some(9)),
+                ("shout".to_string(), Some(5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_max_call_depth() {
+        let src = b"fun down(n) {
+    if (n == 0) { return 0; }
+    return down(n - 1) + 1;
+}
+var shallow = down(5);
+var deep = down(50);
+"
+        .to_vec();
+        set_config(Config {
+            max_call_depth: Some(20),
+            ..Config::default()
+        });
+        let globals = VM::globals();
+        let err = VM::interprate_with(src, globals.clone()).unwrap_err();
+        set_config(Config::default());
+
+        assert!(
+            format!("{}", err).contains("calling `down` would nest more than 20 calls deep"),
+            "{}",
+            err
+        );
+        assert_eq!(
+            (*globals).borrow().resolve(&"shallow".into()),
+            Some(Value::Number(5.0))
+        );
+        // declared when compiled, but never assigned
+        assert!((*globals).borrow().exists(&"deep".into()));
+        assert_eq!((*globals).borrow().resolve(&"deep".into()), None);
+    }
 }
@@ -0,0 +1,106 @@
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+/// Runs the `lox` binary with `args`, piping `stdin` into it
+fn lox(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lox"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_eval_flag() {
+    let output = lox(&["--eval", "var x = 1 + 1;\nprint x * 3;"], "");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "6\n");
+}
+
+#[test]
+fn test_source_from_stdin() {
+    let output = lox(
+        &["-"],
+        "fun greet(name) {\n    print \"hi \" + name;\n}\ngreet(\"lox\");\n",
+    );
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "\"hi lox\"\n");
+
+    // nothing piped in is an empty program
+    let output = lox(&["-"], "");
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_profile_flag() {
+    let output = lox(&["--profile", "--eval", "print 1 + 2;"], "");
+    assert!(output.status.success());
+    // the script's output is left alone, the counts go to stderr
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3\n");
+    let profile = String::from_utf8(output.stderr).unwrap();
+    assert!(profile.starts_with("== profile: "), "{}", profile);
+    assert!(profile.contains("OP_PRINT"), "{}", profile);
+
+    let output = lox(&["--eval", "print 1 + 2;"], "");
+    assert!(!String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("== profile"));
+}
+
+#[test]
+fn test_error_format_json() {
+    let output = lox(&["--error-format", "json", "--eval", "var a = ;"], "");
+    assert!(!output.status.success());
+    // nothing but the errors, and those on stderr
+    assert!(output.stdout.is_empty());
+    let errors = String::from_utf8(output.stderr).unwrap();
+    assert!(!errors.is_empty());
+    for line in errors.lines() {
+        assert!(line.starts_with('{') && line.ends_with('}'), "{}", errors);
+    }
+    assert!(errors.contains("Expected expression"), "{}", errors);
+}
+
+#[test]
+fn test_error_format_plain_runtime() {
+    let output = lox(
+        &[
+            "--error-format",
+            "plain",
+            "--eval",
+            "var a = 1;\nvar b = \"x\" - a;",
+        ],
+        "",
+    );
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    // the line the failing instruction came from, without the display's prefix
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "<eval>:2: \"-\" can only be performed on 2 Numbers\n"
+    );
+
+    let output = lox(
+        &[
+            "--error-format",
+            "json",
+            "--eval",
+            "var a = 1;\nvar b = \"x\" - a;",
+        ],
+        "",
+    );
+    let error = String::from_utf8(output.stderr).unwrap();
+    assert!(error.contains("\"line\": 2, \"column\": null"), "{}", error);
+}
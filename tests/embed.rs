@@ -0,0 +1,53 @@
+use lox::{Interpreter, Value};
+
+#[test]
+fn test_eval_returns_the_last_expression() {
+    let mut lox = Interpreter::new();
+    assert_eq!(lox.eval("1 + 2;\n").unwrap(), Value::Number(3.0));
+    assert_eq!(lox.eval("var x = 1;\n").unwrap(), Value::Nil);
+}
+
+#[test]
+fn test_eval_keeps_globals() {
+    let mut lox = Interpreter::new();
+    lox.eval("fun square(n) { return n * n; }\nvar base = 4;\n")
+        .unwrap();
+    assert_eq!(lox.eval("square(base);\n").unwrap(), Value::Number(16.0));
+}
+
+#[test]
+fn test_eval_errors() {
+    let mut lox = Interpreter::new();
+    assert!(lox.eval("var = ;\n").is_err());
+    assert!(lox.eval("undefined_fn();\n").is_err());
+}
+
+#[test]
+fn test_register_native() {
+    let mut lox = Interpreter::new();
+    let calls = std::rc::Rc::new(std::cell::Cell::new(0));
+    let seen = calls.clone();
+    lox.register_native("ping", 0, move |_, _| {
+        seen.set(seen.get() + 1);
+        Ok(Value::String("pong".into()))
+    });
+    assert_eq!(
+        lox.eval("ping();\nping();\n").unwrap(),
+        Value::String("pong".into())
+    );
+    assert_eq!(calls.get(), 2);
+}
+
+#[test]
+fn test_deep_recursion_errors_instead_of_overflowing() {
+    // test threads get the 2MiB stack the default depth is sized for
+    let mut lox = Interpreter::new();
+    lox.eval("fun down(n) {\n    if (n == 0) return 0;\n    return down(n - 1) + 1;\n}\n")
+        .unwrap();
+    assert_eq!(lox.eval("down(20);\n").unwrap(), Value::Number(20.0));
+    let err = lox.eval("down(100000);\n").unwrap_err();
+    assert!(format!("{}", err).contains("would nest more than 64 calls deep"));
+
+    lox.set_max_call_depth(100);
+    assert_eq!(lox.eval("down(80);\n").unwrap(), Value::Number(80.0));
+}
@@ -0,0 +1,33 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// Runs the `lox` binary on `src` piped through stdin, handing back its
+/// exit code
+fn exit_code(src: &str) -> Option<i32> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lox"))
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(src.as_bytes())
+        .unwrap();
+    child.wait().unwrap().code()
+}
+
+#[test]
+fn test_exit_codes() {
+    assert_eq!(exit_code("var x = 1;\nprint x;\n"), Some(0));
+    // compile errors
+    assert_eq!(exit_code("var = ;\n"), Some(65));
+    assert_eq!(exit_code("print (1;\nvar 2;\n"), Some(65));
+    // runtime errors, including ones raised inside a function
+    assert_eq!(exit_code("print 1 - \"a\";\n"), Some(70));
+    assert_eq!(exit_code("fun f() { return nil.x; }\nf();\n"), Some(70));
+}